@@ -0,0 +1,7 @@
+#![no_main]
+use lc3_emulator::fuzz::check_assemble;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    check_assemble(source);
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use lc3_emulator::fuzz::check_decode;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: [u8; 2]| {
+    check_decode(u16::from_be_bytes(data));
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use lc3_emulator::fuzz::check_execute;
+use libfuzzer_sys::fuzz_target;
+
+// the word to execute, the address it's at, then each register
+fuzz_target!(|data: [u8; 20]| {
+    let words: Vec<u16> = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let mut registers = [0; 8];
+    registers.copy_from_slice(&words[2..]);
+    check_execute(words[0], words[1], registers);
+});
@@ -0,0 +1,257 @@
+use crate::assembler::lexer::LexError;
+use crate::assembler::parser::ParseError;
+use crate::assembler::Span;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// a secondary span that helps explain a diagnostic, like where a label that
+/// was used is defined
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// something the assembler has to say about a program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// what the diagnostic is about, which gets underlined
+    pub span: Span,
+    pub labels: Vec<Label>,
+    /// a suggestion for fixing the problem
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            span,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn warning(message: String, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message, span)
+        }
+    }
+
+    /// render the diagnostic with a frame of the code it points at, in the
+    /// style of rustc. `color` adds ANSI escapes, for printing to a terminal
+    pub fn pretty(&self, filename: &str, source: &str, color: bool) -> String {
+        let style = Style { color };
+        let lines: Vec<&str> = source.lines().collect();
+        let severity_code = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        // every line that has something pointing at it, in order
+        let mut underlines = vec![(self.span, '^', severity_code, "")];
+        underlines.extend(
+            self.labels
+                .iter()
+                .map(|label| (label.span, '-', BLUE, label.message.as_ref())),
+        );
+        let mut line_numbers: Vec<usize> = underlines.iter().map(|(span, ..)| span.line).collect();
+        line_numbers.sort_unstable();
+        line_numbers.dedup();
+
+        let gutter_width = (line_numbers.last().unwrap() + 1).to_string().len();
+        let gutter =
+            |text: &str| style.paint(BLUE, &format!("{:>width$} |", text, width = gutter_width));
+
+        let mut rendered = format!(
+            "{}{}\n{}{} {}:{}:{}\n{}\n",
+            style.paint(severity_code, &self.severity.to_string()),
+            style.paint(BOLD, &format!(": {}", self.message)),
+            " ".repeat(gutter_width),
+            style.paint(BLUE, "-->"),
+            filename,
+            self.span.line + 1,
+            self.span.column + 1,
+            gutter(""),
+        );
+        let mut previous_line = None;
+        for line_number in line_numbers {
+            if previous_line.is_some_and(|previous| line_number > previous + 1) {
+                rendered.push_str(&style.paint(BLUE, "...\n"));
+            }
+            previous_line = Some(line_number);
+
+            let line = lines.get(line_number).copied().unwrap_or("");
+            rendered.push_str(&format!(
+                "{} {}\n",
+                gutter(&(line_number + 1).to_string()),
+                line.replace('\t', " ")
+            ));
+            for (span, marker, code, message) in &underlines {
+                if span.line != line_number {
+                    continue;
+                }
+                let marker = marker.to_string().repeat(span.len.max(1));
+                let underline = format!("{}{} {}", " ".repeat(span.column), marker, message);
+                rendered.push_str(&format!(
+                    "{} {}\n",
+                    gutter(""),
+                    style.paint(code, underline.trim_end())
+                ));
+            }
+        }
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!(
+                "{} {} {}\n",
+                " ".repeat(gutter_width),
+                style.paint(BLUE, "="),
+                style.paint(BOLD, &format!("help: {}", help))
+            ));
+        }
+        rendered
+    }
+}
+
+const BOLD: &str = "1";
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+
+/// wraps text in ANSI escape codes, unless color is turned off
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(error: LexError) -> Self {
+        let span = Span {
+            line: error.line,
+            column: error.character,
+            len: 1,
+        };
+        Diagnostic::error(error.message, span)
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Self {
+        Diagnostic {
+            labels: error.labels,
+            help: error.help,
+            ..Diagnostic::error(error.message, error.span)
+        }
+    }
+}
+
+/// render every diagnostic, one after another
+pub fn render(diagnostics: &[Diagnostic], filename: &str, source: &str, color: bool) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.pretty(filename, source, color))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: usize, column: usize, len: usize) -> Span {
+        Span { line, column, len }
+    }
+
+    #[test]
+    fn test_pretty() {
+        let diagnostic = Diagnostic::error(String::from("expected a register"), span(1, 4, 2));
+        assert_eq!(
+            diagnostic.pretty("test.asm", ".ORIG x3000\nADD #1", false),
+            "error: expected a register
+ --> test.asm:2:5
+  |
+2 | ADD #1
+  |     ^^
+"
+        );
+    }
+
+    #[test]
+    fn test_pretty_with_labels_and_help() {
+        let source = ".ORIG x3000\nLD R0, far\nHALT\nHALT\nfar .FILL 0";
+        let diagnostic = Diagnostic {
+            labels: vec![Label {
+                span: span(4, 0, 3),
+                message: String::from("far is defined here"),
+            }],
+            help: Some(String::from("move far closer")),
+            ..Diagnostic::error(String::from("too far"), span(1, 7, 3))
+        };
+        assert_eq!(
+            diagnostic.pretty("test.asm", source, false),
+            "error: too far
+ --> test.asm:2:8
+  |
+2 | LD R0, far
+  |        ^^^
+...
+5 | far .FILL 0
+  | --- far is defined here
+  = help: move far closer
+"
+        );
+    }
+
+    #[test]
+    fn test_pretty_in_color() {
+        let diagnostic = Diagnostic::warning(String::from("w"), span(0, 0, 1));
+        assert_eq!(
+            diagnostic.pretty("test.asm", "x", true),
+            "\x1b[1;33mwarning\x1b[0m\x1b[1m: w\x1b[0m
+ \x1b[1;34m-->\x1b[0m test.asm:1:1
+\x1b[1;34m  |\x1b[0m
+\x1b[1;34m1 |\x1b[0m x
+\x1b[1;34m  |\x1b[0m \x1b[1;33m^\x1b[0m
+"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "x\ny";
+        let diagnostics = vec![
+            Diagnostic::error(String::from("a"), Span::default()),
+            Diagnostic::warning(String::from("b"), span(1, 0, 0)),
+        ];
+        assert_eq!(
+            render(&diagnostics, "test.asm", source, false),
+            "error: a\n --> test.asm:1:1\n  |\n1 | x\n  | ^\n\nwarning: b\n --> test.asm:2:1\n  |\n2 | y\n  | ^\n"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use super::reader::Reader;
-use std::u16;
+use crate::assembler::Span;
+use std::convert::TryFrom;
 
 #[derive(Debug, PartialEq)]
 pub struct LexError {
@@ -8,29 +9,6 @@ pub struct LexError {
     pub character: usize,
 }
 
-impl LexError {
-    pub fn pretty(self, filename: &str, source: &str) -> String {
-        let line = source.lines().nth(self.line).unwrap();
-        let line_indicator = format!("{} | ", self.line);
-        let marker_line = format!(
-            "{:width$}^ {}",
-            "",
-            self.message,
-            width = line_indicator.len() + self.character + 1
-        );
-        format!(
-            "{}:{}:{}\n\nlex error: {}\n{}{}\n{}",
-            filename,
-            self.line,
-            self.character,
-            self.message,
-            line_indicator,
-            line.replace('\t', " "),
-            marker_line
-        )
-    }
-}
-
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
     Directive(String),
@@ -38,176 +16,328 @@ pub enum TokenKind {
     Number(u16),
     Comma,
     Str(String),
+    /// one of `+ - * / ( )`, for building expressions
+    Operator(char),
+    /// after a label, like `loop:`
+    Colon,
     Newline,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
-    pub offset: usize,
+    pub span: Span,
+    /// where the macro this token was expanded from was used, if it came
+    /// from one
+    pub expansion: Option<Span>,
 }
 
 #[allow(dead_code)]
 impl Token {
-    pub fn new(kind: TokenKind, offset: usize) -> Token {
-        Token { kind, offset }
+    pub fn new(kind: TokenKind) -> Token {
+        Token {
+            kind,
+            span: Span::default(),
+            expansion: None,
+        }
+    }
+
+    pub fn directive(string: &str) -> Token {
+        Token::new(TokenKind::Directive(string.to_string()))
     }
 
-    pub fn directive(string: &str, offset: usize) -> Token {
-        Token::new(TokenKind::Directive(string.to_string()), offset)
+    pub fn symbol(string: &str) -> Token {
+        Token::new(TokenKind::Symbol(string.to_string()))
     }
 
-    pub fn symbol(string: &str, offset: usize) -> Token {
-        Token::new(TokenKind::Symbol(string.to_string()), offset)
+    pub fn number(number: u16) -> Token {
+        Token::new(TokenKind::Number(number))
     }
 
-    pub fn number(number: u16, offset: usize) -> Token {
-        Token::new(TokenKind::Number(number), offset)
+    pub fn str(string: &str) -> Token {
+        Token::new(TokenKind::Str(string.to_string()))
     }
 
-    pub fn str(string: &str, offset: usize) -> Token {
-        Token::new(TokenKind::Str(string.to_string()), offset)
+    pub fn newline() -> Token {
+        Token::new(TokenKind::Newline)
     }
 
-    pub fn newline(offset: usize) -> Token {
-        Token::new(TokenKind::Newline, offset)
+    pub fn comma() -> Token {
+        Token::new(TokenKind::Comma)
     }
 
-    pub fn comma(offset: usize) -> Token {
-        Token::new(TokenKind::Comma, offset)
+    pub fn operator(operator: char) -> Token {
+        Token::new(TokenKind::Operator(operator))
+    }
+
+    pub fn at(self, line: usize, column: usize, len: usize) -> Token {
+        Token {
+            span: Span { line, column, len },
+            ..self
+        }
     }
 }
 
-struct Lexer {
-    reader: Reader<char>,
+struct Lexer<'a> {
+    reader: Reader<'a, char>,
+    /// whether the last token could be the left hand side of an operator, which
+    /// decides whether a `-` is subtraction or the sign of a number
+    after_operand: bool,
 }
 
-impl Lexer {
-    fn from(source: &str) -> Self {
+impl<'a> Lexer<'a> {
+    fn new(chars: &'a [char]) -> Self {
         Self {
-            reader: Reader::from(source.chars().collect(), |c| c == '\n'),
+            reader: Reader::new(chars, |c| *c == '\n'),
+            after_operand: false,
         }
     }
 
-    fn lex(&mut self) -> Result<Vec<Token>, LexError> {
+    fn lex(&mut self) -> Result<Vec<Token>, Vec<LexError>> {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
         loop {
+            let start = self.reader.position();
+            self.after_operand = matches!(
+                tokens.last(),
+                Some(Token {
+                    kind: TokenKind::Number(_) | TokenKind::Symbol(_) | TokenKind::Operator(')'),
+                    ..
+                })
+            );
             match self.reader.peek() {
                 None => break,
-                Some(c) => {
-                    if let Some(token) = self.lex_char(c)? {
-                        tokens.push(token);
+                Some(&c) => match self.lex_char(c) {
+                    Ok(Some(kind)) => tokens.push(Token {
+                        kind,
+                        span: self.reader.span_from(start),
+                        expansion: None,
+                    }),
+                    Ok(None) => {}
+                    Err(err) => {
+                        // give up on the rest of the line, so one bad
+                        // character doesn't hide the errors after it
+                        errors.push(err);
+                        self.reader.skip_while(|c| *c != '\n');
                     }
-                }
+                },
             }
         }
 
-        Ok(tokens)
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
     }
 
     pub(crate) fn take_while<F>(&mut self, predicate: F) -> String
     where
-        F: Fn(char) -> bool + Copy,
+        F: Fn(char) -> bool,
     {
-        self.reader.take_while(predicate).iter().collect()
+        self.reader.take_while(|c| predicate(*c)).iter().collect()
     }
 
-    fn lex_char(&mut self, c: char) -> Result<Option<Token>, LexError> {
+    /// lex whatever starts with `c`, which might not be a token at all
+    fn lex_char(&mut self, c: char) -> Result<Option<TokenKind>, LexError> {
         if c == '\n' {
-            let offset = self.reader.offset;
             self.reader.next();
-            let token = Token {
-                kind: TokenKind::Newline,
-                offset,
-            };
-            return Ok(Some(token));
+            return Ok(Some(TokenKind::Newline));
         }
 
         if c.is_whitespace() {
-            self.reader.skip_while(char::is_whitespace);
+            // newlines are tokens, so they can't be skipped along with the
+            // rest, like the \r of a \r\n or a trailing space
+            self.reader.skip_while(|c| c.is_whitespace() && *c != '\n');
             return Ok(None);
         }
 
         if c == ';' {
-            self.reader.skip_while(|c| c != '\n');
+            self.reader.skip_while(|c| *c != '\n');
             return Ok(None);
         }
 
-        if c == 'x' {
-            let offset = self.reader.offset;
+        // like hex literals, only a word made entirely of binary digits is a
+        // number, so BR, BLKW and labels like `b2` or `bit` are symbols
+        if c == 'b' && self.is_literal(|c| *c == '0' || *c == '1') {
+            self.reader.next();
+            return Ok(Some(self.lex_binary("b")?));
+        }
+
+        let next = self.reader.peek_n(1).copied();
+        if c == '0' && next == Some('b') {
+            self.reader.next();
             self.reader.next();
+            return Ok(Some(self.lex_binary("0b")?));
+        }
 
+        // only a word made entirely of hex digits is a number, so labels
+        // like `xor_result` or plain `x` are still symbols
+        if c == 'x' && self.is_literal(char::is_ascii_hexdigit) {
+            self.reader.next();
             let hex: String = self.take_while(char::is_alphanumeric);
             let num = u16::from_str_radix(&hex, 16)
                 .map_err(|e| self.error(format!("invalid hex literal 'x{}': {}", hex, e)))?;
-
-            let token = Token {
-                kind: TokenKind::Number(num),
-                offset,
-            };
-
-            return Ok(Some(token));
+            return Ok(Some(TokenKind::Number(num)));
         }
 
         if c == '#' {
-            let offset = self.reader.offset;
             self.reader.next();
-            return Ok(Some(self.lex_decimal(offset)?));
+            return Ok(Some(self.lex_decimal()?));
         }
 
-        if c.is_numeric() || c == '-' {
-            let offset = self.reader.offset;
-            return Ok(Some(self.lex_decimal(offset)?));
+        let negative_number = c == '-' && !self.after_operand && next.is_some_and(char::is_numeric);
+        if c.is_numeric() || negative_number {
+            return Ok(Some(self.lex_decimal()?));
+        }
+
+        if "+-*/()".contains(c) {
+            self.reader.next();
+            return Ok(Some(TokenKind::Operator(c)));
         }
 
         if c == ',' {
-            let offset = self.reader.offset;
             self.reader.next();
-            let token = Token {
-                kind: TokenKind::Comma,
-                offset,
-            };
-            return Ok(Some(token));
+            return Ok(Some(TokenKind::Comma));
+        }
+
+        if c == ':' {
+            self.reader.next();
+            return Ok(Some(TokenKind::Colon));
         }
 
         if c == '.' {
-            let offset = self.reader.offset;
             self.reader.next();
-            let directive = self.take_while(char::is_alphanumeric);
-            let token = Token {
-                kind: TokenKind::Directive(directive),
-                offset,
-            };
-            return Ok(Some(token));
+            let directive = self.take_while(|c| c.is_alphanumeric() || c == '_');
+            return Ok(Some(TokenKind::Directive(directive)));
         }
 
-        if c == '"' {
-            let offset = self.reader.offset;
+        if c == '\'' {
             self.reader.next();
-            let string = self.take_while(|c| c != '"');
+            return Ok(Some(self.lex_char_literal()?));
+        }
+
+        if c == '"' {
             self.reader.next();
-            let token = Token {
-                kind: TokenKind::Str(string),
-                offset,
-            };
-            return Ok(Some(token));
+            return Ok(Some(self.lex_string()?));
         }
 
         if c.is_alphabetic() {
-            let offset = self.reader.offset;
             let symbol = self.take_while(|c| c.is_alphanumeric() || c == '_');
-            let token = Token {
-                kind: TokenKind::Symbol(symbol),
-                offset,
-            };
-            return Ok(Some(token));
+            return Ok(Some(TokenKind::Symbol(symbol)));
         }
 
+        self.reader.next();
         Err(self.error(format!("unexpected char {}", c)))
     }
 
-    fn lex_decimal(&mut self, offset: usize) -> Result<Token, LexError> {
-        let negative = if self.reader.peek().map_or(false, |c| c == '-') {
+    /// lex a character literal like 'a' or '\n' into its ASCII code, after
+    /// the opening quote
+    fn lex_char_literal(&mut self) -> Result<TokenKind, LexError> {
+        // the newline is left for the next token, so errors stay on this line
+        let c = match self.reader.peek().copied() {
+            None | Some('\n') => {
+                return Err(self.error(String::from("unterminated character literal")))
+            }
+            Some('\'') => {
+                self.reader.next();
+                return Err(self.error(String::from("empty character literal")));
+            }
+            Some('\\') => {
+                self.reader.next();
+                self.lex_escape()?
+            }
+            Some(c) => {
+                self.reader.next();
+                c
+            }
+        };
+
+        match self.reader.peek().copied() {
+            Some('\'') => {
+                self.reader.next();
+            }
+            Some(c) if c != '\n' => {
+                let closed_later = self
+                    .reader
+                    .rest()
+                    .iter()
+                    .take_while(|c| **c != '\n')
+                    .any(|c| *c == '\'');
+                self.reader.next();
+                return Err(self.error(String::from(if closed_later {
+                    "character literals can only hold one character"
+                } else {
+                    "unterminated character literal"
+                })));
+            }
+            _ => return Err(self.error(String::from("unterminated character literal"))),
+        }
+
+        u16::try_from(c as u32)
+            .map(TokenKind::Number)
+            .map_err(|_| self.error(format!("'{}' does not fit in 16 bits", c)))
+    }
+
+    /// lex the contents of a string literal, after the opening quote
+    fn lex_string(&mut self) -> Result<TokenKind, LexError> {
+        // point at the opening quote, since the missing closing one could be
+        // anywhere
+        let unterminated = self.error(String::from("unterminated string literal"));
+        let mut string = String::new();
+        loop {
+            let c = match self.reader.peek().copied() {
+                None | Some('\n') => return Err(unterminated),
+                Some(c) => c,
+            };
+            self.reader.next();
+            match c {
+                '"' => return Ok(TokenKind::Str(string)),
+                '\\' => string.push(self.lex_escape()?),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// lex what follows a backslash in a character or string literal
+    fn lex_escape(&mut self) -> Result<char, LexError> {
+        if self.reader.peek().is_none_or(|c| *c == '\n') {
+            return Err(self.error(String::from("unterminated escape sequence")));
+        }
+        match self.reader.next().copied() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('\'') => Ok('\''),
+            Some('"') => Ok('"'),
+            c => Err(self.error(format!("unknown escape sequence '\\{}'", c.unwrap_or(' ')))),
+        }
+    }
+
+    /// whether the word at the current offset is a prefix, like the `x` of a
+    /// hex literal, followed by only the digits `is_digit` accepts
+    fn is_literal(&self, is_digit: fn(&char) -> bool) -> bool {
+        let digits: Vec<char> = self.reader.rest()[1..]
+            .iter()
+            .copied()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        !digits.is_empty() && digits.iter().all(is_digit)
+    }
+
+    /// lex the digits of a binary literal, after its prefix
+    fn lex_binary(&mut self, prefix: &str) -> Result<TokenKind, LexError> {
+        let bin = self.take_while(char::is_alphanumeric);
+        let num = u16::from_str_radix(&bin, 2).map_err(|e| {
+            self.error(format!("invalid binary literal '{}{}': {}", prefix, bin, e))
+        })?;
+        Ok(TokenKind::Number(num))
+    }
+
+    fn lex_decimal(&mut self) -> Result<TokenKind, LexError> {
+        let negative = if self.reader.peek() == Some(&'-') {
             self.reader.next(); // skip the sign
             true
         } else {
@@ -215,38 +345,33 @@ impl Lexer {
         };
 
         let dec = self.take_while(char::is_alphanumeric);
-        let num = u16::from_str_radix(&dec, 10)
-            .map(|num| {
-                if negative {
-                    flip_sign_twos_complement(num)
-                } else {
-                    num
-                }
-            })
+        let num = dec
+            .parse::<i64>()
+            .map(|num| if negative { -num } else { num })
             .map_err(|e| self.error(format!("invalid decimal literal '{}': {}", dec, e)))?;
-        let token = Token {
-            kind: TokenKind::Number(num),
-            offset,
-        };
-        Ok(token)
+        // a word can be read as signed or unsigned, so either kind of number
+        // fits, but anything bigger would silently wrap around
+        if !(-0x8000..=0xFFFF).contains(&num) {
+            return Err(self.error(format!(
+                "decimal literal is out of range: {} does not fit in 16 bits (-32768..65535)",
+                num
+            )));
+        }
+        Ok(TokenKind::Number(num as u16))
     }
 
     fn error(&self, message: String) -> LexError {
         LexError {
             message,
-            line: self.reader.line,
-            character: self.reader.item_in_line - 1,
+            line: self.reader.position().line,
+            character: self.reader.position().column - 1,
         }
     }
 }
 
-/// flip the sign of an unsigned integer
-fn flip_sign_twos_complement(n: u16) -> u16 {
-    !(n - 1)
-}
-
-pub fn lex(source: &str) -> Result<Vec<Token>, LexError> {
-    Lexer::from(source).lex()
+pub fn lex(source: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    let chars: Vec<char> = source.chars().collect();
+    Lexer::new(&chars).lex()
 }
 
 #[cfg(test)]
@@ -255,7 +380,23 @@ mod tests {
 
     #[test]
     fn test_ignores_whitespace() {
-        assert_eq!(lex(" \n\r\t"), Ok(vec![]));
+        assert_eq!(lex(" \r\t"), Ok(vec![]));
+        assert_eq!(lex(" \r\n\t"), Ok(vec![Token::newline().at(0, 2, 1)]));
+    }
+
+    #[test]
+    fn test_lex_line_endings() {
+        let halts = |newline: usize| {
+            Ok(vec![
+                Token::symbol("HALT").at(0, 0, 4),
+                Token::newline().at(0, newline, 1),
+                Token::symbol("HALT").at(1, 0, 4),
+            ])
+        };
+        assert_eq!(lex("HALT\nHALT"), halts(4));
+        assert_eq!(lex("HALT\r\nHALT"), halts(5));
+        // whitespace before a newline doesn't take the newline with it
+        assert_eq!(lex("HALT \nHALT"), halts(5));
     }
 
     #[test]
@@ -263,11 +404,14 @@ mod tests {
         assert_eq!(lex("; this is a comment"), Ok(vec![]));
         assert_eq!(
             lex(".directive ; this is a comment"),
-            Ok(vec![Token::directive("directive", 0)])
+            Ok(vec![Token::directive("directive").at(0, 0, 10)])
         );
         assert_eq!(
             lex(".label\n ; this is a comment"),
-            Ok(vec![Token::directive("label", 0), Token::newline(6)])
+            Ok(vec![
+                Token::directive("label").at(0, 0, 6),
+                Token::newline().at(0, 6, 1)
+            ])
         );
     }
 
@@ -275,7 +419,10 @@ mod tests {
     fn test_continues_after_comments() {
         assert_eq!(
             lex("; a\n.directive"),
-            Ok(vec![Token::newline(3), Token::directive("directive", 4)])
+            Ok(vec![
+                Token::newline().at(0, 3, 1),
+                Token::directive("directive").at(1, 0, 10)
+            ])
         );
     }
 
@@ -283,104 +430,338 @@ mod tests {
     fn test_lex_directive() {
         assert_eq!(
             lex(".directive"),
-            Ok(vec![Token::directive("directive", 0)])
+            Ok(vec![Token::directive("directive").at(0, 0, 10)])
         );
         assert_eq!(
             lex("    .directive"),
-            Ok(vec![Token::directive("directive", 4)])
+            Ok(vec![Token::directive("directive").at(0, 4, 10)])
         );
         assert_eq!(
             lex("\n.directive"),
-            Ok(vec![Token::newline(0), Token::directive("directive", 1)])
+            Ok(vec![
+                Token::newline().at(0, 0, 1),
+                Token::directive("directive").at(1, 0, 10)
+            ])
         );
         assert_eq!(
             lex(".d1\n.d2"),
             Ok(vec![
-                Token::directive("d1", 0),
-                Token::newline(3),
-                Token::directive("d2", 4)
+                Token::directive("d1").at(0, 0, 3),
+                Token::newline().at(0, 3, 1),
+                Token::directive("d2").at(1, 0, 3)
             ])
         );
     }
 
     #[test]
     fn test_lex_symbol() {
-        assert_eq!(lex("sym"), Ok(vec![Token::symbol("sym", 0)]));
+        assert_eq!(lex("sym"), Ok(vec![Token::symbol("sym").at(0, 0, 3)]));
         assert_eq!(
             lex("s1\ns2"),
             Ok(vec![
-                Token::symbol("s1", 0),
-                Token::newline(2),
-                Token::symbol("s2", 3)
+                Token::symbol("s1").at(0, 0, 2),
+                Token::newline().at(0, 2, 1),
+                Token::symbol("s2").at(1, 0, 2)
             ])
         );
     }
 
     #[test]
     fn test_lex_hex() {
-        assert_eq!(lex("x0"), Ok(vec![Token::number(0, 0)]));
-        assert_eq!(lex("xFFFF"), Ok(vec![Token::number(0xFFFF, 0)]));
+        assert_eq!(lex("x0"), Ok(vec![Token::number(0).at(0, 0, 2)]));
+        assert_eq!(lex("xFFFF"), Ok(vec![Token::number(0xFFFF).at(0, 0, 5)]));
         assert_eq!(
-            lex("xG"),
-            Err(LexError {
-                message: "invalid hex literal 'xG': invalid digit found in string".to_string(),
+            lex("x10000"),
+            Err(vec![LexError {
+                message: "invalid hex literal 'x10000': number too large to fit in target type"
+                    .to_string(),
                 line: 0,
-                character: 1,
-            })
+                character: 5,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_lex_colon() {
+        assert_eq!(
+            lex("loop: HALT"),
+            Ok(vec![
+                Token::symbol("loop").at(0, 0, 4),
+                Token::new(TokenKind::Colon).at(0, 4, 1),
+                Token::symbol("HALT").at(0, 6, 4),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lex_operators() {
+        assert_eq!(
+            lex("SIZE*2+(x10-1)/-3"),
+            Ok(vec![
+                Token::symbol("SIZE").at(0, 0, 4),
+                Token::operator('*').at(0, 4, 1),
+                Token::number(2).at(0, 5, 1),
+                Token::operator('+').at(0, 6, 1),
+                Token::operator('(').at(0, 7, 1),
+                Token::number(0x10).at(0, 8, 3),
+                Token::operator('-').at(0, 11, 1),
+                Token::number(1).at(0, 12, 1),
+                Token::operator(')').at(0, 13, 1),
+                Token::operator('/').at(0, 14, 1),
+                Token::number(0xFFFD).at(0, 15, 2),
+            ])
+        );
+        // a minus after a comma, or with nothing before it, is a sign
+        assert_eq!(
+            lex("-1, -SIZE"),
+            Ok(vec![
+                Token::number(0xFFFF).at(0, 0, 2),
+                Token::comma().at(0, 2, 1),
+                Token::operator('-').at(0, 4, 1),
+                Token::symbol("SIZE").at(0, 5, 4),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lex_symbols_starting_with_x() {
+        assert_eq!(lex("x"), Ok(vec![Token::symbol("x").at(0, 0, 1)]));
+        assert_eq!(lex("xG"), Ok(vec![Token::symbol("xG").at(0, 0, 2)]));
+        assert_eq!(
+            lex("xor_result"),
+            Ok(vec![Token::symbol("xor_result").at(0, 0, 10)])
+        );
+        assert_eq!(lex("x1_"), Ok(vec![Token::symbol("x1_").at(0, 0, 3)]));
+        // a word of hex digits is always a number, even if it reads like a word
+        assert_eq!(lex("xAdd"), Ok(vec![Token::number(0xADD).at(0, 0, 4)]));
+        assert_eq!(
+            lex("BRz x,x1"),
+            Ok(vec![
+                Token::symbol("BRz").at(0, 0, 3),
+                Token::symbol("x").at(0, 4, 1),
+                Token::comma().at(0, 5, 1),
+                Token::number(1).at(0, 6, 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lex_symbols_starting_with_b() {
+        assert_eq!(lex("b"), Ok(vec![Token::symbol("b").at(0, 0, 1)]));
+        assert_eq!(lex("b2"), Ok(vec![Token::symbol("b2").at(0, 0, 2)]));
+        assert_eq!(lex("bit"), Ok(vec![Token::symbol("bit").at(0, 0, 3)]));
+        assert_eq!(lex("b102"), Ok(vec![Token::symbol("b102").at(0, 0, 4)]));
+        // a word of binary digits is always a number, like a word of hex ones
+        assert_eq!(lex("b1"), Ok(vec![Token::number(1).at(0, 0, 2)]));
+        assert_eq!(
+            lex("b2 BRz b2"),
+            Ok(vec![
+                Token::symbol("b2").at(0, 0, 2),
+                Token::symbol("BRz").at(0, 3, 3),
+                Token::symbol("b2").at(0, 7, 2),
+            ])
         );
     }
 
     #[test]
     fn test_lex_decimal() {
-        assert_eq!(lex("#0"), Ok(vec![Token::number(0, 0)]));
-        assert_eq!(lex("#1000"), Ok(vec![Token::number(1000, 0)]));
+        assert_eq!(lex("#0"), Ok(vec![Token::number(0).at(0, 0, 2)]));
+        assert_eq!(lex("#1000"), Ok(vec![Token::number(1000).at(0, 0, 5)]));
         assert_eq!(
             lex("#-1"),
-            Ok(vec![Token::number(0b1111_1111_1111_1111, 0)])
+            Ok(vec![Token::number(0b1111_1111_1111_1111).at(0, 0, 3)])
+        );
+        // negative zero is just zero
+        assert_eq!(lex("#-0"), Ok(vec![Token::number(0).at(0, 0, 3)]));
+        assert_eq!(lex("-0"), Ok(vec![Token::number(0).at(0, 0, 2)]));
+        assert_eq!(lex("#65535"), Ok(vec![Token::number(0xFFFF).at(0, 0, 6)]));
+        assert_eq!(lex("#-32768"), Ok(vec![Token::number(0x8000).at(0, 0, 7)]));
+        assert_eq!(
+            lex("#-40000"),
+            Err(vec![LexError {
+                message: "decimal literal is out of range: -40000 does not fit in 16 bits (-32768..65535)"
+                    .to_string(),
+                line: 0,
+                character: 6,
+            }])
+        );
+        assert_eq!(
+            lex("70000"),
+            Err(vec![LexError {
+                message:
+                    "decimal literal is out of range: 70000 does not fit in 16 bits (-32768..65535)"
+                        .to_string(),
+                line: 0,
+                character: 4,
+            }])
         );
         assert_eq!(
             lex("#G"),
-            Err(LexError {
+            Err(vec![LexError {
                 message: "invalid decimal literal 'G': invalid digit found in string".to_string(),
                 line: 0,
                 character: 1,
-            })
+            }])
+        );
+    }
+
+    #[test]
+    fn test_lex_binary() {
+        assert_eq!(lex("b0"), Ok(vec![Token::number(0).at(0, 0, 2)]));
+        assert_eq!(
+            lex("b10000000"),
+            Ok(vec![Token::number(0b1000_0000).at(0, 0, 9)])
+        );
+        assert_eq!(lex("0b101"), Ok(vec![Token::number(0b101).at(0, 0, 5)]));
+        assert_eq!(
+            lex("b1111111111111111"),
+            Ok(vec![Token::number(0xFFFF).at(0, 0, 17)])
+        );
+        // symbols can still start with a b
+        assert_eq!(
+            lex("BRz b_1"),
+            Ok(vec![
+                Token::symbol("BRz").at(0, 0, 3),
+                Token::symbol("b_1").at(0, 4, 3)
+            ])
+        );
+        assert_eq!(
+            lex("0b"),
+            Err(vec![LexError {
+                message: "invalid binary literal '0b': cannot parse integer from empty string"
+                    .to_string(),
+                line: 0,
+                character: 1,
+            }])
+        );
+        assert_eq!(
+            lex("b10000000000000000"),
+            Err(vec![LexError {
+                message:
+                    "invalid binary literal 'b10000000000000000': number too large to fit in target type"
+                        .to_string(),
+                line: 0,
+                character: 17,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_reports_every_bad_line() {
+        assert_eq!(
+            lex("@ @\nADD\n  #G"),
+            Err(vec![
+                LexError {
+                    message: "unexpected char @".to_string(),
+                    line: 0,
+                    character: 0,
+                },
+                LexError {
+                    message: "invalid decimal literal 'G': invalid digit found in string"
+                        .to_string(),
+                    line: 2,
+                    character: 3,
+                },
+            ])
         );
     }
 
+    #[test]
+    fn test_lex_char_literals() {
+        assert_eq!(lex("'A'"), Ok(vec![Token::number(65).at(0, 0, 3)]));
+        assert_eq!(lex("'0'"), Ok(vec![Token::number(48).at(0, 0, 3)]));
+        assert_eq!(lex("' '"), Ok(vec![Token::number(32).at(0, 0, 3)]));
+        assert_eq!(lex("';'"), Ok(vec![Token::number(59).at(0, 0, 3)]));
+        assert_eq!(lex("'\"'"), Ok(vec![Token::number(34).at(0, 0, 3)]));
+        assert_eq!(lex("'\\n'"), Ok(vec![Token::number(10).at(0, 0, 4)]));
+        assert_eq!(lex("'\\''"), Ok(vec![Token::number(39).at(0, 0, 4)]));
+        assert_eq!(lex("'\\\\'"), Ok(vec![Token::number(92).at(0, 0, 4)]));
+        assert_eq!(lex("'\\0'"), Ok(vec![Token::number(0).at(0, 0, 4)]));
+        assert_eq!(
+            lex(".FILL 'a', 'b'"),
+            Ok(vec![
+                Token::directive("FILL").at(0, 0, 5),
+                Token::number(97).at(0, 6, 3),
+                Token::comma().at(0, 9, 1),
+                Token::number(98).at(0, 11, 3),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_bad_char_literals() {
+        let message = |source| lex(source).unwrap_err().remove(0).message;
+        assert_eq!(message("''"), "empty character literal");
+        assert_eq!(message("'a"), "unterminated character literal");
+        assert_eq!(message("'a\n'"), "unterminated character literal");
+        assert_eq!(message("'"), "unterminated character literal");
+        assert_eq!(
+            message("'ab'"),
+            "character literals can only hold one character"
+        );
+        assert_eq!(message("'\\q'"), "unknown escape sequence '\\q'");
+        assert_eq!(message("'😀'"), "'😀' does not fit in 16 bits");
+        assert_eq!(message("'\\\n'"), "unterminated escape sequence");
+    }
+
     #[test]
     fn test_lex_strings() {
-        assert_eq!(lex("\"hello\""), Ok(vec![Token::str("hello", 0)]));
+        assert_eq!(lex("\"hello\""), Ok(vec![Token::str("hello").at(0, 0, 7)]));
+        assert_eq!(
+            lex(r#""line\n\t\"quoted\"\\\0""#),
+            Ok(vec![Token::str("line\n\t\"quoted\"\\\0").at(0, 0, 24)])
+        );
+        assert_eq!(
+            lex(r#"msg .STRINGZ "a\q""#)
+                .unwrap_err()
+                .into_iter()
+                .map(|error| (error.message, error.character))
+                .collect::<Vec<_>>(),
+            vec![(String::from("unknown escape sequence '\\q'"), 16)]
+        );
+        assert_eq!(
+            lex("msg .STRINGZ \"abc\nHALT\n")
+                .unwrap_err()
+                .into_iter()
+                .map(|error| (error.message, error.line, error.character))
+                .collect::<Vec<_>>(),
+            vec![(String::from("unterminated string literal"), 0, 13)]
+        );
+        assert!(lex("\"abc").is_err());
+        assert!(lex("\"abc\\\"").is_err());
     }
 
     #[test]
     fn test_real_asm() {
         assert_eq!(
             lex(".orig x3000"),
-            Ok(vec![Token::directive("orig", 0), Token::number(0x3000, 6)])
+            Ok(vec![
+                Token::directive("orig").at(0, 0, 5),
+                Token::number(0x3000).at(0, 6, 5)
+            ])
         );
         assert_eq!(
             lex("	.FILL BAD_INT	; x01"),
             Ok(vec![
-                Token::directive("FILL", 1),
-                Token::symbol("BAD_INT", 7)
+                Token::directive("FILL").at(0, 1, 5),
+                Token::symbol("BAD_INT").at(0, 7, 7)
             ])
         );
         assert_eq!(
             lex("LD R0, MPR_INIT"),
             Ok(vec![
-                Token::symbol("LD", 0),
-                Token::symbol("R0", 3),
-                Token::comma(5),
-                Token::symbol("MPR_INIT", 7)
+                Token::symbol("LD").at(0, 0, 2),
+                Token::symbol("R0").at(0, 3, 2),
+                Token::comma().at(0, 5, 1),
+                Token::symbol("MPR_INIT").at(0, 7, 8)
             ])
         );
         assert_eq!(
             lex("mystring .STRINGZ \"hello\""),
             Ok(vec![
-                Token::symbol("mystring", 0),
-                Token::directive("STRINGZ", 9),
-                Token::str("hello", 18)
+                Token::symbol("mystring").at(0, 0, 8),
+                Token::directive("STRINGZ").at(0, 9, 8),
+                Token::str("hello").at(0, 18, 7)
             ])
         );
     }
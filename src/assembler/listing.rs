@@ -0,0 +1,78 @@
+use super::{Executable, Segment};
+
+/// render an lc3as-style listing: every source line next to the address and
+/// words (in hex and binary) that were assembled from it
+pub fn to_string(executable: &Executable, source: &str) -> String {
+    let mut listing = String::new();
+    let source_line = |address| {
+        executable
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.location(address))
+            .map(|location| location.line)
+    };
+
+    // segments are emitted in source order, so the words can be consumed
+    // alongside the lines they came from
+    let mut words = executable
+        .segments
+        .iter()
+        .flat_map(Segment::addressed_words)
+        .peekable();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let mut first = true;
+        while let Some((address, word)) =
+            words.next_if(|(address, _)| source_line(*address) == Some(line_number))
+        {
+            let text = if first {
+                format!("({:4}) {}", line_number + 1, line)
+            } else {
+                String::new()
+            };
+            listing.push_str(
+                format!("({:04X}) {:04X}  {:016b} {}", address, word, word, text).trim_end(),
+            );
+            listing.push('\n');
+            first = false;
+        }
+
+        if first {
+            listing.push_str(format!("{:30}({:4}) {}", "", line_number + 1, line).trim_end());
+            listing.push('\n');
+        }
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_listing() {
+        let source = ".ORIG x3000
+loop ADD R0, R0, #-1
+     BRp loop
+msg  .STRINGZ \"hi\"
+.ORIG x4000
+.FILL xFFFF
+.END";
+        let executable = assemble("test.asm", source).unwrap();
+        assert_eq!(
+            to_string(&executable, source),
+            "                              (   1) .ORIG x3000
+(3000) 103F  0001000000111111 (   2) loop ADD R0, R0, #-1
+(3001) 03FE  0000001111111110 (   3)      BRp loop
+(3002) 0068  0000000001101000 (   4) msg  .STRINGZ \"hi\"
+(3003) 0069  0000000001101001
+(3004) 0000  0000000000000000
+                              (   5) .ORIG x4000
+(4000) FFFF  1111111111111111 (   6) .FILL xFFFF
+                              (   7) .END
+"
+        );
+    }
+}
@@ -0,0 +1,557 @@
+use crate::assembler::diagnostic::{Diagnostic, Label};
+use crate::assembler::lexer::{Token, TokenKind};
+use crate::assembler::parser::is_mnemonic;
+use crate::assembler::Span;
+use std::collections::{HashMap, HashSet};
+
+/// how deep macros can be used inside each other before it's assumed that one
+/// of them uses itself forever
+const MAX_DEPTH: usize = 64;
+
+/// a block of statements defined between .MACRO and .END_MACRO
+struct Macro {
+    /// where the macro is named in its definition
+    span: Span,
+    params: Vec<String>,
+    /// every line of the body, each ending in a newline
+    body: Vec<Token>,
+}
+
+impl Macro {
+    /// the labels defined inside the body, which get renamed every time the
+    /// macro is used so that using it twice doesn't define them twice
+    fn labels(&self, macros: &HashMap<String, Macro>) -> HashSet<String> {
+        split_lines(self.body.clone())
+            .into_iter()
+            .filter_map(|line| match line.into_iter().next() {
+                Some(Token {
+                    kind: TokenKind::Symbol(symbol),
+                    ..
+                }) if !is_mnemonic(&symbol)
+                    && !self.params.contains(&symbol)
+                    && !macros.contains_key(&symbol) =>
+                {
+                    Some(symbol)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>,
+    /// how many times a macro has been used so far, for making unique labels
+    expansions: usize,
+    errors: Vec<Diagnostic>,
+}
+
+impl Expander {
+    /// take every macro definition out of the program, leaving the lines that
+    /// aren't part of one
+    fn collect_definitions(&mut self, lines: Vec<Vec<Token>>) -> Vec<Vec<Token>> {
+        let mut lines = lines.into_iter();
+        let mut rest = Vec::new();
+        while let Some(line) = lines.next() {
+            match directive(&line).as_deref() {
+                Some("macro") => self.define(line, &mut lines),
+                Some("end_macro") => self.errors.push(Diagnostic::error(
+                    String::from(".END_MACRO without a matching .MACRO"),
+                    line[0].span,
+                )),
+                _ => rest.push(line),
+            }
+        }
+        rest
+    }
+
+    /// define a macro, given its .MACRO line and the lines that follow it
+    fn define(&mut self, header: Vec<Token>, lines: &mut impl Iterator<Item = Vec<Token>>) {
+        let directive_span = header[0].span;
+        // take the body first, so a bad header doesn't leave it to be
+        // assembled as if it were outside the macro
+        let body = match self.take_body(lines) {
+            Some(body) => body,
+            None => {
+                self.errors.push(Diagnostic::error(
+                    String::from("unterminated macro: expected .END_MACRO"),
+                    directive_span,
+                ));
+                return;
+            }
+        };
+
+        let mut operands = header
+            .into_iter()
+            .skip(1)
+            .filter(|token| token.kind != TokenKind::Newline);
+        let (name, span) = match operands.next() {
+            Some(Token {
+                kind: TokenKind::Symbol(name),
+                span,
+                ..
+            }) => (name, span),
+            other => {
+                let span = other.map_or(directive_span, |token| token.span);
+                self.errors.push(Diagnostic::error(
+                    String::from("expected a macro name"),
+                    span,
+                ));
+                return;
+            }
+        };
+        if is_mnemonic(&name) {
+            self.errors.push(Diagnostic::error(
+                format!(
+                    "{} is an instruction, so it can't be the name of a macro",
+                    name
+                ),
+                span,
+            ));
+            return;
+        }
+
+        // parameters are separated by commas, like operands
+        let mut params = Vec::new();
+        let operands: Vec<Token> = operands.collect();
+        for (index, token) in operands.iter().enumerate() {
+            match (&token.kind, index % 2) {
+                (TokenKind::Symbol(param), 0) => params.push(param.clone()),
+                (TokenKind::Comma, 1) => {}
+                (_, 0) => {
+                    self.errors.push(Diagnostic::error(
+                        String::from("expected a parameter name"),
+                        token.span,
+                    ));
+                    return;
+                }
+                _ => {
+                    self.errors.push(Diagnostic::error(
+                        String::from("expected a comma"),
+                        token.span,
+                    ));
+                    return;
+                }
+            }
+        }
+        if operands
+            .last()
+            .is_some_and(|token| token.kind == TokenKind::Comma)
+        {
+            self.errors.push(Diagnostic::error(
+                String::from("expected a parameter name after the comma"),
+                operands.last().unwrap().span,
+            ));
+            return;
+        }
+
+        if let Some(previous) = self.macros.get(&name) {
+            self.errors.push(Diagnostic {
+                labels: vec![Label {
+                    span: previous.span,
+                    message: format!("{} is first defined here", name),
+                }],
+                ..Diagnostic::error(format!("macro {} is already defined", name), span)
+            });
+            return;
+        }
+        self.macros.insert(name, Macro { span, params, body });
+    }
+
+    /// take the lines up to the .END_MACRO that ends a definition, or nothing
+    /// if there isn't one
+    fn take_body(&mut self, lines: &mut impl Iterator<Item = Vec<Token>>) -> Option<Vec<Token>> {
+        let mut body = Vec::new();
+        // a definition inside another one is an error, but its .END_MACRO
+        // still shouldn't end the outer one
+        let mut depth = 0;
+        for mut line in lines {
+            match directive(&line).as_deref() {
+                Some("macro") => {
+                    self.errors.push(Diagnostic::error(
+                        String::from("macros can't be defined inside other macros"),
+                        line[0].span,
+                    ));
+                    depth += 1;
+                }
+                Some("end_macro") if depth == 0 => return Some(body),
+                Some("end_macro") => depth -= 1,
+                _ if depth == 0 => {
+                    if line
+                        .last()
+                        .is_some_and(|token| token.kind != TokenKind::Newline)
+                    {
+                        line.push(Token::new(TokenKind::Newline));
+                    }
+                    body.extend(line);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// copy a line to the output, replacing it with the body of the macro it
+    /// uses if it uses one
+    fn expand_line(&mut self, mut line: Vec<Token>, depth: usize, output: &mut Vec<Token>) {
+        // a macro can be used on its own or after a label, just like an
+        // instruction
+        let label_len = match (line.first(), line.get(1)) {
+            (
+                Some(Token {
+                    kind: TokenKind::Symbol(label),
+                    ..
+                }),
+                Some(Token {
+                    kind: TokenKind::Colon,
+                    ..
+                }),
+            ) if !is_mnemonic(label) => 2,
+            (
+                Some(Token {
+                    kind: TokenKind::Symbol(label),
+                    ..
+                }),
+                _,
+            ) if !is_mnemonic(label) && !self.macros.contains_key(label) => 1,
+            _ => 0,
+        };
+        if line
+            .get(label_len)
+            .is_none_or(|token| self.macro_name(token).is_none())
+        {
+            output.extend(line);
+            return;
+        }
+
+        if label_len > 0 {
+            // put the label on a line of its own, so it gets the address of
+            // whatever the macro starts with
+            let label: Vec<Token> = line.drain(..label_len).collect();
+            let end = label.last().unwrap().span;
+            let newline = Token {
+                span: Span {
+                    column: end.column + end.len,
+                    len: 0,
+                    ..end
+                },
+                ..Token::new(TokenKind::Newline)
+            };
+            output.extend(label);
+            output.push(newline);
+        }
+        let use_token = line.remove(0);
+        let name = self.macro_name(&use_token).unwrap().to_string();
+        let newline = match line.last() {
+            Some(token) if token.kind == TokenKind::Newline => line.pop(),
+            _ => None,
+        };
+
+        match self.expand(&name, &use_token, line, depth) {
+            Ok(lines) => {
+                for line in lines {
+                    self.expand_line(line, depth + 1, output);
+                }
+            }
+            Err(error) => self.errors.push(error),
+        }
+        output.extend(newline);
+    }
+
+    /// the lines of a macro's body, with its parameters replaced by the
+    /// arguments it was used with
+    fn expand(
+        &mut self,
+        name: &str,
+        use_token: &Token,
+        args: Vec<Token>,
+        depth: usize,
+    ) -> Result<Vec<Vec<Token>>, Diagnostic> {
+        let definition = &self.macros[name];
+        let defined_here = Label {
+            span: definition.span,
+            message: format!("{} is defined here", name),
+        };
+        let args = split_args(args).map_err(|span| Diagnostic {
+            labels: vec![defined_here.clone()],
+            ..Diagnostic::error(String::from("expected an argument"), span)
+        })?;
+        if args.len() != definition.params.len() {
+            return Err(Diagnostic {
+                labels: vec![defined_here],
+                ..Diagnostic::error(
+                    format!(
+                        "macro {} takes {} argument{} but {} {} given",
+                        name,
+                        definition.params.len(),
+                        if definition.params.len() == 1 {
+                            ""
+                        } else {
+                            "s"
+                        },
+                        args.len(),
+                        if args.len() == 1 { "was" } else { "were" },
+                    ),
+                    use_token.span,
+                )
+            });
+        }
+        if depth >= MAX_DEPTH {
+            return Err(Diagnostic::error(
+                format!(
+                    "macro {} is nested more than {} deep, it probably uses itself",
+                    name, MAX_DEPTH
+                ),
+                use_token.span,
+            ));
+        }
+
+        // nested macros belong to the outermost use, which is what's actually
+        // written in the program
+        let site = use_token.expansion.unwrap_or(use_token.span);
+        let labels = definition.labels(&self.macros);
+        self.expansions += 1;
+        let suffix = format!("@{}", self.expansions);
+
+        let mut body = Vec::new();
+        for token in &definition.body {
+            match &token.kind {
+                TokenKind::Symbol(symbol) => {
+                    if let Some(index) = definition.params.iter().position(|p| p == symbol) {
+                        body.extend(args[index].iter().map(|arg| Token {
+                            expansion: Some(site),
+                            ..arg.clone()
+                        }));
+                        continue;
+                    }
+                    let kind = if labels.contains(symbol) {
+                        TokenKind::Symbol(format!("{}{}", symbol, suffix))
+                    } else {
+                        token.kind.clone()
+                    };
+                    body.push(Token {
+                        kind,
+                        expansion: Some(site),
+                        ..token.clone()
+                    });
+                }
+                _ => body.push(Token {
+                    expansion: Some(site),
+                    ..token.clone()
+                }),
+            }
+        }
+        Ok(split_lines(body))
+    }
+
+    fn macro_name<'a>(&self, token: &'a Token) -> Option<&'a str> {
+        match &token.kind {
+            TokenKind::Symbol(symbol) if self.macros.contains_key(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+}
+
+/// the lowercase name of the directive a line starts with, if it starts with one
+fn directive(line: &[Token]) -> Option<String> {
+    match line.first() {
+        Some(Token {
+            kind: TokenKind::Directive(directive),
+            ..
+        }) => Some(directive.to_lowercase()),
+        _ => None,
+    }
+}
+
+/// split tokens into lines, keeping the newline at the end of each one
+fn split_lines(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut lines = vec![Vec::new()];
+    for token in tokens {
+        let is_newline = token.kind == TokenKind::Newline;
+        lines.last_mut().unwrap().push(token);
+        if is_newline {
+            lines.push(Vec::new());
+        }
+    }
+    lines.retain(|line| !line.is_empty());
+    lines
+}
+
+/// split the arguments a macro was used with on their commas. an argument can
+/// be any number of tokens, but not none
+fn split_args(tokens: Vec<Token>) -> Result<Vec<Vec<Token>>, Span> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec![Vec::new()];
+    let mut last_span = Span::default();
+    for token in tokens {
+        last_span = token.span;
+        if token.kind == TokenKind::Comma {
+            if args.last().unwrap().is_empty() {
+                return Err(token.span);
+            }
+            args.push(Vec::new());
+        } else {
+            args.last_mut().unwrap().push(token);
+        }
+    }
+    if args.last().unwrap().is_empty() {
+        return Err(last_span);
+    }
+    Ok(args)
+}
+
+/// replace every use of a macro with its body, and remove the definitions
+pub fn expand(tokens: Vec<Token>) -> Result<Vec<Token>, Vec<Diagnostic>> {
+    let mut expander = Expander::default();
+    let lines = expander.collect_definitions(split_lines(tokens));
+    let mut output = Vec::new();
+    for line in lines {
+        expander.expand_line(line, 0, &mut output);
+    }
+
+    if expander.errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(expander.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::diagnostic::{Diagnostic, Label};
+    use crate::assembler::{assemble, assemble_with_diagnostics, SourceLocation, Span};
+
+    fn span(line: usize, column: usize, len: usize) -> Span {
+        Span { line, column, len }
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        assemble_with_diagnostics("test.asm", source)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    const PUSH: &str = ".MACRO PUSH reg\nADD R6, R6, #-1\nSTR reg, R6, #0\n.END_MACRO\n";
+
+    #[test]
+    fn test_expand_params() {
+        let source = format!("{}.ORIG x3000\nPUSH R0\nPUSH R1\nHALT\n.END", PUSH);
+        let executable = assemble("test.asm", &source).unwrap();
+        assert_eq!(
+            executable.segments[0].words,
+            vec![0x1DBF, 0x7180, 0x1DBF, 0x7380, 0xF025]
+        );
+        // the words a macro expands to belong to the line that used it
+        let debug_info = executable.debug_info.unwrap();
+        assert_eq!(
+            debug_info.location(0x3001),
+            Some(SourceLocation { line: 5, column: 0 })
+        );
+        assert_eq!(
+            debug_info.location(0x3002),
+            Some(SourceLocation { line: 6, column: 0 })
+        );
+    }
+
+    #[test]
+    fn test_unique_labels() {
+        let source =
+            ".MACRO SPIN\nloop BRnzp loop\n.END_MACRO\n.ORIG x3000\nstart SPIN\nend: SPIN\n.END";
+        let executable = assemble("test.asm", source).unwrap();
+        assert_eq!(executable.segments[0].words, vec![0x0FFF, 0x0FFF]);
+        assert_eq!(
+            executable.symbols,
+            vec![
+                (String::from("end"), 0x3001),
+                (String::from("loop@1"), 0x3000),
+                (String::from("loop@2"), 0x3001),
+                (String::from("start"), 0x3000),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_nested_macros() {
+        let source = format!(
+            "{}.MACRO PUSH2 a, b\nPUSH a\nPUSH b\n.END_MACRO\n.ORIG x3000\nPUSH2 R0, R1",
+            PUSH
+        );
+        let executable = assemble("test.asm", &source).unwrap();
+        assert_eq!(
+            executable.segments[0].words,
+            vec![0x1DBF, 0x7180, 0x1DBF, 0x7380]
+        );
+    }
+
+    #[test]
+    fn test_errors_point_at_the_body_and_the_use() {
+        let source = ".MACRO BIG\nADD R0, R0, #100\n.END_MACRO\n.ORIG x3000\nHALT\nBIG";
+        assert_eq!(
+            assemble_with_diagnostics("test.asm", source).unwrap_err(),
+            vec![Diagnostic {
+                labels: vec![Label {
+                    span: span(5, 0, 3),
+                    message: String::from("in this macro expansion"),
+                }],
+                ..Diagnostic::error(
+                    String::from(
+                        "ADD immediate is out of range: 100 does not fit in 5 bits (-16..15)"
+                    ),
+                    span(1, 12, 4)
+                )
+            }]
+        );
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let use_once = |macro_use: &str| errors(&format!("{}.ORIG x3000\n{}", PUSH, macro_use));
+        assert_eq!(
+            use_once("PUSH"),
+            vec!["macro PUSH takes 1 argument but 0 were given"]
+        );
+        assert_eq!(
+            use_once("PUSH R0, R1"),
+            vec!["macro PUSH takes 1 argument but 2 were given"]
+        );
+        assert_eq!(use_once("PUSH R0,"), vec!["expected an argument"]);
+
+        assert_eq!(
+            errors(".MACRO FOREVER\nFOREVER\n.END_MACRO\n.ORIG x3000\nFOREVER"),
+            vec!["macro FOREVER is nested more than 64 deep, it probably uses itself"]
+        );
+        assert_eq!(
+            errors(".MACRO A\nHALT\n"),
+            vec!["unterminated macro: expected .END_MACRO"]
+        );
+        assert_eq!(
+            errors(".END_MACRO"),
+            vec![".END_MACRO without a matching .MACRO"]
+        );
+        assert_eq!(
+            errors(".MACRO A\n.MACRO B\n.END_MACRO\n.END_MACRO\n"),
+            vec!["macros can't be defined inside other macros"]
+        );
+        assert_eq!(
+            errors(".MACRO A\n.END_MACRO\n.MACRO A\n.END_MACRO"),
+            vec!["macro A is already defined"]
+        );
+        assert_eq!(
+            errors(".MACRO ADD\n.END_MACRO"),
+            vec!["ADD is an instruction, so it can't be the name of a macro"]
+        );
+        assert_eq!(errors(".MACRO\n.END_MACRO"), vec!["expected a macro name"]);
+        assert_eq!(
+            errors(".MACRO A #1\n.END_MACRO"),
+            vec!["expected a parameter name"]
+        );
+        assert_eq!(errors(".MACRO A x y\n.END_MACRO"), vec!["expected a comma"]);
+    }
+}
@@ -1,16 +1,146 @@
+pub mod diagnostic;
 mod lexer;
+pub mod listing;
+mod macros;
+pub mod object;
+pub mod output;
 mod parser;
 mod reader;
+pub mod symbols;
+
+use diagnostic::Diagnostic;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// a contiguous block of words, started by a .ORIG
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Segment {
+    /// address the first word is loaded at
+    pub origin: u16,
+    pub words: Vec<u16>,
+}
+
+impl Segment {
+    /// the address of every word in the segment, along with the word itself
+    pub fn addressed_words(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .map(move |(index, word)| (self.origin.wrapping_add(index as u16), *word))
+    }
+
+    /// true if one of the segment's words is loaded at `address`
+    pub fn contains(&self, address: u16) -> bool {
+        usize::from(address.wrapping_sub(self.origin)) < self.words.len()
+    }
+}
+
+/// a position in a source file, both zero-indexed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// a run of characters on a single line of source, both zero-indexed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn start(&self) -> SourceLocation {
+        SourceLocation {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+/// where each word of an executable was assembled from
+#[derive(Debug, Default, PartialEq)]
+pub struct DebugInfo {
+    pub filename: String,
+    /// the start of the statement each word was assembled from, by address
+    pub locations: BTreeMap<u16, SourceLocation>,
+    /// addresses of the words assembled from instructions, rather than data
+    pub code: BTreeSet<u16>,
+    /// where each label is defined
+    pub labels: BTreeMap<String, Span>,
+}
+
+impl DebugInfo {
+    pub fn location(&self, address: u16) -> Option<SourceLocation> {
+        self.locations.get(&address).copied()
+    }
+}
 
 #[derive(Debug, Default, PartialEq)]
 pub struct Executable {
-    pub instructions: Vec<u16>,
+    pub segments: Vec<Segment>,
+    /// address execution starts from
+    pub entry: u16,
+    /// the address of every label in the program
+    pub symbols: BTreeMap<String, u16>,
+    /// only available when the executable was assembled from source
+    pub debug_info: Option<DebugInfo>,
 }
 
+/// settings that change how a program gets assembled
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// constants to define before the program starts, like `--define` does
+    pub defines: BTreeMap<String, u16>,
+    /// reject the looser syntax lc3as accepts, like operands without commas
+    /// between them and labels ending in colons
+    pub strict: bool,
+    /// fail on warnings, as though they were errors
+    pub warnings_as_errors: bool,
+    /// labels to start out with, as though something assembled earlier
+    /// defined them. the program can refer to them like its own labels, and
+    /// they're included in its symbols
+    pub symbols: BTreeMap<String, u16>,
+}
+
+/// assemble a program, rendering any errors without color. warnings are dropped
 pub fn assemble(filename: &str, source: &str) -> Result<Executable, String> {
-    let tokens = lexer::lex(source).map_err(|err| err.pretty(filename, source))?;
-    let instructions = parser::parse(tokens).map_err(|err| err.pretty())?;
-    Ok(Executable { instructions })
+    assemble_with_diagnostics(filename, source)
+        .map(|(executable, _)| executable)
+        .map_err(|diagnostics| diagnostic::render(&diagnostics, filename, source, false))
+}
+
+/// assemble a program, collecting everything wrong with it rather than stopping
+/// at the first problem. only fails if at least one diagnostic is an error
+pub fn assemble_with_diagnostics(
+    filename: &str,
+    source: &str,
+) -> Result<(Executable, Vec<Diagnostic>), Vec<Diagnostic>> {
+    assemble_with_options(filename, source, &Options::default())
+}
+
+/// like `assemble_with_diagnostics`, but with settings other than the defaults
+pub fn assemble_with_options(
+    filename: &str,
+    source: &str,
+    options: &Options,
+) -> Result<(Executable, Vec<Diagnostic>), Vec<Diagnostic>> {
+    // parse errors in a file that doesn't lex would mostly be noise
+    let tokens = lexer::lex(source)
+        .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect::<Vec<_>>())?;
+    let tokens = macros::expand(tokens)?;
+    let (executable, warnings) = parser::parse(filename, tokens, options)
+        .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect::<Vec<_>>())?;
+    if options.warnings_as_errors && !warnings.is_empty() {
+        return Err(warnings
+            .into_iter()
+            .map(|warning| Diagnostic {
+                severity: diagnostic::Severity::Error,
+                ..warning
+            })
+            .collect());
+    }
+    Ok((executable, warnings))
 }
 
 #[cfg(test)]
@@ -20,10 +150,320 @@ mod tests {
     #[test]
     fn test_assemble_empty() {
         assert_eq!(
-            assemble("empty.asm", ""),
+            assemble("empty.asm", ".ORIG x3000\n.END"),
             Ok(Executable {
-                instructions: Vec::new()
+                segments: vec![Segment {
+                    origin: 0x3000,
+                    words: Vec::new(),
+                }],
+                entry: 0x3000,
+                symbols: BTreeMap::new(),
+                debug_info: Some(DebugInfo {
+                    filename: String::from("empty.asm"),
+                    locations: BTreeMap::new(),
+                    code: BTreeSet::new(),
+                    labels: BTreeMap::new(),
+                }),
             })
         );
     }
+
+    #[test]
+    fn test_assemble_origin() {
+        assert_eq!(
+            assemble("halt.asm", ".ORIG x4000\nHALT\n.END"),
+            Ok(Executable {
+                segments: vec![Segment {
+                    origin: 0x4000,
+                    words: vec![0xF025],
+                }],
+                entry: 0x4000,
+                symbols: BTreeMap::new(),
+                debug_info: Some(DebugInfo {
+                    filename: String::from("halt.asm"),
+                    locations: vec![(0x4000, SourceLocation { line: 1, column: 0 })]
+                        .into_iter()
+                        .collect(),
+                    code: vec![0x4000].into_iter().collect(),
+                    labels: BTreeMap::new(),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_symbols() {
+        let executable = assemble(
+            "labels.asm",
+            ".ORIG x3000\nstart ADD R0, R0, #1\nloop BR loop\ndata .FILL 0\n.END",
+        )
+        .unwrap();
+        assert_eq!(
+            executable.symbols,
+            vec![
+                (String::from("data"), 0x3002),
+                (String::from("loop"), 0x3001),
+                (String::from("start"), 0x3000),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_assemble_segments() {
+        let executable = assemble(
+            "segments.asm",
+            ".ORIG x3000\nLD R0, data\nHALT\n.ORIG x3100\ndata .FILL x2A\n.END",
+        )
+        .unwrap();
+        assert_eq!(
+            executable.segments,
+            vec![
+                Segment {
+                    origin: 0x3000,
+                    words: vec![0x20FF, 0xF025],
+                },
+                Segment {
+                    origin: 0x3100,
+                    words: vec![0x2A],
+                },
+            ]
+        );
+        assert_eq!(executable.entry, 0x3000);
+    }
+
+    #[test]
+    fn test_addressed_words() {
+        let segment = Segment {
+            origin: 0xFFFF,
+            words: vec![1, 2],
+        };
+        assert_eq!(
+            segment.addressed_words().collect::<Vec<_>>(),
+            vec![(0xFFFF, 1), (0x0000, 2)]
+        );
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let executable = assemble(
+            "debug.asm",
+            ".ORIG x3000\nloop  ADD R0, R0, #1\n      BR loop\n  msg .STRINGZ \"a\"",
+        )
+        .unwrap();
+        let debug_info = executable.debug_info.unwrap();
+        assert_eq!(debug_info.filename, "debug.asm");
+        assert_eq!(
+            debug_info.location(0x3000),
+            Some(SourceLocation { line: 1, column: 6 })
+        );
+        assert_eq!(
+            debug_info.location(0x3001),
+            Some(SourceLocation { line: 2, column: 6 })
+        );
+        assert_eq!(
+            debug_info.location(0x3003),
+            Some(SourceLocation { line: 3, column: 6 })
+        );
+        assert_eq!(debug_info.location(0x3004), None);
+        assert_eq!(debug_info.code, vec![0x3000, 0x3001].into_iter().collect());
+        assert_eq!(
+            debug_info.labels,
+            vec![
+                (
+                    String::from("loop"),
+                    Span {
+                        line: 1,
+                        column: 0,
+                        len: 4
+                    }
+                ),
+                (
+                    String::from("msg"),
+                    Span {
+                        line: 3,
+                        column: 2,
+                        len: 3
+                    }
+                ),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_assemble_char_literals() {
+        let executable = assemble(
+            "chars.asm",
+            ".ORIG x3000\nADD R1, R1, '\\0'\n.FILL '0'\n.FILL '\\n'\n.END",
+        )
+        .unwrap();
+        assert_eq!(executable.segments[0].words, vec![0x1260, 0x30, 0x0A]);
+    }
+
+    #[test]
+    fn test_assemble_without_origin() {
+        assert!(assemble("empty.asm", "").is_err());
+    }
+
+    #[test]
+    fn test_assemble_with_diagnostics() {
+        let (_, warnings) =
+            assemble_with_diagnostics("end.asm", ".ORIG x3000\n.END\nHALT").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, diagnostic::Severity::Warning);
+
+        let errors =
+            assemble_with_diagnostics("bad.asm", "@\n.ORIG x3000\nADD R0, R0, #G").unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.span.line, error.message.as_ref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, "unexpected char @"),
+                (
+                    2,
+                    "invalid decimal literal 'G': invalid digit found in string"
+                ),
+            ]
+        );
+        assert!(errors
+            .iter()
+            .all(|error| error.severity == diagnostic::Severity::Error));
+
+        let errors = assemble_with_diagnostics("bad.asm", ".ORIG x3000\n  ADD R0").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![Diagnostic::error(
+                String::from("unexpected end of input"),
+                Span {
+                    line: 1,
+                    column: 8,
+                    len: 1
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_assemble_with_defines() {
+        let source = ".ORIG x3000\n.IFDEF SIZE\n.BLKW SIZE\n.ELSE\nHALT\n.ENDIF";
+        let words = |options: &Options| {
+            assemble_with_options("test.asm", source, options)
+                .unwrap()
+                .0
+                .segments[0]
+                .words
+                .clone()
+        };
+        assert_eq!(words(&Options::default()), vec![0xF025]);
+        let options = Options {
+            defines: vec![(String::from("SIZE"), 2)].into_iter().collect(),
+            ..Options::default()
+        };
+        assert_eq!(words(&options), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_assemble_with_symbols() {
+        let options = Options {
+            symbols: vec![(String::from("start"), 0x3000)].into_iter().collect(),
+            ..Options::default()
+        };
+        let (executable, warnings) =
+            assemble_with_options("test.asm", ".ORIG x3002\nnext BR start", &options).unwrap();
+        assert_eq!(executable.segments[0].words, vec![0x0FFD]);
+        assert_eq!(
+            executable.symbols,
+            vec![
+                (String::from("next"), 0x3002),
+                (String::from("start"), 0x3000)
+            ]
+            .into_iter()
+            .collect()
+        );
+        // only the program's own labels can go unused
+        assert_eq!(warnings.len(), 1);
+        assert!(assemble("test.asm", ".ORIG x3002\nBR start").is_err());
+    }
+
+    #[test]
+    fn test_warnings_as_errors() {
+        let source = ".ORIG x3000\nunused HALT";
+        let options = Options {
+            warnings_as_errors: true,
+            ..Options::default()
+        };
+        let errors = assemble_with_options("test.asm", source, &options).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![Diagnostic::error(
+                String::from("label unused is never used"),
+                Span {
+                    line: 1,
+                    column: 0,
+                    len: 6
+                }
+            )]
+        );
+        assert!(assemble_with_options("test.asm", source, &Options::default()).is_ok());
+    }
+
+    #[test]
+    fn test_assemble_crlf_line_endings() {
+        let source = ".ORIG x3000\nloop ADD R0, R0, #1 ; count\nBR loop\n.END\n";
+        let words = |source: &str| assemble("crlf.asm", source).unwrap().segments;
+        assert_eq!(words(&source.replace('\n', "\r\n")), words(source));
+    }
+
+    #[test]
+    fn test_assemble_os() {
+        let os = assemble("os.asm", include_str!("../os.asm")).unwrap();
+        assert_eq!(os.segments[0].origin, 0x0000);
+        // the trap vector table points at the trap handlers
+        assert_eq!(os.segments[0].words[0x25], os.symbols["TRAP_HALT"],);
+    }
+
+    #[test]
+    fn test_fill_values_must_fit_in_a_word() {
+        let errors = assemble_with_diagnostics(
+            "fill.asm",
+            ".ORIG x3000\n.FILL #-40000\n.BLKW 2, #70000\n.FILL #-32768\n.BLKW 1 65535",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.span.line, error.message.as_ref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    1,
+                    "decimal literal is out of range: -40000 does not fit in 16 bits (-32768..65535)"
+                ),
+                (
+                    2,
+                    "decimal literal is out of range: 70000 does not fit in 16 bits (-32768..65535)"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_their_source() {
+        assert_eq!(
+            assemble("test.asm", ".ORIG x3000\nADD R0, R0, #1\n  .FILL R0"),
+            Err(String::from(
+                "error: expected a number
+ --> test.asm:3:9
+  |
+3 |   .FILL R0
+  |         ^^
+"
+            ))
+        );
+    }
 }
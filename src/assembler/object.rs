@@ -0,0 +1,96 @@
+use super::{Executable, Segment};
+
+/// render a segment in the lc3as .obj format: the origin followed by each word
+/// of the segment, all big-endian
+pub fn to_bytes(segment: &Segment) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity((segment.words.len() + 1) * 2);
+    bytes.extend(&segment.origin.to_be_bytes());
+    for word in &segment.words {
+        bytes.extend(&word.to_be_bytes());
+    }
+    bytes
+}
+
+/// read an executable back out of the lc3as .obj format
+pub fn from_bytes(bytes: &[u8]) -> Result<Executable, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(format!(
+            "object file has an odd number of bytes ({}), so it can't be made of 16 bit words",
+            bytes.len()
+        ));
+    }
+
+    let mut words = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    let origin = words
+        .next()
+        .ok_or_else(|| String::from("object file is empty, so it has no origin"))?;
+    Ok(Executable {
+        segments: vec![Segment {
+            origin,
+            words: words.collect(),
+        }],
+        entry: origin,
+        symbols: Default::default(),
+        debug_info: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(origin: u16, words: Vec<u16>) -> Segment {
+        Segment { origin, words }
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(
+            to_bytes(&segment(0x3000, vec![0x1021, 0xF025])),
+            vec![0x30, 0x00, 0x10, 0x21, 0xF0, 0x25]
+        );
+    }
+
+    #[test]
+    fn test_empty_to_bytes() {
+        assert_eq!(to_bytes(&segment(0x4000, vec![])), vec![0x40, 0x00]);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        assert_eq!(
+            from_bytes(&[0x30, 0x00, 0x10, 0x21, 0xF0, 0x25]),
+            Ok(Executable {
+                segments: vec![segment(0x3000, vec![0x1021, 0xF025])],
+                entry: 0x3000,
+                symbols: Default::default(),
+                debug_info: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let segment = segment(0x0200, vec![0, 0xFFFF, 0x00FF, 0xFF00]);
+        assert_eq!(
+            from_bytes(&to_bytes(&segment)).map(|executable| executable.segments),
+            Ok(vec![segment])
+        );
+    }
+
+    #[test]
+    fn test_from_bad_bytes() {
+        assert_eq!(
+            from_bytes(&[]),
+            Err(String::from("object file is empty, so it has no origin"))
+        );
+        assert_eq!(
+            from_bytes(&[0x30, 0x00, 0x10]),
+            Err(String::from(
+                "object file has an odd number of bytes (3), so it can't be made of 16 bit words"
+            ))
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use super::{object, Executable, Segment};
+use std::str::FromStr;
+
+/// the formats an executable can be written out in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// binary lc3as object file
+    Obj,
+    /// one word per line, like `x3001`
+    Hex,
+    /// one word per line, like `0011000000000001`
+    Bin,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Obj => "obj",
+            Format::Hex => "hex",
+            Format::Bin => "bin",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "obj" => Ok(Format::Obj),
+            "hex" => Ok(Format::Hex),
+            "bin" => Ok(Format::Bin),
+            _ => Err(format!(
+                "unknown format '{}', expected one of obj, hex or bin",
+                format
+            )),
+        }
+    }
+}
+
+/// render an executable in the given format. like .obj files, the text formats
+/// start with the origin, so they can only hold a single segment. programs
+/// with more than one have to be run from source instead
+pub fn write(executable: &Executable, format: Format) -> Result<Vec<u8>, String> {
+    let segment = match executable.segments.as_slice() {
+        [segment] => segment,
+        segments => {
+            let origins: Vec<String> = segments
+                .iter()
+                .map(|segment| format!("x{:04X}", segment.origin))
+                .collect();
+            return Err(format!(
+                "{} files hold a single .ORIG block, but the program has {}, at {}. \
+                 put each block in a file of its own, or run the source directly",
+                format.extension(),
+                segments.len(),
+                origins.join(", ")
+            ));
+        }
+    };
+
+    Ok(match format {
+        Format::Obj => object::to_bytes(segment),
+        Format::Hex => lines(segment, |word| format!("x{:04X}", word)),
+        Format::Bin => lines(segment, |word| format!("{:016b}", word)),
+    })
+}
+
+fn lines<F>(segment: &Segment, render: F) -> Vec<u8>
+where
+    F: Fn(u16) -> String,
+{
+    let mut text = String::new();
+    for word in std::iter::once(&segment.origin).chain(&segment.words) {
+        text.push_str(&render(*word));
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executable() -> Executable {
+        Executable {
+            segments: vec![Segment {
+                origin: 0x3000,
+                words: vec![0x3001, 0xF025],
+            }],
+            entry: 0x3000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(
+            String::from_utf8(write(&executable(), Format::Hex).unwrap()).unwrap(),
+            "x3000\nx3001\nxF025\n"
+        );
+    }
+
+    #[test]
+    fn test_bin() {
+        assert_eq!(
+            String::from_utf8(write(&executable(), Format::Bin).unwrap()).unwrap(),
+            "0011000000000000\n0011000000000001\n1111000000100101\n"
+        );
+    }
+
+    #[test]
+    fn test_obj() {
+        assert_eq!(
+            write(&executable(), Format::Obj),
+            Ok(object::to_bytes(&executable().segments[0]))
+        );
+    }
+
+    #[test]
+    fn test_multiple_segments() {
+        let mut executable = executable();
+        executable.segments.push(Segment {
+            origin: 0x4000,
+            words: vec![1],
+        });
+        for format in [Format::Obj, Format::Hex, Format::Bin] {
+            assert_eq!(
+                write(&executable, format),
+                Err(format!(
+                    "{} files hold a single .ORIG block, but the program has 2, at x3000, x4000. \
+                     put each block in a file of its own, or run the source directly",
+                    format.extension()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("hex".parse(), Ok(Format::Hex));
+        assert_eq!("bin".parse(), Ok(Format::Bin));
+        assert_eq!("obj".parse(), Ok(Format::Obj));
+        assert_eq!(
+            "elf".parse::<Format>(),
+            Err(String::from(
+                "unknown format 'elf', expected one of obj, hex or bin"
+            ))
+        );
+    }
+}
@@ -1,157 +1,1339 @@
+use crate::assembler::diagnostic::{Diagnostic, Label};
 use crate::assembler::lexer::{Token, TokenKind};
+use crate::assembler::{DebugInfo, Executable, Options, Segment, SourceLocation, Span};
+use crate::instructions::Instruction;
 
 use super::reader::Reader;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::iter::Extend;
 
 #[derive(Debug, PartialEq)]
 pub struct ParseError {
     pub message: String,
+    /// the token that caused the error
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
 }
 
 impl ParseError {
-    pub fn pretty(self) -> String {
-        String::from("")
+    fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label { span, message });
+        self
+    }
+
+    fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
     }
 }
 
-type Instruction = u16;
+/// an .IFDEF or .IFNDEF block that hasn't been closed yet
+struct Condition {
+    /// where the block was opened
+    span: Span,
+    /// whether the part of the block being parsed gets assembled
+    active: bool,
+    has_else: bool,
+}
 
-struct Parser {
-    reader: Reader<Token>,
-    labels: HashMap<String, usize>,
-    orig: Option<u16>,
-    instructions: Vec<Instruction>,
+struct Parser<'a> {
+    filename: String,
+    reader: Reader<'a, Token>,
+    labels: HashMap<String, u16>,
+    /// where each label is defined
+    label_spans: HashMap<String, Span>,
+    /// values defined with .EQU, which have to come before they're used
+    constants: HashMap<String, u16>,
+    /// where each constant is defined
+    constant_spans: HashMap<String, Span>,
+    /// constants that are defined before the program starts
+    defines: HashMap<String, u16>,
+    /// whether to insist on commas between operands and labels without colons,
+    /// rather than accepting the looser syntax lc3as does
+    strict: bool,
+    /// every conditional block the statement being parsed is inside of
+    conditions: Vec<Condition>,
+    /// address of the next word to be emitted
+    address: u16,
+    /// true while label addresses are still being collected
+    first_pass: bool,
+    /// one segment for every .ORIG seen so far
+    segments: Vec<Segment>,
+    /// where each emitted word came from
+    locations: BTreeMap<u16, SourceLocation>,
+    /// which emitted words are instructions
+    code: BTreeSet<u16>,
+    /// the first token of the statement being parsed
+    statement_span: Span,
+    /// where the macro the statement being parsed came from was used
+    statement_expansion: Option<Span>,
+    /// warnings about the program, collected on the second pass
+    warnings: Vec<Diagnostic>,
+    /// labels that something refers to, collected on the second pass
+    used_labels: HashSet<String>,
+    /// every label defined again after its first definition, along with where
+    /// the one it replaces was defined
+    redefined_labels: Vec<(String, Span, Span)>,
+    /// the first label used by the most recently parsed expression
+    expression_label: Option<String>,
+    /// every statement that failed on the second pass
+    errors: Vec<ParseError>,
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
+impl<'a> Parser<'a> {
+    fn new(filename: &str, tokens: &'a [Token]) -> Self {
         Parser {
-            reader: Reader::from(tokens, |t| t.kind == TokenKind::Newline),
+            filename: filename.to_string(),
+            reader: Reader::new(tokens, |t| t.kind == TokenKind::Newline),
             labels: HashMap::new(),
-            instructions: Vec::new(),
-            orig: None,
+            label_spans: HashMap::new(),
+            constants: HashMap::new(),
+            constant_spans: HashMap::new(),
+            defines: HashMap::new(),
+            strict: false,
+            conditions: Vec::new(),
+            segments: Vec::new(),
+            locations: BTreeMap::new(),
+            code: BTreeSet::new(),
+            statement_span: Span::default(),
+            statement_expansion: None,
+            address: 0,
+            first_pass: false,
+            warnings: Vec::new(),
+            used_labels: HashSet::new(),
+            redefined_labels: Vec::new(),
+            expression_label: None,
+            errors: Vec::new(),
         }
     }
 
-    fn parse(&mut self) -> Result<Vec<Instruction>, ParseError> {
+    fn parse(&mut self) -> Result<(Executable, Vec<Diagnostic>), Vec<ParseError>> {
         self.find_labels();
+        self.parse_statements();
 
-        while let Some(token) = self.reader.next() {
-            match token.kind {
-                TokenKind::Directive(directive) => {
-                    self.parse_directive(&directive)?;
-                    continue;
-                }
-                TokenKind::Symbol(_string) => continue,
-                TokenKind::Number(_num) => continue,
-                TokenKind::Comma => continue,
-                TokenKind::Str(_string) => continue,
-                TokenKind::Newline => continue,
+        let entry = match self.segments.first() {
+            Some(segment) => segment.origin,
+            None => {
+                let error = self.error(Span::default(), String::from("missing .ORIG directive"));
+                self.errors.push(error);
+                0
             }
+        };
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        self.warn_about_labels();
+
+        let executable = Executable {
+            segments: std::mem::take(&mut self.segments),
+            entry,
+            symbols: std::mem::take(&mut self.labels).into_iter().collect(),
+            debug_info: Some(DebugInfo {
+                filename: std::mem::take(&mut self.filename),
+                locations: std::mem::take(&mut self.locations),
+                code: std::mem::take(&mut self.code),
+                labels: std::mem::take(&mut self.label_spans).into_iter().collect(),
+            }),
+        };
+        Ok((executable, std::mem::take(&mut self.warnings)))
+    }
+
+    /// warn about labels that are defined twice or never used, once the whole
+    /// program has been parsed
+    fn warn_about_labels(&mut self) {
+        for (label, span, previous) in std::mem::take(&mut self.redefined_labels) {
+            self.warnings.push(Diagnostic {
+                labels: vec![Label {
+                    span: previous,
+                    message: format!("{} is first defined here", label),
+                }],
+                help: Some(String::from("uses of it refer to the last definition")),
+                ..Diagnostic::warning(format!("label {} is defined more than once", label), span)
+            });
         }
 
-        Ok(self.instructions.clone())
+        let mut unused: Vec<(&String, &Span)> = self
+            .label_spans
+            .iter()
+            // labels made by expanding a macro can't be used outside of it
+            .filter(|(label, _)| !self.used_labels.contains(*label) && !label.contains('@'))
+            .collect();
+        unused.sort_by_key(|(_, span)| (span.line, span.column));
+        for (label, span) in unused {
+            self.warnings.push(Diagnostic::warning(
+                format!("label {} is never used", label),
+                *span,
+            ));
+        }
     }
 
+    /// first pass: run through the whole program to assign every label the
+    /// address of the word that follows it, without resolving any operands
     fn find_labels(&mut self) {
+        self.constants = self.defines.clone();
+        self.first_pass = true;
+        self.parse_statements();
+        self.first_pass = false;
+
+        self.reader.reset();
+        self.constants = self.defines.clone();
+        self.constant_spans.clear();
+        self.segments.clear();
+        self.locations.clear();
+        self.code.clear();
+        self.address = 0;
+    }
+
+    /// parse every statement, skipping to the next line whenever one fails so
+    /// that the rest of the program still gets checked. errors are only
+    /// collected on the second pass, since the first pass hits the same ones
+    fn parse_statements(&mut self) {
         while let Some(token) = self.reader.next() {
-            if let TokenKind::Symbol(label) = token.kind {
-                // if a symbol is at position 0 in the line, it's a label
-                // rather than reference to a label
-                if self.reader.item_in_line == 0 {
-                    self.labels.insert(label, self.reader.line);
+            self.statement_span = token.span;
+            self.statement_expansion = token.expansion;
+
+            // conditional directives have to be followed even where nothing
+            // is being assembled, to know where that stops
+            if let Some(result) = self.parse_conditional(token) {
+                if let Err(error) = result {
+                    self.skip_line();
+                    if !self.first_pass {
+                        self.errors.push(error);
+                    }
+                }
+                continue;
+            }
+            if !self.conditions.iter().all(|condition| condition.active) {
+                if token.kind != TokenKind::Newline {
+                    self.skip_line();
+                }
+                continue;
+            }
+
+            let address = self.address;
+            let takes_one_word = match &token.kind {
+                TokenKind::Symbol(symbol) => is_mnemonic(symbol),
+                TokenKind::Directive(directive) => directive.eq_ignore_ascii_case("fill"),
+                _ => false,
+            };
+
+            if let Err(error) = self.parse_statement(token) {
+                // keep the addresses of everything after a bad instruction the
+                // same as if it had assembled, so its labels don't move
+                if takes_one_word && self.address == address && !self.segments.is_empty() {
+                    self.emit(0);
+                }
+                self.skip_line();
+                if !self.first_pass {
+                    self.errors.push(error);
                 }
             }
         }
-        self.reader.reset();
+        self.statement_expansion = None;
+
+        for condition in std::mem::take(&mut self.conditions) {
+            if !self.first_pass {
+                let error = self.error(
+                    condition.span,
+                    String::from("unterminated conditional block: expected .ENDIF"),
+                );
+                self.errors.push(error);
+            }
+        }
+    }
+
+    /// handle .IFDEF, .IFNDEF, .ELSE and .ENDIF, or nothing if the token is
+    /// something else
+    fn parse_conditional(&mut self, token: &Token) -> Option<Result<(), ParseError>> {
+        let directive = match &token.kind {
+            TokenKind::Directive(directive) => directive,
+            _ => return None,
+        };
+        let result = match directive.to_lowercase().as_ref() {
+            "ifdef" => self.parse_if(false, token.span),
+            "ifndef" => self.parse_if(true, token.span),
+            "else" => match self.conditions.last_mut() {
+                Some(condition) if condition.has_else => Err(self
+                    .error(token.span, String::from("this block already has an .ELSE"))
+                    .with_label(
+                        self.conditions.last().unwrap().span,
+                        String::from("the block starts here"),
+                    )),
+                Some(condition) => {
+                    condition.active = !condition.active;
+                    condition.has_else = true;
+                    Ok(())
+                }
+                None => Err(self.error(
+                    token.span,
+                    String::from(".ELSE without a matching .IFDEF or .IFNDEF"),
+                )),
+            },
+            "endif" => match self.conditions.pop() {
+                Some(_) => Ok(()),
+                None => Err(self.error(
+                    token.span,
+                    String::from(".ENDIF without a matching .IFDEF or .IFNDEF"),
+                )),
+            },
+            _ => return None,
+        };
+        Some(result.and_then(|_| self.expect_end_of_statement(&format!(".{}", directive))))
+    }
+
+    /// open a block that's only assembled if a constant is defined, or isn't
+    fn parse_if(&mut self, negate: bool, span: Span) -> Result<(), ParseError> {
+        let name = match self.next_operand()? {
+            Token {
+                kind: TokenKind::Symbol(name),
+                ..
+            } => Ok(name),
+            token => Err(self.error(token.span, String::from("expected the name of a constant"))),
+        };
+        // a block with a bad condition is skipped, but still has to be closed
+        let active = name
+            .as_ref()
+            .is_ok_and(|name| self.constants.contains_key(*name) != negate);
+        self.conditions.push(Condition {
+            span,
+            active,
+            has_else: false,
+        });
+        name.map(|_| ())
+    }
+
+    fn parse_statement(&mut self, token: &Token) -> Result<(), ParseError> {
+        match &token.kind {
+            TokenKind::Directive(directive) => {
+                self.parse_directive(directive)?;
+                self.expect_end_of_statement(&format!(".{}", directive))
+            }
+            TokenKind::Symbol(symbol) => {
+                if is_mnemonic(symbol) {
+                    self.expect_not_a_name(symbol, token.span)?;
+                    self.parse_instruction(symbol)?;
+                    self.expect_end_of_statement(symbol)
+                } else if looks_like_register(symbol) {
+                    Err(self.error(token.span, format!("unexpected register: {}", symbol)))
+                } else {
+                    self.parse_label(symbol, token.span)
+                }
+            }
+            TokenKind::Number(_)
+            | TokenKind::Comma
+            | TokenKind::Str(_)
+            | TokenKind::Operator(_)
+            | TokenKind::Colon => {
+                Err(self.error(token.span, format!("unexpected {}", describe(&token.kind))))
+            }
+            TokenKind::Newline => Ok(()),
+        }
+    }
+
+    /// parse a statement that starts with a label, or the name of a constant
+    fn parse_label(&mut self, symbol: &str, span: Span) -> Result<(), ParseError> {
+        // in strict mode, the label is still defined so its uses don't fail
+        let mut colon_error = None;
+        if let Some(&Token {
+            kind: TokenKind::Colon,
+            span: colon,
+            ..
+        }) = self.reader.peek()
+        {
+            self.reader.next();
+            if self.strict {
+                colon_error = Some(
+                    self.error(colon, format!("unexpected colon after {}", symbol))
+                        .with_help(String::from("labels don't end with a colon in strict mode")),
+                );
+            }
+        }
+
+        if self.peek_directive("equ") {
+            self.reader.next();
+            self.define_constant(symbol, span)?;
+            return match colon_error {
+                Some(error) => Err(error),
+                None => self.expect_end_of_statement(".EQU"),
+            };
+        }
+        // operands are consumed by the instruction they belong
+        // to, so any other symbol must be a label. it's defined
+        // even if what follows it is wrong, so that every use of
+        // it doesn't fail too
+        if self.first_pass {
+            self.labels.insert(symbol.to_string(), self.address);
+            if let Some(previous) = self.label_spans.insert(symbol.to_string(), span) {
+                self.redefined_labels
+                    .push((symbol.to_string(), span, previous));
+            }
+        }
+        match colon_error {
+            Some(error) => Err(error),
+            None => self.expect_statement_after_label(symbol),
+        }
+    }
+
+    /// an instruction's name followed by a colon or .EQU was meant to be the
+    /// name of a label or constant, which would otherwise be reported as
+    /// operands the instruction doesn't take
+    fn expect_not_a_name(&self, mnemonic: &str, span: Span) -> Result<(), ParseError> {
+        let after_colon = match self.reader.peek() {
+            Some(Token {
+                kind: TokenKind::Colon,
+                ..
+            }) => 1,
+            _ => 0,
+        };
+        let equ = matches!(
+            self.reader.peek_n(after_colon),
+            Some(Token {
+                kind: TokenKind::Directive(directive),
+                ..
+            }) if directive.eq_ignore_ascii_case("equ")
+        );
+        let name = match (after_colon, equ) {
+            (_, true) => "constant",
+            (1, false) => "label",
+            _ => return Ok(()),
+        };
+        Err(self
+            .error(
+                span,
+                format!(
+                    "{} is an instruction, so it can't be the name of a {}",
+                    mnemonic, name
+                ),
+            )
+            .with_help(format!("give the {} another name", name)))
+    }
+
+    /// move past whatever is left of the current line
+    fn skip_line(&mut self) {
+        // the failing statement might have already read the end of its line
+        let consumed_newline = self
+            .reader
+            .previous()
+            .is_some_and(|token| token.kind == TokenKind::Newline);
+        if !consumed_newline {
+            self.reader
+                .skip_while(|token| token.kind != TokenKind::Newline);
+        }
+    }
+
+    fn emit(&mut self, word: u16) {
+        // `expect_origin` makes sure there's a segment to emit into
+        self.segments.last_mut().unwrap().words.push(word);
+        // words from a macro belong to the line that used it
+        let location = self.statement_expansion.unwrap_or(self.statement_span);
+        self.locations.insert(self.address, location.start());
+        self.address = self.address.wrapping_add(1);
     }
 
     fn parse_directive(&mut self, directive: &str) -> Result<(), ParseError> {
         match directive.to_lowercase().as_ref() {
             "fill" => {
+                self.expect_origin(directive)?;
                 let num = self.expect_number()?;
-                self.instructions.push(num);
+                self.emit(num);
             }
             "stringz" => {
+                self.expect_origin(directive)?;
                 let string = self.expect_string()?;
+                self.warn_about_string_length(string);
 
                 let mut null_terminated_chars = Vec::new();
                 null_terminated_chars.extend(string.chars().map(|c| c as u16));
                 // null-terminate the string
                 null_terminated_chars.push(0);
 
-                self.instructions.extend(null_terminated_chars);
+                for c in null_terminated_chars {
+                    self.emit(c);
+                }
+            }
+            "stringp" => {
+                self.expect_origin(directive)?;
+                let string = self.expect_string()?;
+                self.warn_about_string_length(string);
+                let bytes = string
+                    .chars()
+                    .map(|c| u8::try_from(c as u32))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| {
+                        self.error(
+                            self.current_span(),
+                            String::from("packed strings can only hold characters up to xFF"),
+                        )
+                    })?;
+
+                // two characters to a word, the first in the low byte, which
+                // is the order PUTSP prints them in. the last character of an
+                // odd length string has a word to itself. a whole word of
+                // zeroes ends the string, since not every PUTSP stops at the
+                // first NUL byte
+                for pair in bytes.chunks(2) {
+                    let high = pair.get(1).copied().unwrap_or(0);
+                    self.emit(u16::from_le_bytes([pair[0], high]));
+                }
+                self.emit(0);
             }
             "blkw" => {
-                let num_reserved_slots = self.expect_number()?;
-                let reserved = vec![0; num_reserved_slots as usize];
-                self.instructions.extend(reserved);
+                self.expect_origin(directive)?;
+                let num_reserved_slots = self.expect_constant()?;
+                // the slots can be filled with something other than zero,
+                // given with or without a comma like `.BLKW 8 xFFFF`
+                let fill = match self.reader.peek() {
+                    None
+                    | Some(Token {
+                        kind: TokenKind::Newline,
+                        ..
+                    }) => 0,
+                    Some(Token {
+                        kind: TokenKind::Comma,
+                        ..
+                    }) => {
+                        self.reader.next();
+                        self.expect_number()?
+                    }
+                    Some(_) => self.expect_number()?,
+                };
+                for _ in 0..num_reserved_slots {
+                    self.emit(fill);
+                }
             }
             "orig" => {
-                let orig = self.expect_number()?;
-                self.orig = Some(orig);
+                let orig = self.expect_constant()?;
+                self.segments.push(Segment {
+                    origin: orig,
+                    ..Default::default()
+                });
+                self.address = orig;
+            }
+            "equ" => {
+                return Err(self
+                    .error(
+                        self.statement_span,
+                        String::from(".EQU needs a name for the constant"),
+                    )
+                    .with_help(String::from("write it like SIZE .EQU 10")))
             }
             "end" => {
-                // stop parsing by moving to end of reader
-                // TODO: fix this awful hack
-                self.reader.offset = std::usize::MAX;
+                let ignored = self
+                    .reader
+                    .rest()
+                    .iter()
+                    .any(|token| token.kind != TokenKind::Newline);
+                if ignored && !self.first_pass {
+                    self.warnings.push(Diagnostic::warning(
+                        String::from("everything after .END is ignored"),
+                        self.statement_span,
+                    ));
+                }
+                self.reader.finish();
             }
             _ => {
-                return Err(ParseError {
-                    message: format!("unrecognized directive: {}", directive),
-                })
+                let error = self.error(
+                    self.current_span(),
+                    format!("unrecognized directive: {}", directive),
+                );
+                return Err(match suggest(directive, DIRECTIVES) {
+                    Some(suggestion) => {
+                        error.with_help(format!("did you mean .{}?", suggestion.to_uppercase()))
+                    }
+                    None => error,
+                });
             }
         }
 
         Ok(())
     }
 
-    fn expect_number(&mut self) -> Result<u16, ParseError> {
-        match self.reader.next() {
+    fn warn_about_string_length(&mut self, string: &str) {
+        let len = string.chars().count();
+        if len > MAX_STRING_LEN && !self.first_pass {
+            self.warnings.push(Diagnostic::warning(
+                format!(
+                    "string is {} characters long, more than the {} most LC-3 tools allow",
+                    len, MAX_STRING_LEN
+                ),
+                self.current_span(),
+            ));
+        }
+    }
+
+    fn parse_instruction(&mut self, mnemonic: &str) -> Result<(), ParseError> {
+        self.expect_origin(mnemonic)?;
+        let instruction = match mnemonic.to_lowercase().as_ref() {
+            "add" | "and" => {
+                let dest = self.expect_register()?;
+                self.expect_comma()?;
+                let source = self.expect_register()?;
+                self.expect_comma()?;
+                // the last operand decides between the register and immediate forms
+                let immediate = match self.reader.peek() {
+                    Some(Token {
+                        kind: TokenKind::Symbol(symbol),
+                        ..
+                    }) if looks_like_register(symbol) => None,
+                    _ => Some(self.expect_immediate(mnemonic)?),
+                };
+                match (mnemonic.to_lowercase().as_ref(), immediate) {
+                    ("add", Some(value)) => Instruction::AddImmediate {
+                        dest,
+                        source,
+                        value,
+                    },
+                    ("add", None) => Instruction::Add {
+                        dest,
+                        source_1: source,
+                        source_2: self.expect_register()?,
+                    },
+                    (_, Some(value)) => Instruction::AndImmediate {
+                        dest,
+                        source,
+                        value,
+                    },
+                    (_, None) => Instruction::And {
+                        dest,
+                        source_1: source,
+                        source_2: self.expect_register()?,
+                    },
+                }
+            }
+            "nop" => Instruction::Br {
+                n: false,
+                z: false,
+                p: false,
+                pc_offset: 0,
+            },
+            "jmp" => Instruction::Jmp {
+                base: self.expect_register()?,
+            },
+            "jmpt" => Instruction::JmpT {
+                base: self.expect_register()?,
+            },
+            "jsr" => Instruction::Jsr {
+                pc_offset: self.expect_pc_offset(mnemonic, 11)?,
+            },
+            "jsrr" => Instruction::JsrR {
+                base: self.expect_register()?,
+            },
+            "ld" => {
+                let (dest, pc_offset) = self.expect_register_and_pc_offset(mnemonic)?;
+                Instruction::Ld { dest, pc_offset }
+            }
+            "ldi" => {
+                let (dest, pc_offset) = self.expect_register_and_pc_offset(mnemonic)?;
+                Instruction::LdI { dest, pc_offset }
+            }
+            "ldr" => {
+                let dest = self.expect_register()?;
+                self.expect_comma()?;
+                let (base, offset) = self.expect_register_and_offset(mnemonic)?;
+                Instruction::LdR { dest, base, offset }
+            }
+            "lea" => {
+                let (dest, pc_offset) = self.expect_register_and_pc_offset(mnemonic)?;
+                Instruction::Lea { dest, pc_offset }
+            }
+            "not" => {
+                let dest = self.expect_register()?;
+                self.expect_comma()?;
+                let source = self.expect_register()?;
+                Instruction::Not { dest, source }
+            }
+            "rti" => Instruction::Rti,
+            "st" => {
+                let (source, pc_offset) = self.expect_register_and_pc_offset(mnemonic)?;
+                Instruction::St { source, pc_offset }
+            }
+            "sti" => {
+                let (source, pc_offset) = self.expect_register_and_pc_offset(mnemonic)?;
+                Instruction::StI { source, pc_offset }
+            }
+            "str" => {
+                let source = self.expect_register()?;
+                self.expect_comma()?;
+                let (base, offset) = self.expect_register_and_offset(mnemonic)?;
+                Instruction::StR {
+                    source,
+                    base,
+                    offset,
+                }
+            }
+            "trap" => Instruction::Trap {
+                vec: self.expect_trap_vector(mnemonic)?,
+            },
+            "ret" => Instruction::Ret,
+            "getc" => Instruction::Trap { vec: 0x20 },
+            "out" => Instruction::Trap { vec: 0x21 },
+            "puts" => Instruction::Trap { vec: 0x22 },
+            "in" => Instruction::Trap { vec: 0x23 },
+            "putsp" => Instruction::Trap { vec: 0x24 },
+            "halt" => Instruction::Trap { vec: 0x25 },
+            lowercase => match parse_branch_condition(lowercase) {
+                Some((n, z, p)) => Instruction::Br {
+                    n,
+                    z,
+                    p,
+                    pc_offset: self.expect_pc_offset(mnemonic, 9)?,
+                },
+                None => {
+                    return Err(self.error(
+                        self.current_span(),
+                        format!("unrecognized instruction: {}", mnemonic),
+                    ))
+                }
+            },
+        };
+
+        self.code.insert(self.address);
+        self.emit(instruction.encode());
+        Ok(())
+    }
+
+    /// parse the `R1, LABEL` operand pair used by the PC-relative loads and stores
+    fn expect_register_and_pc_offset(&mut self, mnemonic: &str) -> Result<(u16, u16), ParseError> {
+        let register = self.expect_register()?;
+        self.expect_comma()?;
+        let pc_offset = self.expect_pc_offset(mnemonic, 9)?;
+        Ok((register, pc_offset))
+    }
+
+    /// parse the `R1, #OFFSET` operand pair used by LDR and STR
+    fn expect_register_and_offset(&mut self, mnemonic: &str) -> Result<(u16, u16), ParseError> {
+        let register = self.expect_register()?;
+        self.expect_comma()?;
+        let (offset, at) = self.expect_expression()?;
+        self.check_signed(offset, 6, &format!("{} offset", mnemonic), at)?;
+        self.warn_about_address(at);
+        Ok((register, offset))
+    }
+
+    /// parse the imm5 operand of ADD and AND
+    fn expect_immediate(&mut self, mnemonic: &str) -> Result<u16, ParseError> {
+        let (value, at) = self.expect_expression()?;
+        self.check_signed(value, 5, &format!("{} immediate", mnemonic), at)?;
+        self.warn_about_address(at);
+        Ok(value)
+    }
+
+    /// a label's address that happens to fit in an immediate was probably
+    /// meant to be used as an address, rather than as a number
+    fn warn_about_address(&mut self, at: Span) {
+        if let Some(label) = self.expression_label.take() {
+            if !self.first_pass {
+                self.warnings.push(Diagnostic {
+                    help: Some(format!(
+                        "use LEA to get the address of {}, or LD to load what's there",
+                        label
+                    )),
+                    ..Diagnostic::warning(
+                        format!("the address of label {} is used as a number", label),
+                        at,
+                    )
+                });
+            }
+        }
+    }
+
+    /// parse the unsigned trapvect8 operand of TRAP
+    fn expect_trap_vector(&mut self, mnemonic: &str) -> Result<u16, ParseError> {
+        let (vec, at) = self.expect_expression()?;
+        if vec > 0xFF {
+            // like other operands, a word with its top bit set was most likely
+            // written as a negative number
+            return Err(self.error(
+                at,
+                format!(
+                    "{} vector is out of range: {} does not fit in 8 bits (0..255)",
+                    mnemonic, vec as i16
+                ),
+            ));
+        }
+        Ok(vec)
+    }
+
+    /// parse an offset from the incremented PC, given either as a literal or
+    /// as an expression with a label in it, like `BUFFER+2`, which is resolved
+    /// against the address of the current instruction
+    fn expect_pc_offset(&mut self, mnemonic: &str, bits: u16) -> Result<u16, ParseError> {
+        // registers and punctuation can't start an expression
+        let starts_expression = match self.reader.peek() {
             Some(Token {
-                kind: TokenKind::Number(num),
+                kind: TokenKind::Symbol(symbol),
                 ..
-            }) => Ok(num),
-            Some(_) => Err(ParseError {
-                message: String::from("expected a number"),
-            }),
-            None => Err(ParseError {
-                message: String::from("unexpected end of input"),
+            }) => parse_register(symbol).is_none(),
+            Some(Token {
+                kind: TokenKind::Number(_) | TokenKind::Operator('-' | '('),
+                ..
+            }) => true,
+            _ => false,
+        };
+        if !starts_expression {
+            let token = self.next_operand()?;
+            return Err(self.error(token.span, String::from("expected a label or a number")));
+        }
+
+        let (value, at) = self.expect_expression()?;
+        let mut label_span = None;
+        let (offset, field) = match self.expression_label.take() {
+            Some(label) => {
+                label_span = self
+                    .label_spans
+                    .get(&label)
+                    .map(|span| (*span, format!("{} is defined here", label)));
+                (
+                    value.wrapping_sub(self.address.wrapping_add(1)),
+                    format!("{} offset to label {}", mnemonic, label),
+                )
+            }
+            None => (value, format!("{} offset", mnemonic)),
+        };
+        if self.first_pass {
+            // labels may be defined later in the file, so there will be
+            // nothing to resolve against until the second pass
+            return Ok(0);
+        }
+
+        self.check_signed(offset, bits, &field, at)
+            .map_err(|error| match label_span {
+                Some((span, message)) => error.with_label(span, message),
+                None => error,
+            })?;
+        Ok(offset)
+    }
+
+    fn expect_register(&mut self) -> Result<u16, ParseError> {
+        let token = self.next_operand()?;
+        let at = token.span;
+        match &token.kind {
+            TokenKind::Symbol(symbol) => parse_register(symbol).ok_or_else(|| {
+                if looks_like_register(symbol) {
+                    self.error(at, format!("invalid register: {} (expected R0-R7)", symbol))
+                } else {
+                    self.error(at, String::from("expected a register"))
+                }
             }),
+            _ => Err(self.error(at, String::from("expected a register"))),
+        }
+    }
+
+    /// expect the comma between two operands, which can be left out unless
+    /// parsing strictly
+    fn expect_comma(&mut self) -> Result<(), ParseError> {
+        if self.peek_comma() {
+            self.reader.next();
+            return Ok(());
+        }
+        // without a comma, the next operand still has to be on the same line
+        let another_operand = self
+            .reader
+            .peek()
+            .is_some_and(|token| token.kind != TokenKind::Newline);
+        if another_operand && !self.strict {
+            return Ok(());
         }
+        let token = self.next_operand()?;
+        Err(self.error(token.span, String::from("expected a comma")))
     }
 
-    fn expect_string(&mut self) -> Result<String, ParseError> {
-        match self.reader.next() {
+    fn peek_comma(&self) -> bool {
+        matches!(
+            self.reader.peek(),
             Some(Token {
-                kind: TokenKind::Str(string),
+                kind: TokenKind::Comma,
                 ..
-            }) => Ok(string),
-            Some(_) => Err(ParseError {
-                message: String::from("expected a string literal"),
-            }),
-            None => Err(ParseError {
-                message: String::from("unexpected end of input"),
-            }),
+            })
+        )
+    }
+
+    fn expect_number(&mut self) -> Result<u16, ParseError> {
+        self.expect_expression().map(|(value, _)| value)
+    }
+
+    /// parse an expression that can't use labels, because it decides where
+    /// things go and so has to be known on the first pass
+    fn expect_constant(&mut self) -> Result<u16, ParseError> {
+        self.expect_sum(false)
+    }
+
+    /// parse an expression made of numbers, constants, labels and
+    /// `+ - * / ( )`, along with the span it covers. arithmetic wraps around
+    /// like the LC-3's does, and division is signed
+    fn expect_expression(&mut self) -> Result<(u16, Span), ParseError> {
+        self.expression_label = None;
+        let start = self
+            .reader
+            .peek()
+            .map_or(Span::default(), |token| token.span);
+        let value = self.expect_sum(true)?;
+        let end = self.current_span();
+        let span = if start.line == end.line {
+            Span {
+                len: end.column + end.len - start.column,
+                ..start
+            }
+        } else {
+            start
+        };
+        Ok((value, span))
+    }
+
+    fn expect_sum(&mut self, labels: bool) -> Result<u16, ParseError> {
+        let mut value = self.expect_product(labels)?;
+        while !self.peek_negative_operand() {
+            let Some(operator) = self.next_operator("+-") else {
+                break;
+            };
+            let rhs = self.expect_product(labels)?;
+            value = match operator {
+                '+' => value.wrapping_add(rhs),
+                _ => value.wrapping_sub(rhs),
+            };
+        }
+        Ok(value)
+    }
+
+    /// whether the next token is a `-` that starts another operand, rather than
+    /// subtracting from this one. without commas, `.BLKW 2 -1` is two operands
+    /// because the `-` has a space before it and a number right after it
+    fn peek_negative_operand(&self) -> bool {
+        if self.strict {
+            return false;
+        }
+        let rest = self.reader.rest();
+        let (Some(previous), [minus, number, ..]) = (self.reader.previous(), rest) else {
+            return false;
+        };
+        minus.kind == TokenKind::Operator('-')
+            && matches!(number.kind, TokenKind::Number(_))
+            && previous.span.line == minus.span.line
+            && previous.span.column + previous.span.len < minus.span.column
+            && minus.span.line == number.span.line
+            && minus.span.column + minus.span.len == number.span.column
+    }
+
+    fn expect_product(&mut self, labels: bool) -> Result<u16, ParseError> {
+        let mut value = self.expect_factor(labels)?;
+        while let Some(operator) = self.next_operator("*/") {
+            let rhs = self.expect_factor(labels)?;
+            value = match operator {
+                '*' => value.wrapping_mul(rhs),
+                _ if rhs == 0 => {
+                    return Err(self.error(self.current_span(), String::from("division by zero")))
+                }
+                _ => (value as i16).wrapping_div(rhs as i16) as u16,
+            };
+        }
+        Ok(value)
+    }
+
+    fn expect_factor(&mut self, labels: bool) -> Result<u16, ParseError> {
+        let token = self.next_operand()?;
+        let at = token.span;
+        match &token.kind {
+            TokenKind::Number(num) => Ok(*num),
+            TokenKind::Operator('-') => Ok(self.expect_factor(labels)?.wrapping_neg()),
+            TokenKind::Operator('(') => {
+                let value = self.expect_sum(labels)?;
+                match self.next_operator(")") {
+                    Some(_) => Ok(value),
+                    None => Err(self.error(self.current_span(), String::from("expected a ')'"))),
+                }
+            }
+            TokenKind::Symbol(symbol) if parse_register(symbol).is_none() => {
+                if let Some(value) = self.constants.get(symbol) {
+                    return Ok(*value);
+                }
+                if !labels {
+                    return Err(match self.label_spans.get(symbol) {
+                        Some(span) => self
+                            .error(
+                                at,
+                                format!("{} is a label, but this needs a constant", symbol),
+                            )
+                            .with_label(*span, format!("{} is defined here", symbol)),
+                        None => self.error(at, format!("undefined constant: {}", symbol)),
+                    });
+                }
+                match self.labels.get(symbol) {
+                    Some(address) => {
+                        let address = *address;
+                        if !self.first_pass {
+                            self.used_labels.insert(symbol.clone());
+                        }
+                        self.expression_label.get_or_insert(symbol.clone());
+                        Ok(address)
+                    }
+                    // labels may be defined later in the file
+                    None if self.first_pass => Ok(0),
+                    None => Err(self.error(at, format!("undefined label: {}", symbol))),
+                }
+            }
+            _ => Err(self.error(at, String::from("expected a number"))),
+        }
+    }
+
+    /// move past the next token if it's one of `operators`
+    fn next_operator(&mut self, operators: &str) -> Option<char> {
+        match self.reader.peek() {
+            Some(&Token {
+                kind: TokenKind::Operator(operator),
+                ..
+            }) if operators.contains(operator) => {
+                self.reader.next();
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    fn peek_directive(&self, name: &str) -> bool {
+        matches!(
+            self.reader.peek(),
+            Some(Token {
+                kind: TokenKind::Directive(directive),
+                ..
+            }) if directive.eq_ignore_ascii_case(name)
+        )
+    }
+
+    /// give a name to the value of the expression after a .EQU
+    fn define_constant(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        let value = self.expect_constant()?;
+        if let Some(previous) = self.constant_spans.get(name) {
+            return Err(self
+                .error(span, format!("constant {} is already defined", name))
+                .with_label(*previous, format!("{} is first defined here", name)));
+        }
+        if let Some(label) = self.label_spans.get(name) {
+            return Err(self
+                .error(span, format!("{} is already a label", name))
+                .with_label(*label, format!("{} is defined here", name)));
+        }
+        self.constants.insert(name.to_string(), value);
+        self.constant_spans.insert(name.to_string(), span);
+        Ok(())
+    }
+
+    fn expect_string(&mut self) -> Result<&'a str, ParseError> {
+        let token = self.next_operand()?;
+        match &token.kind {
+            TokenKind::Str(string) => Ok(string),
+            _ => Err(self.error(token.span, String::from("expected a string literal"))),
+        }
+    }
+
+    fn next_operand(&mut self) -> Result<&'a Token, ParseError> {
+        self.reader.next().ok_or_else(|| {
+            // point just past the last thing in the file
+            let last = self.current_span();
+            let span = Span {
+                column: last.column + last.len,
+                len: 1,
+                ..last
+            };
+            self.error(span, String::from("unexpected end of input"))
+        })
+    }
+
+    /// anything that takes up memory needs to know where it's going to be put
+    fn expect_origin(&mut self, statement: &str) -> Result<(), ParseError> {
+        if self.segments.is_empty() {
+            // carry on as if the program started at x0000, rather than failing
+            // on every statement until the first .ORIG
+            self.segments.push(Segment::default());
+            return Err(self.error(
+                self.current_span(),
+                format!("{} must come after .ORIG", statement),
+            ));
+        }
+        Ok(())
+    }
+
+    /// make sure nothing but a newline follows the operands of a statement.
+    /// a trailing comma is fine, unless parsing strictly
+    fn expect_end_of_statement(&mut self, statement: &str) -> Result<(), ParseError> {
+        let trailing_comma = self.peek_comma()
+            && self
+                .reader
+                .peek_n(1)
+                .is_none_or(|token| token.kind == TokenKind::Newline);
+        if trailing_comma && !self.strict {
+            self.reader.next();
+        }
+        match self.reader.peek() {
+            None
+            | Some(Token {
+                kind: TokenKind::Newline,
+                ..
+            }) => Ok(()),
+            Some(token) => {
+                Err(self.error(token.span, format!("too many operands for {}", statement)))
+            }
+        }
+    }
+
+    /// a label can only be followed by an instruction, a directive, or the end of the line
+    fn expect_statement_after_label(&self, label: &str) -> Result<(), ParseError> {
+        match self.reader.peek() {
+            Some(Token {
+                kind: TokenKind::Symbol(symbol),
+                span,
+                ..
+            }) if !is_mnemonic(symbol) => {
+                let error = self.error(
+                    *span,
+                    format!(
+                        "expected an instruction or directive after label {}, found {}",
+                        label, symbol
+                    ),
+                );
+                // a misspelled instruction looks just like a label
+                Err(match suggest(label, MNEMONICS) {
+                    Some(suggestion) => error
+                        .with_label(
+                            self.statement_span,
+                            format!("{} is taken to be a label", label),
+                        )
+                        .with_help(format!("did you mean {}?", suggestion.to_uppercase())),
+                    None => error,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// the span of the most recently read token
+    fn current_span(&self) -> Span {
+        self.reader
+            .previous()
+            .map_or(Span::default(), |token| token.span)
+    }
+
+    /// check that a two's complement value fits in a signed field of `bits` bits,
+    /// rather than silently dropping the high bits when it gets encoded
+    fn check_signed(&self, value: u16, bits: u16, field: &str, at: Span) -> Result<(), ParseError> {
+        let min = -(1 << (bits - 1));
+        let max = (1 << (bits - 1)) - 1;
+        let value = value as i16 as i32;
+        if value < min || value > max {
+            return Err(self.error(
+                at,
+                format!(
+                    "{} is out of range: {} does not fit in {} bits ({}..{})",
+                    field, value, bits, min, max
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn error(&self, span: Span, message: String) -> ParseError {
+        let error = ParseError {
+            message,
+            span,
+            labels: Vec::new(),
+            help: None,
+        };
+        match self.statement_expansion {
+            Some(site) => error.with_label(site, String::from("in this macro expansion")),
+            None => error,
+        }
+    }
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Directive(directive) => format!("directive .{}", directive),
+        TokenKind::Symbol(symbol) => format!("symbol {}", symbol),
+        TokenKind::Number(num) => format!("number {}", num),
+        TokenKind::Comma => String::from("comma"),
+        TokenKind::Str(string) => format!("string \"{}\"", string),
+        TokenKind::Operator(operator) => format!("operator {}", operator),
+        TokenKind::Colon => String::from("colon"),
+        TokenKind::Newline => String::from("newline"),
+    }
+}
+
+/// the longest string lc3as and friends will assemble
+const MAX_STRING_LEN: usize = 255;
+
+const DIRECTIVES: &[&str] = &[
+    "orig",
+    "end",
+    "fill",
+    "blkw",
+    "stringz",
+    "stringp",
+    "macro",
+    "end_macro",
+    "equ",
+    "ifdef",
+    "ifndef",
+    "else",
+    "endif",
+];
+
+/// every mnemonic other than the conditional branches
+const MNEMONICS: &[&str] = &[
+    "add", "and", "br", "jmp", "jmpt", "jsr", "jsrr", "ld", "ldi", "ldr", "lea", "nop", "not",
+    "rti", "st", "sti", "str", "trap", "ret", "getc", "out", "puts", "in", "putsp", "halt",
+];
+
+pub(super) fn is_mnemonic(symbol: &str) -> bool {
+    let lowercase = symbol.to_lowercase();
+    parse_branch_condition(&lowercase).is_some() || MNEMONICS.contains(&lowercase.as_ref())
+}
+
+/// the candidate closest to a misspelled word, if any is close enough that it
+/// was probably what was meant
+fn suggest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    if word.len() < 2 {
+        return None;
+    }
+    let max_distance = if word.len() > 4 { 2 } else { 1 };
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&word, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// how many single character insertions, deletions, substitutions or swaps of
+/// neighbouring characters it takes to turn one word into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i chars of a and the
+    // first j chars of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
         }
     }
+    distances[a.len()][b.len()]
+}
+
+/// parse the n, z and p flags out of a lowercase `br` mnemonic. a bare `br` is
+/// unconditional, like `brnzp`
+fn parse_branch_condition(mnemonic: &str) -> Option<(bool, bool, bool)> {
+    match mnemonic {
+        "br" | "brnzp" => Some((true, true, true)),
+        "brn" => Some((true, false, false)),
+        "brz" => Some((false, true, false)),
+        "brp" => Some((false, false, true)),
+        "brnz" => Some((true, true, false)),
+        "brnp" => Some((true, false, true)),
+        "brzp" => Some((false, true, true)),
+        _ => None,
+    }
+}
+
+/// symbols like `R8` are almost certainly meant to be registers, rather than labels
+fn looks_like_register(symbol: &str) -> bool {
+    let mut chars = symbol.chars();
+    matches!(chars.next(), Some('r') | Some('R'))
+        && !symbol[1..].is_empty()
+        && symbol[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// parse a register name like `R3` or `r3` into its number
+fn parse_register(symbol: &str) -> Option<u16> {
+    match symbol.to_lowercase().as_ref() {
+        "r0" => Some(0),
+        "r1" => Some(1),
+        "r2" => Some(2),
+        "r3" => Some(3),
+        "r4" => Some(4),
+        "r5" => Some(5),
+        "r6" => Some(6),
+        "r7" => Some(7),
+        _ => None,
+    }
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<u16>, ParseError> {
-    Parser::new(tokens).parse()
+pub fn parse(
+    filename: &str,
+    tokens: Vec<Token>,
+    options: &Options,
+) -> Result<(Executable, Vec<Diagnostic>), Vec<ParseError>> {
+    let mut parser = Parser::new(filename, &tokens);
+    parser.defines = options.defines.clone().into_iter().collect();
+    parser.labels = options.symbols.clone().into_iter().collect();
+    parser.strict = options.strict;
+    parser.parse()
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
 
+    /// parse a program into its words, assembling it at x3000 unless it sets
+    /// its own origin. only the first error is kept
+    fn parse_words(tokens: Vec<Token>) -> Result<Vec<u16>, ParseError> {
+        parse_words_with(tokens, &Options::default())
+    }
+
+    fn parse_words_with(tokens: Vec<Token>, options: &Options) -> Result<Vec<u16>, ParseError> {
+        let has_origin = matches!(
+            tokens.first(),
+            Some(Token {
+                kind: TokenKind::Directive(directive),
+                ..
+            }) if directive.eq_ignore_ascii_case("orig")
+        );
+        let mut program = Vec::new();
+        if !has_origin {
+            program.push(Token::directive("orig"));
+            program.push(Token::number(0x3000));
+            program.push(Token::newline());
+        }
+        program.extend(tokens);
+        parse("test.asm", program, options)
+            .map(|(executable, _)| {
+                executable
+                    .segments
+                    .into_iter()
+                    .flat_map(|segment| segment.words)
+                    .collect()
+            })
+            .map_err(|mut errors| errors.remove(0))
+    }
+
     #[test]
     fn test_bad_directive() {
         assert_eq!(
-            parse(vec![Token::directive(".bad", 0)]),
+            parse_words(vec![Token::directive(".bad")]),
             Err(ParseError {
-                message: String::from("unrecognized directive: .bad")
+                message: String::from("unrecognized directive: .bad"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
             }),
         );
     }
@@ -159,7 +1341,7 @@ mod tests {
     #[test]
     fn fill_with_number() {
         assert_eq!(
-            parse(vec![Token::directive("fill", 0), Token::number(10, 0)]),
+            parse_words(vec![Token::directive("fill"), Token::number(10)]),
             Ok(vec![10])
         );
     }
@@ -167,9 +1349,16 @@ mod tests {
     #[test]
     fn fill_without_literal() {
         assert_eq!(
-            parse(vec![Token::directive("fill", 0), Token::comma(0)]),
+            parse_words(vec![Token::directive("fill"), Token::comma()]),
             Err(ParseError {
-                message: String::from("expected a number")
+                message: String::from("expected a number"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
             })
         )
     }
@@ -177,9 +1366,16 @@ mod tests {
     #[test]
     fn fill_without_next_token() {
         assert_eq!(
-            parse(vec![Token::directive("fill", 0)]),
+            parse_words(vec![Token::directive("fill")]),
             Err(ParseError {
                 message: String::from("unexpected end of input"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
             })
         )
     }
@@ -187,13 +1383,13 @@ mod tests {
     #[test]
     fn stringz_with_string_literal() {
         assert_eq!(
-            parse(vec![Token::directive("stringz", 0), Token::str("a", 0)]),
+            parse_words(vec![Token::directive("stringz"), Token::str("a")]),
             Ok(vec![97, 0])
         );
         assert_eq!(
-            parse(vec![
-                Token::directive("stringz", 0),
-                Token::str("hello, world!", 0)
+            parse_words(vec![
+                Token::directive("stringz"),
+                Token::str("hello, world!")
             ]),
             Ok(vec![
                 104, 101, 108, 108, 111, 44, 32, 119, 111, 114, 108, 100, 33, 0
@@ -204,9 +1400,16 @@ mod tests {
     #[test]
     fn stringz_without_string_literal() {
         assert_eq!(
-            parse(vec![Token::directive("stringz", 0), Token::number(10, 0)]),
+            parse_words(vec![Token::directive("stringz"), Token::number(10)]),
             Err(ParseError {
-                message: String::from("expected a string literal")
+                message: String::from("expected a string literal"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
             })
         )
     }
@@ -214,39 +1417,1209 @@ mod tests {
     #[test]
     fn stringz_without_next_token() {
         assert_eq!(
-            parse(vec![Token::directive("stringz", 0)]),
+            parse_words(vec![Token::directive("stringz")]),
             Err(ParseError {
-                message: String::from("unexpected end of input")
+                message: String::from("unexpected end of input"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
             })
         )
     }
 
+    #[test]
+    fn stringp_packs_two_characters_per_word() {
+        assert_eq!(
+            parse_words(vec![Token::directive("stringp"), Token::str("hi!")]),
+            Ok(vec![0x6968, 0x0021, 0])
+        );
+        assert_eq!(
+            parse_words(vec![Token::directive("STRINGP"), Token::str("ok")]),
+            Ok(vec![0x6B6F, 0])
+        );
+        assert_eq!(
+            parse_words(vec![Token::directive("stringp"), Token::str("")]),
+            Ok(vec![0])
+        );
+        assert_eq!(
+            parse_words(vec![Token::directive("stringp"), Token::str("é")]),
+            Ok(vec![0x00E9, 0])
+        );
+        assert_eq!(
+            parse_words(vec![Token::directive("stringp"), Token::str("→")])
+                .map_err(|error| error.message),
+            Err(String::from(
+                "packed strings can only hold characters up to xFF"
+            ))
+        );
+        assert_eq!(
+            parse_words(vec![Token::directive("stringp"), Token::number(10)])
+                .map_err(|error| error.message),
+            Err(String::from("expected a string literal"))
+        );
+    }
+
     #[test]
     fn orig() {
-        let mut parser = Parser::new(vec![Token::directive("orig", 0), Token::number(0x3000, 0)]);
-        assert_eq!(parser.parse(), Ok(vec![]));
-        assert_eq!(parser.orig, Some(0x3000));
+        let tokens = [Token::directive("orig"), Token::number(0x3000)];
+        let mut parser = Parser::new("test.asm", &tokens);
+        assert_eq!(
+            parser.parse(),
+            Ok((
+                Executable {
+                    segments: vec![Segment {
+                        origin: 0x3000,
+                        words: vec![],
+                    }],
+                    entry: 0x3000,
+                    symbols: Default::default(),
+                    debug_info: Some(DebugInfo {
+                        filename: String::from("test.asm"),
+                        locations: BTreeMap::new(),
+                        code: BTreeSet::new(),
+                        labels: BTreeMap::new(),
+                    }),
+                },
+                vec![]
+            ))
+        );
     }
 
     #[test]
     fn stop_parsing_after_end() {
         assert_eq!(
-            parse(vec![
-                Token::directive("fill", 0),
-                Token::number(0, 0),
-                Token::directive("end", 0),
-                Token::directive("stringz", 0),
-                Token::str("hey", 0),
+            parse_words(vec![
+                Token::directive("fill"),
+                Token::number(0),
+                Token::newline(),
+                Token::directive("end"),
+                Token::newline(),
+                Token::directive("stringz"),
+                Token::str("hey"),
             ]),
             Ok(vec![0])
         );
     }
 
     #[test]
-    fn blkw() {
-        assert_eq!(
-            parse(vec![Token::directive("blkw", 0), Token::number(10, 0),]),
+    fn warn_about_code_after_end() {
+        let (_, warnings) = parse(
+            "test.asm",
+            crate::assembler::lexer::lex(".ORIG x3000\nHALT\n  .END\n").unwrap(),
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(warnings, vec![]);
+
+        let (_, warnings) = parse(
+            "test.asm",
+            crate::assembler::lexer::lex(".ORIG x3000\nHALT\n  .END\nHALT").unwrap(),
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            warnings,
+            vec![Diagnostic::warning(
+                String::from("everything after .END is ignored"),
+                Span {
+                    line: 2,
+                    column: 2,
+                    len: 4
+                }
+            )]
+        );
+    }
+
+    /// every warning message from a program that assembles, in order
+    fn parse_warnings(source: &str) -> Vec<String> {
+        let tokens = crate::assembler::lexer::lex(source).unwrap();
+        let (_, warnings) = parse("test.asm", tokens, &Options::default()).unwrap();
+        warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn warnings() {
+        assert_eq!(
+            parse_warnings(".ORIG x3000\nloop BR loop\nunused HALT\ndata .FILL loop"),
+            vec!["label unused is never used", "label data is never used"]
+        );
+        assert_eq!(
+            parse_warnings(".ORIG x3000\nx HALT\nx HALT\nBR x"),
+            vec!["label x is defined more than once"]
+        );
+        assert_eq!(
+            parse_warnings(".ORIG x0\nADD R0, R0, small\nLDR R0, R0, small\nsmall HALT"),
+            vec![
+                "the address of label small is used as a number",
+                "the address of label small is used as a number"
+            ]
+        );
+        assert_eq!(
+            parse_warnings(&format!(
+                ".ORIG x3000\n.STRINGZ \"{}\"\n.STRINGZ \"{}\"",
+                "a".repeat(255),
+                "a".repeat(256)
+            )),
+            vec!["string is 256 characters long, more than the 255 most LC-3 tools allow"]
+        );
+    }
+
+    #[test]
+    fn binary_operands() {
+        assert_eq!(
+            parse_source(".FILL b10000000\nADD R0, R0, b111\nAND R1, R1, 0b1"),
+            Ok(vec![
+                0b1000_0000,
+                0b0001_000_000_1_00111,
+                0b0101_001_001_1_00001
+            ])
+        );
+    }
+
+    #[test]
+    fn blkw() {
+        assert_eq!(
+            parse_words(vec![Token::directive("blkw"), Token::number(10),]),
             Ok(vec![0; 10])
         );
     }
+
+    #[test]
+    fn add_registers() {
+        assert_eq!(
+            parse_words(vec![
+                Token::symbol("ADD"),
+                Token::symbol("R1"),
+                Token::comma(),
+                Token::symbol("R2"),
+                Token::comma(),
+                Token::symbol("R3"),
+            ]),
+            Ok(vec![0b0001_0010_1000_0011])
+        );
+    }
+
+    #[test]
+    fn and_immediate() {
+        assert_eq!(
+            parse_words(vec![
+                Token::symbol("and"),
+                Token::symbol("r0"),
+                Token::comma(),
+                Token::symbol("r0"),
+                Token::comma(),
+                Token::number(0),
+            ]),
+            Ok(vec![0b0101_0000_0010_0000])
+        );
+    }
+
+    #[test]
+    fn load_store() {
+        assert_eq!(
+            parse_words(vec![
+                Token::symbol("LD"),
+                Token::symbol("R0"),
+                Token::comma(),
+                Token::number(2),
+                Token::newline(),
+                Token::symbol("STR"),
+                Token::symbol("R1"),
+                Token::comma(),
+                Token::symbol("R6"),
+                Token::comma(),
+                Token::number(0xFFFF),
+            ]),
+            Ok(vec![0b0010_0000_0000_0010, 0b0111_0011_1011_1111])
+        );
+    }
+
+    #[test]
+    fn trap_and_jumps() {
+        assert_eq!(
+            parse_words(vec![
+                Token::symbol("TRAP"),
+                Token::number(0x25),
+                Token::newline(),
+                Token::symbol("JMP"),
+                Token::symbol("R7"),
+                Token::newline(),
+                Token::symbol("JSRR"),
+                Token::symbol("R3"),
+                Token::newline(),
+                Token::symbol("NOT"),
+                Token::symbol("R1"),
+                Token::comma(),
+                Token::symbol("R2"),
+            ]),
+            Ok(vec![0xF025, 0xC1C0, 0x40C0, 0b1001_0010_1011_1111])
+        );
+    }
+
+    #[test]
+    fn instruction_without_register() {
+        assert_eq!(
+            parse_words(vec![
+                Token::symbol("NOT"),
+                Token::number(1),
+                Token::comma(),
+                Token::symbol("R2"),
+            ]),
+            Err(ParseError {
+                message: String::from("expected a register"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn instruction_without_comma() {
+        let tokens = vec![Token::symbol("LEA"), Token::symbol("R1"), Token::number(1)];
+        assert_eq!(parse_words(tokens.clone()), Ok(vec![0xE201]));
+        let strict = Options {
+            strict: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            parse_words_with(tokens, &strict),
+            Err(ParseError {
+                message: String::from("expected a comma"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    /// lex and parse a program, adding a line to assemble it at x3000 unless
+    /// it sets its own origin
+    fn parse_source(source: &str) -> Result<Vec<u16>, ParseError> {
+        let source = if source.trim_start().to_lowercase().starts_with(".orig") {
+            source.to_string()
+        } else {
+            format!(".ORIG x3000\n{}", source)
+        };
+        parse_words(crate::assembler::lexer::lex(&source).unwrap())
+    }
+
+    #[test]
+    fn labels_resolve_to_pc_offsets() {
+        assert_eq!(
+            parse_source(
+                ".orig x3000
+                 loop ADD R1, R1, #-1
+                      BR loop
+                      LD R0, data
+                      JSR loop
+                 data .FILL x2A"
+            ),
+            Ok(vec![0x127F, 0x0FFE, 0x2001, 0x4FFC, 0x2A])
+        );
+    }
+
+    #[test]
+    fn pc_offsets_can_be_expressions() {
+        assert_eq!(
+            parse_source(
+                "LD R0, buf+1
+                 LEA R1, buf + 1
+                 BRz buf-1
+                 BRnzp 1-2
+            buf  .BLKW 2"
+            ),
+            Ok(vec![0x2004, 0xE203, 0x0400, 0x0FFF, 0, 0])
+        );
+        assert_eq!(
+            parse_source("LD R0, far+300\nfar .FILL 0").map_err(|error| error.message),
+            Err(String::from(
+                "LD offset to label far is out of range: 300 does not fit in 9 bits (-256..255)"
+            ))
+        );
+        assert_eq!(
+            parse_source("LD R0, R1").map_err(|error| error.message),
+            Err(String::from("expected a label or a number"))
+        );
+    }
+
+    #[test]
+    fn negative_zero() {
+        assert_eq!(
+            parse_source("ADD R0, R0, -0\n.FILL #-0\n.FILL -0"),
+            Ok(vec![0x1020, 0, 0])
+        );
+    }
+
+    #[test]
+    fn label_addresses_account_for_directives() {
+        let tokens = crate::assembler::lexer::lex(
+            ".orig x3000
+             msg .STRINGZ \"hi\"
+             buf .BLKW 4
+             end LEA R0, msg",
+        )
+        .unwrap();
+        let (executable, _) = Parser::new("test.asm", &tokens).parse().unwrap();
+        assert_eq!(
+            executable.segments[0].words,
+            vec![104, 105, 0, 0, 0, 0, 0, 0xE1F8]
+        );
+        assert_eq!(executable.symbols.get("msg"), Some(&0x3000));
+        assert_eq!(executable.symbols.get("buf"), Some(&0x3003));
+        assert_eq!(executable.symbols.get("end"), Some(&0x3007));
+        assert_eq!(
+            executable
+                .debug_info
+                .unwrap()
+                .locations
+                .values()
+                .map(|location| location.line)
+                .collect::<Vec<_>>(),
+            vec![1, 1, 1, 2, 2, 2, 2, 3]
+        );
+    }
+
+    #[test]
+    fn undefined_label() {
+        assert_eq!(
+            parse_source("LEA R0, nowhere"),
+            Err(ParseError {
+                message: String::from("undefined label: nowhere"),
+                span: Span {
+                    line: 1,
+                    column: 8,
+                    len: 7,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn label_out_of_range() {
+        assert_eq!(
+            parse_source("LD R0, far\n.BLKW 256\nfar .FILL 0"),
+            Err(ParseError {
+                message: String::from("LD offset to label far is out of range: 256 does not fit in 9 bits (-256..255)"),
+                span: Span {
+                    line: 1,
+                    column: 7,
+                    len: 3,
+                },
+                labels: vec![Label {
+                    span: Span {
+                        line: 3,
+                        column: 0,
+                        len: 3,
+                    },
+                    message: String::from("far is defined here"),
+                }],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn suggest_misspelled_directives_and_instructions() {
+        let help = |source| parse_source(source).unwrap_err().help;
+        assert_eq!(
+            help(".STRING \"hi\""),
+            Some(String::from("did you mean .STRINGZ?"))
+        );
+        assert_eq!(help(".BLOCK 2"), None);
+        assert_eq!(
+            help("ADDD R0, R0, R1"),
+            Some(String::from("did you mean ADD?"))
+        );
+        assert_eq!(help("loop again"), None);
+
+        assert_eq!(
+            parse_source("HLAT R0").unwrap_err().labels,
+            vec![Label {
+                span: Span {
+                    line: 1,
+                    column: 0,
+                    len: 4,
+                },
+                message: String::from("HLAT is taken to be a label"),
+            }]
+        );
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("add", "add"), 0);
+        assert_eq!(edit_distance("addd", "add"), 1);
+        assert_eq!(edit_distance("hlat", "halt"), 1);
+        assert_eq!(edit_distance("loop", "nop"), 2);
+        assert_eq!(edit_distance("", "br"), 2);
+    }
+
+    #[test]
+    fn base_offset_out_of_range() {
+        assert_eq!(
+            parse_source("LDR R0, R1, #32"),
+            Err(ParseError {
+                message: String::from(
+                    "LDR offset is out of range: 32 does not fit in 6 bits (-32..31)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 12,
+                    len: 3,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_register() {
+        assert_eq!(
+            parse_source("ADD R1, R8, R2"),
+            Err(ParseError {
+                message: String::from("invalid register: R8 (expected R0-R7)"),
+                span: Span {
+                    line: 1,
+                    column: 8,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("JMP r9"),
+            Err(ParseError {
+                message: String::from("invalid register: r9 (expected R0-R7)"),
+                span: Span {
+                    line: 1,
+                    column: 4,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn register_where_label_expected() {
+        assert_eq!(
+            parse_source("LD R0, R1"),
+            Err(ParseError {
+                message: String::from("expected a label or a number"),
+                span: Span {
+                    line: 1,
+                    column: 7,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_operands() {
+        assert_eq!(
+            parse_source("NOT R1, R2, R3"),
+            Err(ParseError {
+                message: String::from("too many operands for NOT"),
+                span: Span {
+                    line: 1,
+                    column: 10,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("ADD R1, R2, #1, #2"),
+            Err(ParseError {
+                message: String::from("too many operands for ADD"),
+                span: Span {
+                    line: 1,
+                    column: 14,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source(".FILL 1 2"),
+            Err(ParseError {
+                message: String::from("too many operands for .FILL"),
+                span: Span {
+                    line: 1,
+                    column: 8,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn too_few_operands() {
+        assert_eq!(
+            parse_source("LDR R1, R2\nRTI"),
+            Err(ParseError {
+                message: String::from("expected a comma"),
+                span: Span {
+                    line: 1,
+                    column: 10,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("AND R1, R2,"),
+            Err(ParseError {
+                message: String::from("unexpected end of input"),
+                span: Span {
+                    line: 1,
+                    column: 11,
+                    len: 1,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn stray_operands() {
+        assert_eq!(
+            parse_source("R1, R2"),
+            Err(ParseError {
+                message: String::from("unexpected register: R1"),
+                span: Span {
+                    line: 1,
+                    column: 0,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("#5"),
+            Err(ParseError {
+                message: String::from("unexpected number 5"),
+                span: Span {
+                    line: 1,
+                    column: 0,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("loop again ADD R1, R1, R1"),
+            Err(ParseError {
+                message: String::from(
+                    "expected an instruction or directive after label loop, found again"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 5,
+                    len: 5,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn immediate_out_of_range() {
+        assert_eq!(
+            parse_source("ADD R1, R1, #15\nADD R1, R1, #-16"),
+            Ok(vec![0x126F, 0x1270])
+        );
+        assert_eq!(
+            parse_source("ADD R1, R1, #16"),
+            Err(ParseError {
+                message: String::from(
+                    "ADD immediate is out of range: 16 does not fit in 5 bits (-16..15)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 12,
+                    len: 3,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+        assert_eq!(
+            parse_source("and r1, r1, #-17"),
+            Err(ParseError {
+                message: String::from(
+                    "and immediate is out of range: -17 does not fit in 5 bits (-16..15)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 12,
+                    len: 4,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn pc_offset_literal_out_of_range() {
+        assert_eq!(
+            parse_source("BR #-256\nJSR #1023"),
+            Ok(vec![0x0F00, 0x4BFF])
+        );
+        assert_eq!(
+            parse_source("JSR #1024"),
+            Err(ParseError {
+                message: String::from(
+                    "JSR offset is out of range: 1024 does not fit in 11 bits (-1024..1023)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 4,
+                    len: 5,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn trap_vector_out_of_range() {
+        assert_eq!(
+            parse_errors(".ORIG x3000\nTRAP #-1\nTRAP 1-2"),
+            vec![
+                "TRAP vector is out of range: -1 does not fit in 8 bits (0..255)",
+                "TRAP vector is out of range: -1 does not fit in 8 bits (0..255)"
+            ]
+        );
+        assert_eq!(
+            parse_source("TRAP x100"),
+            Err(ParseError {
+                message: String::from(
+                    "TRAP vector is out of range: 256 does not fit in 8 bits (0..255)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 5,
+                    len: 4,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn branch_conditions() {
+        assert_eq!(
+            parse_source(
+                "loop BR loop
+                      BRn loop
+                      BRz loop
+                      BRp loop
+                      brnz loop
+                      BRnp loop
+                      BRZP loop
+                      BRnzp loop"
+            ),
+            Ok(vec![
+                0b0000_111_111111111,
+                0b0000_100_111111110,
+                0b0000_010_111111101,
+                0b0000_001_111111100,
+                0b0000_110_111111011,
+                0b0000_101_111111010,
+                0b0000_011_111111001,
+                0b0000_111_111111000,
+            ])
+        );
+    }
+
+    #[test]
+    fn unordered_branch_condition_is_a_label() {
+        assert_eq!(
+            parse_source("BRpz #0"),
+            Err(ParseError {
+                message: String::from("unexpected number 0"),
+                span: Span {
+                    line: 1,
+                    column: 5,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn nop() {
+        assert_eq!(parse_source("NOP\nnop"), Ok(vec![0, 0]));
+        assert_eq!(
+            parse_source("NOP R1"),
+            Err(ParseError {
+                message: String::from("too many operands for NOP"),
+                span: Span {
+                    line: 1,
+                    column: 4,
+                    len: 2,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn jmpt() {
+        // a JMP with the lowest bit set, like the OS uses to start user code
+        assert_eq!(
+            parse_source("JMPT R7\njmpt r2\nJMP R2"),
+            Ok(vec![0xC1C1, 0xC081, 0xC080])
+        );
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        assert_eq!(
+            parse_source("GETC\nOUT\nPUTS\nIN\nPUTSP\nHALT\nRET"),
+            Ok(vec![0xF020, 0xF021, 0xF022, 0xF023, 0xF024, 0xF025, 0xC1C0])
+        );
+        assert_eq!(parse_source("halt\nret"), parse_source("TRAP x25\nJMP R7"));
+        assert_eq!(
+            parse_source("HALT x25"),
+            Err(ParseError {
+                message: String::from("too many operands for HALT"),
+                span: Span {
+                    line: 1,
+                    column: 5,
+                    len: 3,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn missing_orig() {
+        assert_eq!(
+            parse("test.asm", vec![], &Options::default()),
+            Err(vec![ParseError {
+                message: String::from("missing .ORIG directive"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 0,
+                },
+                labels: vec![],
+                help: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn instruction_before_orig() {
+        assert_eq!(
+            parse(
+                "test.asm",
+                crate::assembler::lexer::lex("ADD R1, R1, R1\n.ORIG x3000").unwrap(),
+                &Options::default(),
+            ),
+            Err(vec![ParseError {
+                message: String::from("ADD must come after .ORIG"),
+                span: Span {
+                    line: 0,
+                    column: 0,
+                    len: 3,
+                },
+                labels: vec![],
+                help: None,
+            }])
+        );
+        assert_eq!(
+            parse(
+                "test.asm",
+                crate::assembler::lexer::lex("data .FILL 1\n.ORIG x3000").unwrap(),
+                &Options::default(),
+            ),
+            Err(vec![ParseError {
+                message: String::from("FILL must come after .ORIG"),
+                span: Span {
+                    line: 0,
+                    column: 5,
+                    len: 5,
+                },
+                labels: vec![],
+                help: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn multiple_orig() {
+        let tokens =
+            crate::assembler::lexer::lex(".ORIG x3000\nfirst .FILL 1\n.ORIG x4000\nsecond .FILL 2")
+                .unwrap();
+        let mut parser = Parser::new("test.asm", &tokens);
+        let (executable, _) = parser.parse().unwrap();
+        assert_eq!(
+            executable
+                .segments
+                .iter()
+                .map(|segment| (segment.origin, segment.words.clone()))
+                .collect::<Vec<_>>(),
+            vec![(0x3000, vec![1]), (0x4000, vec![2])]
+        );
+        assert_eq!(executable.symbols.get("second"), Some(&0x4000));
+    }
+
+    /// every error message from a program, in order
+    fn parse_errors(source: &str) -> Vec<String> {
+        parse(
+            "test.asm",
+            crate::assembler::lexer::lex(source).unwrap(),
+            &Options::default(),
+        )
+        .err()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|error| error.message)
+        .collect()
+    }
+
+    #[test]
+    fn report_every_bad_line() {
+        assert_eq!(
+            parse_errors(
+                ".ORIG x3000
+                 ADD R0, R9, R1
+                 HALT
+                 LD R0, #300
+                 JMP"
+            ),
+            vec![
+                "invalid register: R9 (expected R0-R7)",
+                "LD offset is out of range: 300 does not fit in 9 bits (-256..255)",
+                "unexpected end of input",
+            ]
+        );
+    }
+
+    #[test]
+    fn recover_when_the_newline_was_read() {
+        assert_eq!(
+            parse_errors(".ORIG x3000\nADD R0\nNOT R1, R9\n"),
+            vec!["expected a comma", "invalid register: R9 (expected R0-R7)"]
+        );
+    }
+
+    #[test]
+    fn bad_instructions_keep_labels_in_place() {
+        let tokens = crate::assembler::lexer::lex(
+            ".ORIG x3000\nADD R0, R9, R1\n.FILL\nloop .BLKW 2\nend BR loop",
+        )
+        .unwrap();
+        let mut parser = Parser::new("test.asm", &tokens);
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.labels.get("loop"), Some(&0x3002));
+        assert_eq!(parser.labels.get("end"), Some(&0x3004));
+    }
+
+    #[test]
+    fn instructions_cant_be_names() {
+        assert_eq!(
+            parse_errors(".ORIG x3000\nIN: ADD R0, R0, #1\nout .EQU 5\nHALT: .EQU 1\nBRz done"),
+            vec![
+                "IN is an instruction, so it can't be the name of a label",
+                "out is an instruction, so it can't be the name of a constant",
+                "HALT is an instruction, so it can't be the name of a constant",
+                "undefined label: done",
+            ]
+        );
+    }
+
+    #[test]
+    fn suppress_cascading_errors() {
+        // the label is still defined, even though its line is wrong
+        assert_eq!(
+            parse_errors(".ORIG x3000\nloop oops\nBR loop"),
+            vec!["expected an instruction or directive after label loop, found oops"]
+        );
+        // a missing .ORIG only gets reported once
+        assert_eq!(
+            parse_errors("ADD R0, R0, R0\nHALT\ndata .FILL 1"),
+            vec!["ADD must come after .ORIG"]
+        );
+    }
+
+    #[test]
+    fn constant_expressions() {
+        assert_eq!(
+            parse_source(
+                "SIZE .EQU 4
+                 TOP .equ xFE00
+                 .FILL SIZE*2+1
+                 .FILL TOP-SIZE
+                 .FILL -(SIZE - 1) * 2
+                 .FILL SIZE / -2
+                 .BLKW SIZE-3
+                 ADD R0, R0, SIZE
+                 ADD R0, R0, -SIZE
+                 LDR R1, R2, SIZE-5
+                 TRAP x20+SIZE+1"
+            ),
+            Ok(vec![
+                9, 0xFDFC, 0xFFFA, 0xFFFE, 0, 0x1024, 0x103C, 0x62BF, 0xF025
+            ])
+        );
+    }
+
+    #[test]
+    fn expression_errors() {
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.BLKW SIZE\nSIZE .EQU 1"),
+            vec!["undefined constant: SIZE"]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\nA .EQU 1\nA .EQU 2\n.EQU 3"),
+            vec![
+                "constant A is already defined",
+                ".EQU needs a name for the constant"
+            ]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\nloop HALT\nloop .EQU 2"),
+            vec!["loop is already a label"]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.FILL (1 + 2\n.FILL 1 / 0\n.FILL 1 +\n.FILL 2 3"),
+            vec![
+                "expected a ')'",
+                "division by zero",
+                "expected a number",
+                "too many operands for .FILL"
+            ]
+        );
+        // the whole expression is what's out of range
+        assert_eq!(
+            parse_source("ADD R0, R0, 10 + 10"),
+            Err(ParseError {
+                message: String::from(
+                    "ADD immediate is out of range: 20 does not fit in 5 bits (-16..15)"
+                ),
+                span: Span {
+                    line: 1,
+                    column: 12,
+                    len: 7,
+                },
+                labels: vec![],
+                help: None,
+            })
+        );
+    }
+
+    #[test]
+    fn conditional_assembly() {
+        let source = "WITH_OS .EQU 1
+                 .IFDEF WITH_OS
+                   .FILL 1
+                   .IFNDEF WITH_OS
+                     .FILL 2
+                   .ELSE
+                     .FILL 3
+                   .ENDIF
+                 .ELSE
+                   .FILL 4
+                   skipped BR skipped
+                 .ENDIF
+                 .ifdef BARE
+                   .FILL 5
+                 .endif";
+        assert_eq!(parse_source(source), Ok(vec![1, 3]));
+
+        let tokens = crate::assembler::lexer::lex(&format!(".ORIG x3000\n{}", source)).unwrap();
+        let mut parser = Parser::new("test.asm", &tokens);
+        parser.defines.insert(String::from("BARE"), 1);
+        assert_eq!(
+            parser
+                .parse()
+                .map(|(executable, _)| executable.segments[0].words.clone()),
+            Ok(vec![1, 3, 5])
+        );
+        assert_eq!(parser.labels.get("skipped"), None);
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.IFDEF A\nHALT"),
+            vec!["unterminated conditional block: expected .ENDIF"]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.ELSE\n.ENDIF"),
+            vec![
+                ".ELSE without a matching .IFDEF or .IFNDEF",
+                ".ENDIF without a matching .IFDEF or .IFNDEF"
+            ]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.IFDEF A\n.ELSE\n.ELSE\n.ENDIF"),
+            vec!["this block already has an .ELSE"]
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.IFDEF #1\nHALT\n.ENDIF A"),
+            vec![
+                "expected the name of a constant",
+                "too many operands for .ENDIF"
+            ]
+        );
+    }
+
+    #[test]
+    fn blkw_fill_values() {
+        assert_eq!(
+            parse_source(
+                "SIZE .EQU 2
+                 .BLKW 2 xFFFF
+                 .BLKW SIZE, #-2
+                 .BLKW SIZE+1 'a'
+                 .BLKW 1"
+            ),
+            Ok(vec![0xFFFF, 0xFFFF, 0xFFFE, 0xFFFE, 97, 97, 97, 0])
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.BLKW 2,\n.BLKW 2 R0\n.BLKW 2 1 1"),
+            vec![
+                "expected a number",
+                "expected a number",
+                "too many operands for .BLKW"
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_with_labels() {
+        assert_eq!(
+            parse_source(
+                "OFFSET .EQU 2
+                 table .FILL first
+                       .FILL second+OFFSET
+                       .FILL table - 1
+                 first HALT
+                 second .BLKW 1 first"
+            ),
+            Ok(vec![0x3003, 0x3006, 0x2FFF, 0xF025, 0x3003])
+        );
+        assert_eq!(
+            parse_errors(".ORIG x3000\n.FILL nowhere\nbuf .BLKW buf\n.ORIG end\nend HALT"),
+            vec![
+                "undefined label: nowhere",
+                "buf is a label, but this needs a constant",
+                "end is a label, but this needs a constant"
+            ]
+        );
+    }
+
+    #[test]
+    fn lc3as_syntax() {
+        assert_eq!(
+            parse_source(
+                "loop: add r1 r1 #-1
+                 BRp loop,
+                 LDR R0 R1 #0
+                 data: .FILL 1,"
+            ),
+            Ok(vec![0x127F, 0x03FE, 0x6040, 1])
+        );
+        // a missing comma is only fine if there's another operand after it
+        assert_eq!(
+            parse_errors(".ORIG x3000\nADD R0\nADD R0 R0,,R0"),
+            vec!["expected a comma", "expected a number"]
+        );
+    }
+
+    #[test]
+    fn negative_operands_without_commas() {
+        assert_eq!(
+            parse_source(
+                "LD R0 -1
+                 ADD R0 R0 -1
+                 LDR R1 R0 -2
+                 BR -2
+                 .BLKW 2 -1
+                 LD R0 buf - 1
+            buf  .FILL buf-1"
+            ),
+            Ok(vec![
+                0x21FF, 0x103F, 0x623E, 0x0FFE, 0xFFFF, 0xFFFF, 0x21FF, 0x3006
+            ])
+        );
+        // with commas, it's always subtraction
+        let tokens = crate::assembler::lexer::lex(".ORIG x3000\n.BLKW 2 -1").unwrap();
+        let strict = Options {
+            strict: true,
+            ..Options::default()
+        };
+        assert_eq!(parse_words_with(tokens, &strict), Ok(vec![0]));
+    }
+
+    #[test]
+    fn strict_syntax() {
+        let parse_strictly = |source: &str| {
+            let tokens = crate::assembler::lexer::lex(source).unwrap();
+            let options = Options {
+                strict: true,
+                ..Options::default()
+            };
+            parse("test.asm", tokens, &options)
+                .err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|error| error.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            parse_strictly(".ORIG x3000\nloop: HALT\nADD R0 R0, R0\nBR loop,\nNOT R0, R0"),
+            vec![
+                "unexpected colon after loop",
+                "expected a comma",
+                "too many operands for BR"
+            ]
+        );
+    }
 }
@@ -1,14 +1,28 @@
+use crate::assembler::Span;
+
+/// a cursor over borrowed items, like the chars of a source file or the tokens
+/// lexed from it, that keeps track of which line it's on. nothing is cloned,
+/// every item it hands out is a reference into the slice it reads
 #[derive(Debug)]
-pub(crate) struct Reader<T> {
-    items: Vec<T>,
-    is_newline: fn(T) -> bool,
+pub(crate) struct Reader<'a, T> {
+    items: &'a [T],
+    is_newline: fn(&T) -> bool,
+    offset: usize,
+    item_in_line: usize,
+    line: usize,
+}
+
+/// where a reader is, to find the span of what it reads after
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Position {
     pub(crate) offset: usize,
-    pub(crate) item_in_line: usize,
     pub(crate) line: usize,
+    /// how many items into the line
+    pub(crate) column: usize,
 }
 
-impl<T: Clone> Reader<T> {
-    pub(crate) fn from(items: Vec<T>, is_newline: fn(T) -> bool) -> Self {
+impl<'a, T> Reader<'a, T> {
+    pub(crate) fn new(items: &'a [T], is_newline: fn(&T) -> bool) -> Self {
         Reader {
             items,
             is_newline,
@@ -24,52 +38,135 @@ impl<T: Clone> Reader<T> {
         self.line = 0;
     }
 
-    pub(crate) fn get(&self, index: usize) -> Option<T> {
-        self.items.get(index).map(ToOwned::to_owned)
+    pub(crate) fn position(&self) -> Position {
+        Position {
+            offset: self.offset,
+            line: self.line,
+            column: self.item_in_line,
+        }
     }
 
-    pub(crate) fn peek(&self) -> Option<T> {
-        self.get(self.offset)
+    /// the span of everything read since `start`, which is only right if it
+    /// didn't go past the end of the line
+    pub(crate) fn span_from(&self, start: Position) -> Span {
+        Span {
+            line: start.line,
+            column: start.column,
+            len: self.offset - start.offset,
+        }
     }
 
-    pub(crate) fn next(&mut self) -> Option<T> {
-        let c = self.peek();
+    /// the item `next` would return
+    pub(crate) fn peek(&self) -> Option<&'a T> {
+        self.peek_n(0)
+    }
 
-        if let Some(c) = c {
-            if (self.is_newline)(c.clone()) {
-                self.line += 1;
-                self.item_in_line = 0;
-            } else {
-                self.item_in_line += 1;
-            }
+    /// look further ahead, at the item `n` places after the one `peek` returns
+    pub(crate) fn peek_n(&self, n: usize) -> Option<&'a T> {
+        self.items.get(self.offset + n)
+    }
 
-            self.offset += 1;
-            Some(c)
+    /// the item `next` returned last
+    pub(crate) fn previous(&self) -> Option<&'a T> {
+        self.items.get(self.offset.checked_sub(1)?)
+    }
+
+    /// everything that hasn't been read yet
+    pub(crate) fn rest(&self) -> &'a [T] {
+        &self.items[self.offset..]
+    }
+
+    pub(crate) fn next(&mut self) -> Option<&'a T> {
+        let item = self.peek()?;
+        if (self.is_newline)(item) {
+            self.line += 1;
+            self.item_in_line = 0;
         } else {
-            None
+            self.item_in_line += 1;
         }
+        self.offset += 1;
+        Some(item)
+    }
+
+    /// move past everything that's left, so `next` returns nothing
+    pub(crate) fn finish(&mut self) {
+        while self.next().is_some() {}
     }
 
     pub(crate) fn skip_while<F>(&mut self, predicate: F)
     where
-        F: Fn(T) -> bool + Copy,
+        F: Fn(&T) -> bool,
     {
-        while self.peek().map_or(false, predicate) {
-            self.next();
-        }
+        self.take_while(predicate);
     }
 
-    pub(crate) fn take_while<F>(&mut self, predicate: F) -> Vec<T>
+    pub(crate) fn take_while<F>(&mut self, predicate: F) -> &'a [T]
     where
-        F: Fn(T) -> bool + Copy,
+        F: Fn(&T) -> bool,
     {
-        let mut chars = Vec::new();
-        while self.peek().map_or(false, predicate) {
-            match self.next() {
-                Some(c) => chars.push(c),
-                None => break,
-            }
+        let start = self.offset;
+        while self.peek().is_some_and(&predicate) {
+            self.next();
         }
-        chars.iter().map(ToOwned::to_owned).collect()
+        &self.items[start..self.offset]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(source: &str) -> Vec<char> {
+        source.chars().collect()
+    }
+
+    #[test]
+    fn test_next_and_peek() {
+        let items = chars("ab\ncd");
+        let mut reader = Reader::new(&items, |c| *c == '\n');
+        assert_eq!(reader.peek(), Some(&'a'));
+        assert_eq!(reader.peek_n(1), Some(&'b'));
+        assert_eq!(reader.peek_n(4), Some(&'d'));
+        assert_eq!(reader.peek_n(5), None);
+        assert_eq!(reader.previous(), None);
+
+        assert_eq!(reader.next(), Some(&'a'));
+        assert_eq!(reader.previous(), Some(&'a'));
+        assert_eq!(reader.take_while(|c| *c != 'c'), &['b', '\n']);
+        assert_eq!(
+            reader.position(),
+            Position {
+                offset: 3,
+                line: 1,
+                column: 0
+            }
+        );
+        assert_eq!(reader.rest(), &['c', 'd']);
+
+        reader.finish();
+        assert_eq!(reader.next(), None);
+        assert_eq!(reader.peek(), None);
+        assert_eq!(reader.rest(), &[] as &[char]);
+        assert_eq!(reader.previous(), Some(&'d'));
+
+        reader.reset();
+        assert_eq!(reader.next(), Some(&'a'));
+    }
+
+    #[test]
+    fn test_span_from() {
+        let items = chars("one\n  two");
+        let mut reader = Reader::new(&items, |c| *c == '\n');
+        reader.take_while(|c| *c != 't');
+        let start = reader.position();
+        reader.skip_while(char::is_ascii_alphabetic);
+        assert_eq!(
+            reader.span_from(start),
+            Span {
+                line: 1,
+                column: 2,
+                len: 3
+            }
+        );
     }
 }
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+const HEADER: &str = "// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+";
+
+/// render a symbol table in the lc3as .sym format, ordered by address
+pub fn to_string(symbols: &BTreeMap<String, u16>) -> String {
+    let mut by_address: Vec<(&String, &u16)> = symbols.iter().collect();
+    by_address.sort_by_key(|(_, address)| **address);
+
+    let mut sym = String::from(HEADER);
+    for (name, address) in by_address {
+        sym.push_str(&format!("//\t{:<16}  {:04X}\n", name, address));
+    }
+    sym.push('\n');
+    sym
+}
+
+/// read back a symbol table written by `to_string` or by lc3as
+pub fn parse(sym: &str) -> Result<BTreeMap<String, u16>, String> {
+    let mut symbols = BTreeMap::new();
+    for (index, line) in sym.lines().enumerate() {
+        let entry = match line.strip_prefix("//") {
+            Some(entry) => entry.trim(),
+            None if line.trim().is_empty() => continue,
+            None => return Err(format!("line {}: expected a comment", index + 1)),
+        };
+
+        let mut columns = entry.split_whitespace();
+        let (name, address) = match (columns.next(), columns.next(), columns.next()) {
+            (Some(name), Some(address), None) => (name, address),
+            _ => continue,
+        };
+        // the header lines have two columns too, but their second column
+        // isn't a hex number
+        if let Ok(address) = u16::from_str_radix(address, 16) {
+            symbols.insert(name.to_string(), address);
+        }
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols(entries: &[(&str, u16)]) -> BTreeMap<String, u16> {
+        entries
+            .iter()
+            .map(|(name, address)| (name.to_string(), *address))
+            .collect()
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(
+            to_string(&symbols(&[("LOOP", 0x3002), ("START", 0x3000)])),
+            "// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tSTART             3000
+//\tLOOP              3002
+
+"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let table = symbols(&[
+            ("a", 0),
+            ("a_rather_long_label_name", 0xFFFF),
+            ("Mixed_Case", 0x3010),
+        ]);
+        assert_eq!(parse(&to_string(&table)), Ok(table));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse(&to_string(&BTreeMap::new())), Ok(BTreeMap::new()));
+        assert_eq!(parse(""), Ok(BTreeMap::new()));
+    }
+
+    #[test]
+    fn test_parse_not_a_symbol_table() {
+        assert_eq!(
+            parse("// Symbol table\nLOOP 3000"),
+            Err(String::from("line 2: expected a comment"))
+        );
+    }
+}
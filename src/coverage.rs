@@ -0,0 +1,115 @@
+use crate::assembler::DebugInfo;
+use std::collections::BTreeMap;
+
+/// how many times each line of code was executed, by zero-indexed line. lines
+/// that only hold data, labels or comments aren't included
+pub fn line_counts(
+    debug_info: &DebugInfo,
+    execution_counts: &BTreeMap<u16, u64>,
+) -> BTreeMap<usize, u64> {
+    let mut lines = BTreeMap::new();
+    for address in &debug_info.code {
+        if let Some(location) = debug_info.location(*address) {
+            let count = execution_counts.get(address).copied().unwrap_or(0);
+            *lines.entry(location.line).or_insert(0) += count;
+        }
+    }
+    lines
+}
+
+/// render the source with the execution count of each line in the margin, in
+/// the style of gcov: `-` for lines with no code, `#####` for code that never ran
+pub fn annotate(source: &str, line_counts: &BTreeMap<usize, u64>) -> String {
+    let mut annotated = String::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let count = match line_counts.get(&line_number) {
+            None => String::from("-"),
+            Some(0) => String::from("#####"),
+            Some(count) => count.to_string(),
+        };
+        annotated.push_str(&format!("{:>9}:{:>5}:{}\n", count, line_number + 1, line));
+    }
+    annotated
+}
+
+/// render an lcov tracefile, which tools like genhtml can turn into a report
+pub fn lcov(filename: &str, line_counts: &BTreeMap<usize, u64>) -> String {
+    let mut tracefile = format!("TN:\nSF:{}\n", filename);
+    for (line, count) in line_counts {
+        tracefile.push_str(&format!("DA:{},{}\n", line + 1, count));
+    }
+    let hit = line_counts.values().filter(|count| **count > 0).count();
+    tracefile.push_str(&format!(
+        "LF:{}\nLH:{}\nend_of_record\n",
+        line_counts.len(),
+        hit
+    ));
+    tracefile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    const SOURCE: &str = ".ORIG x3000
+        AND R0, R0, #0
+loop    ADD R0, R0, #1   ; count up
+        BRn never
+        HALT
+never   ADD R0, R0, #-1
+data    .FILL 0
+.END";
+
+    fn counts() -> BTreeMap<usize, u64> {
+        let executable = assemble("count.asm", SOURCE).unwrap();
+        let execution_counts = vec![(0x3000, 1), (0x3001, 1), (0x3002, 1), (0x3003, 1)]
+            .into_iter()
+            .collect();
+        line_counts(executable.debug_info.as_ref().unwrap(), &execution_counts)
+    }
+
+    #[test]
+    fn test_line_counts() {
+        assert_eq!(
+            counts(),
+            vec![(1, 1), (2, 1), (3, 1), (4, 1), (5, 0)]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        assert_eq!(
+            annotate(SOURCE, &counts()),
+            "        -:    1:.ORIG x3000
+        1:    2:        AND R0, R0, #0
+        1:    3:loop    ADD R0, R0, #1   ; count up
+        1:    4:        BRn never
+        1:    5:        HALT
+    #####:    6:never   ADD R0, R0, #-1
+        -:    7:data    .FILL 0
+        -:    8:.END
+"
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        assert_eq!(
+            lcov("count.asm", &counts()),
+            "TN:
+SF:count.asm
+DA:2,1
+DA:3,1
+DA:4,1
+DA:5,1
+DA:6,0
+LF:5
+LH:4
+end_of_record
+"
+        );
+    }
+}
@@ -0,0 +1,846 @@
+use crate::assembler::Executable;
+use crate::diff::{Snapshot, StateDiff};
+use crate::disassembler;
+use crate::lc3::{Access, AccessKind, Machine, MachineError, UninitializedRead, Watch, Watched};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the commands the debugger understands
+pub const HELP: &str = "commands:
+    break LOCATION          stop before the instruction at LOCATION runs (b)
+    delete [LOCATION]       remove a breakpoint, or every breakpoint (d)
+    breakpoints             list the breakpoints
+    watch TARGET            stop when a register or the word at a LOCATION is written
+    rwatch TARGET           stop when it's read
+    awatch TARGET           stop when it's read or written
+    unwatch TARGET          stop watching it
+    watches                 list what's being watched
+    step [N]                run N instructions, or 1 (s, or just enter)
+    continue                run until a breakpoint or the program halts (c)
+    registers               print the registers, PC and condition code (r)
+    x/N LOCATION            print N words of memory starting at LOCATION
+    set TARGET VALUE        set a register (R0 to R7 or PC), or the word at a LOCATION
+    snapshot                remember the machine's state, for diff to compare with
+    diff                    print what changed since the snapshot, or since debugging started
+    disassemble [LOCATION] [N]  disassemble N instructions from LOCATION, or around the PC (dis)
+    help                    print this (h)
+    quit                    stop debugging (q)
+locations and values are numbers like x3000 or #-1, or labels";
+
+/// how many instructions either side of the PC `disassemble` shows by default
+const CONTEXT: u16 = 4;
+
+/// an address or value, as it's written in a command
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    Address(u16),
+    Label(String),
+}
+
+/// something `set` can change
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Register(u16),
+    Pc,
+    Memory(Location),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Break(Location),
+    /// remove one breakpoint, or all of them
+    Delete(Option<Location>),
+    Breakpoints,
+    Watch {
+        target: Target,
+        watch: Watch,
+    },
+    Unwatch(Target),
+    Watches,
+    Step(u64),
+    Continue,
+    Registers,
+    Examine {
+        count: u16,
+        start: Location,
+    },
+    Set {
+        target: Target,
+        value: Location,
+    },
+    Disassemble {
+        start: Option<Location>,
+        count: Option<u16>,
+    },
+    Snapshot,
+    Diff,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(Command::Step(1)),
+        };
+        let args: Vec<&str> = words.collect();
+        let command = match (name, args.as_slice()) {
+            ("b" | "break", [location]) => Command::Break(parse_location(location)),
+            ("d" | "delete", []) => Command::Delete(None),
+            ("d" | "delete", [location]) => Command::Delete(Some(parse_location(location))),
+            ("breakpoints", []) => Command::Breakpoints,
+            ("watch" | "rwatch" | "awatch", [target]) => Command::Watch {
+                target: parse_target(target),
+                watch: Watch {
+                    reads: name != "watch",
+                    writes: name != "rwatch",
+                },
+            },
+            ("unwatch", [target]) => Command::Unwatch(parse_target(target)),
+            ("watches", []) => Command::Watches,
+            ("s" | "step", []) => Command::Step(1),
+            ("s" | "step", [count]) => Command::Step(
+                count
+                    .parse()
+                    .map_err(|_| format!("expected a number of steps: {}", count))?,
+            ),
+            ("c" | "continue", []) => Command::Continue,
+            ("r" | "registers", []) => Command::Registers,
+            ("x", [start]) => Command::Examine {
+                count: 1,
+                start: parse_location(start),
+            },
+            (name, [start]) if name.starts_with("x/") => Command::Examine {
+                count: parse_count(&name[2..])?,
+                start: parse_location(start),
+            },
+            ("set", [target, value]) => Command::Set {
+                target: parse_target(target),
+                value: parse_location(value),
+            },
+            ("dis" | "disassemble", args) if args.len() <= 2 => Command::Disassemble {
+                start: args.first().map(|start| parse_location(start)),
+                count: args.get(1).map(|count| parse_count(count)).transpose()?,
+            },
+            ("snapshot", []) => Command::Snapshot,
+            ("diff", []) => Command::Diff,
+            ("h" | "help", []) => Command::Help,
+            ("q" | "quit", []) => Command::Quit,
+            _ => return Err(format!("unknown command: {}", line.trim())),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_count(count: &str) -> Result<u16, String> {
+    count
+        .parse()
+        .map_err(|_| format!("expected a number of words: {}", count))
+}
+
+/// a number the way the assembler writes them, or else a label
+fn parse_location(text: &str) -> Location {
+    parse_number(text).map_or_else(|| Location::Label(text.to_string()), Location::Address)
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix('x').or_else(|| text.strip_prefix('X')) {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    let decimal = text.strip_prefix('#').unwrap_or(text);
+    decimal
+        .parse::<u16>()
+        .ok()
+        .or_else(|| decimal.parse::<i16>().ok().map(|value| value as u16))
+}
+
+fn parse_target(text: &str) -> Target {
+    let register = text
+        .strip_prefix('R')
+        .or_else(|| text.strip_prefix('r'))
+        .and_then(|index| index.parse::<u16>().ok())
+        .filter(|index| *index < 8);
+    match register {
+        Some(index) => Target::Register(index),
+        None if text.eq_ignore_ascii_case("pc") => Target::Pc,
+        None => Target::Memory(parse_location(text)),
+    }
+}
+
+/// why the program stopped running
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    /// every step that was asked for was taken
+    Stepped,
+    /// the PC reached a breakpoint, which hasn't been executed yet
+    Breakpoint(u16),
+    /// the instruction at `address` accessed watched locations
+    Watchpoint {
+        address: u16,
+        accesses: Vec<Access>,
+    },
+    /// the instruction at `address` loaded words that were never written,
+    /// when the machine is tracking uninitialized reads
+    UninitializedRead {
+        address: u16,
+        reads: Vec<UninitializedRead>,
+    },
+    /// the program polled the keyboard and there was nothing to read. it can
+    /// carry on once input has been pushed to the machine
+    WaitingForInput,
+    Halted,
+}
+
+/// runs a machine an instruction at a time, stopping at breakpoints
+pub struct Debugger {
+    machine: Machine,
+    /// every executable loaded into the machine, in the order they were loaded
+    executables: Vec<Executable>,
+    /// the labels of every executable, later ones taking precedence
+    symbols: BTreeMap<String, u16>,
+    breakpoints: BTreeSet<u16>,
+    /// what `diff` compares the machine with
+    snapshot: Snapshot,
+}
+
+impl Debugger {
+    /// debug a machine that's had `executables` loaded into it, in that order
+    pub fn new(machine: Machine, executables: Vec<Executable>) -> Debugger {
+        let symbols = executables
+            .iter()
+            .flat_map(|executable| &executable.symbols)
+            .map(|(name, address)| (name.clone(), *address))
+            .collect();
+        Debugger {
+            snapshot: Snapshot::of(&machine),
+            machine,
+            executables,
+            symbols,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// the machine, for things the debugger doesn't do itself, like pushing input
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// stop before the instruction at `address` is executed
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// returns false if there wasn't a breakpoint there
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// remember the state of the machine now, for `diff` to compare with
+    pub fn take_snapshot(&mut self) {
+        self.snapshot = Snapshot::of(&self.machine);
+    }
+
+    /// what changed since the last snapshot, or since the debugger was made
+    pub fn diff(&self) -> StateDiff {
+        StateDiff::between(&self.snapshot, &Snapshot::of(&self.machine))
+    }
+
+    /// execute up to `count` instructions, stopping early at a breakpoint, if
+    /// the program halts, or if it's waiting for input
+    pub fn step(&mut self, count: u64) -> Result<Stop, MachineError> {
+        for taken in 0..count {
+            if !self.machine.is_running() {
+                return Ok(Stop::Halted);
+            }
+            // the instruction that was stopped at can always be stepped over
+            if taken > 0 && self.breakpoints.contains(&self.machine.pc()) {
+                return Ok(Stop::Breakpoint(self.machine.pc()));
+            }
+            let address = self.machine.pc();
+            self.machine.step()?;
+            let accesses = self.machine.take_accesses();
+            if !accesses.is_empty() {
+                return Ok(Stop::Watchpoint { address, accesses });
+            }
+            let reads = self.machine.take_uninitialized_reads();
+            if !reads.is_empty() {
+                return Ok(Stop::UninitializedRead { address, reads });
+            }
+            if self.machine.is_waiting_for_input() {
+                return Ok(Stop::WaitingForInput);
+            }
+        }
+        if self.machine.is_running() {
+            Ok(Stop::Stepped)
+        } else {
+            Ok(Stop::Halted)
+        }
+    }
+
+    /// run until a breakpoint, the program halts, or it waits for input
+    pub fn resume(&mut self) -> Result<Stop, MachineError> {
+        self.step(u64::MAX)
+    }
+
+    /// the label at each address, for disassembling
+    pub fn labels(&self) -> BTreeMap<u16, &str> {
+        disassembler::labels(&self.symbols)
+    }
+
+    /// look up the register or word of memory a target refers to, for watching
+    pub fn resolve_watched(&self, target: &Target) -> Result<Watched, String> {
+        match target {
+            Target::Register(index) => Ok(Watched::Register(*index)),
+            Target::Pc => Err(String::from("the PC can't be watched")),
+            Target::Memory(location) => Ok(Watched::Memory(self.resolve(location)?)),
+        }
+    }
+
+    /// look up the address a location refers to
+    pub fn resolve(&self, location: &Location) -> Result<u16, String> {
+        match location {
+            Location::Address(address) => Ok(*address),
+            Location::Label(label) => self
+                .symbols
+                .get(label)
+                .copied()
+                .ok_or_else(|| format!("no label called {}", label)),
+        }
+    }
+
+    /// describe an address by the source location it was assembled from, if
+    /// any executable knows where that is
+    pub fn describe(&self, address: u16) -> String {
+        // later executables were loaded over earlier ones
+        describe_address(self.executables.iter().rev(), address)
+    }
+
+    /// the instruction the PC is at and where it came from, like a prompt
+    pub fn current_instruction(&self) -> String {
+        self.instruction(self.machine.pc())
+    }
+
+    /// the instruction at an address and where it came from
+    fn instruction(&self, address: u16) -> String {
+        let labels = self.labels();
+        format!(
+            "{}  {}",
+            self.describe(address),
+            disassembler::instruction(self.machine.memory(address), address, &labels)
+        )
+    }
+
+    /// carry out a command, returning what to show for it. `Quit` is left to
+    /// whatever is reading the commands
+    pub fn execute(&mut self, command: &Command) -> Result<String, String> {
+        match command {
+            Command::Break(location) => {
+                let address = self.resolve(location)?;
+                self.add_breakpoint(address);
+                Ok(format!("breakpoint at {}", self.describe(address)))
+            }
+            Command::Delete(None) => {
+                self.breakpoints.clear();
+                Ok(String::from("deleted every breakpoint"))
+            }
+            Command::Delete(Some(location)) => {
+                let address = self.resolve(location)?;
+                if self.remove_breakpoint(address) {
+                    Ok(format!("deleted the breakpoint at x{:04X}", address))
+                } else {
+                    Err(format!("there's no breakpoint at x{:04X}", address))
+                }
+            }
+            Command::Breakpoints if self.breakpoints.is_empty() => {
+                Ok(String::from("no breakpoints"))
+            }
+            Command::Breakpoints => Ok(self
+                .breakpoints
+                .iter()
+                .map(|address| self.describe(*address))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::Watch { target, watch } => {
+                let watched = self.resolve_watched(target)?;
+                self.machine.watch(watched, *watch);
+                Ok(format!(
+                    "watching {} for {}",
+                    self.name(watched),
+                    describe_watch(*watch)
+                ))
+            }
+            Command::Unwatch(target) => {
+                let watched = self.resolve_watched(target)?;
+                if self.machine.unwatch(watched) {
+                    Ok(format!("stopped watching {}", self.name(watched)))
+                } else {
+                    Err(format!("{} isn't being watched", self.name(watched)))
+                }
+            }
+            Command::Watches if self.machine.watches().is_empty() => {
+                Ok(String::from("nothing is being watched"))
+            }
+            Command::Watches => Ok(self
+                .machine
+                .watches()
+                .iter()
+                .map(|(watched, watch)| {
+                    format!("{} for {}", self.name(*watched), describe_watch(*watch))
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::Step(count) => {
+                let stop = self.step(*count);
+                self.report(stop)
+            }
+            Command::Continue => {
+                let stop = self.resume();
+                self.report(stop)
+            }
+            Command::Registers => Ok(self.registers()),
+            Command::Examine { count, start } => Ok(self.examine(self.resolve(start)?, *count)),
+            Command::Set { target, value } => {
+                let value = self.resolve(value)?;
+                match target {
+                    Target::Register(index) => self.machine.set_register(*index, value),
+                    Target::Pc => self.machine.set_pc(value),
+                    Target::Memory(location) => {
+                        let address = self.resolve(location)?;
+                        self.machine.set_memory(address, value);
+                    }
+                }
+                Ok(String::new())
+            }
+            Command::Disassemble { start, count } => {
+                let start = match start {
+                    Some(start) => self.resolve(start)?,
+                    None => self.machine.pc().wrapping_sub(CONTEXT),
+                };
+                Ok(self.disassemble(start, count.unwrap_or(CONTEXT * 2 + 1)))
+            }
+            Command::Snapshot => {
+                self.take_snapshot();
+                Ok(format!(
+                    "took a snapshot at {}",
+                    self.describe(self.machine.pc())
+                ))
+            }
+            Command::Diff => {
+                let diff = self.diff();
+                if diff.is_empty() {
+                    Ok(String::from("nothing has changed"))
+                } else {
+                    Ok(diff.to_lines().join("\n"))
+                }
+            }
+            Command::Help => Ok(String::from(HELP)),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+
+    /// describe why the program stopped, and where
+    pub fn report(&self, stop: Result<Stop, MachineError>) -> Result<String, String> {
+        let stop = stop.map_err(|e| format!("{}: {}", self.describe(e.address), e.message))?;
+        Ok(match stop {
+            Stop::Halted => String::from("the program halted"),
+            Stop::Breakpoint(_) => format!("breakpoint: {}", self.current_instruction()),
+            Stop::Watchpoint { address, accesses } => {
+                let mut lines = vec![format!("watchpoint: {}", self.instruction(address))];
+                lines.extend(accesses.iter().map(|access| match access.kind {
+                    AccessKind::Read => {
+                        format!(
+                            "    read {}: x{:04X}",
+                            self.name(access.watched),
+                            access.old
+                        )
+                    }
+                    AccessKind::Write => format!(
+                        "    wrote {}: x{:04X} -> x{:04X}",
+                        self.name(access.watched),
+                        access.old,
+                        access.new
+                    ),
+                }));
+                lines.push(self.current_instruction());
+                lines.join("\n")
+            }
+            Stop::UninitializedRead { address, reads } => {
+                let mut lines = vec![format!("uninitialized read: {}", self.instruction(address))];
+                lines.extend(reads.iter().map(|read| {
+                    format!(
+                        "    loaded {}, which was never written",
+                        self.name(Watched::Memory(read.target))
+                    )
+                }));
+                lines.push(self.current_instruction());
+                lines.join("\n")
+            }
+            Stop::Stepped | Stop::WaitingForInput => self.current_instruction(),
+        })
+    }
+
+    /// what to call a watched location: a register, or an address along with
+    /// its label if it has one
+    fn name(&self, watched: Watched) -> String {
+        match watched {
+            Watched::Register(index) => format!("R{}", index),
+            Watched::Memory(address) => match self.labels().get(&address) {
+                Some(label) => format!("x{:04X} ({})", address, label),
+                None => format!("x{:04X}", address),
+            },
+        }
+    }
+
+    fn registers(&self) -> String {
+        let registers: Vec<String> = self
+            .machine
+            .registers()
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format!("R{} x{:04X}", index, value))
+            .collect();
+        format!(
+            "{}  PC x{:04X}  CC {}",
+            registers.join("  "),
+            self.machine.pc(),
+            self.machine.condition()
+        )
+    }
+
+    /// the words starting at `start`, eight to a line
+    fn examine(&self, start: u16, count: u16) -> String {
+        let mut lines = Vec::new();
+        for row in (0..count).step_by(8) {
+            let address = start.wrapping_add(row);
+            let words: Vec<String> = (row..count.min(row.saturating_add(8)))
+                .map(|offset| format!("x{:04X}", self.machine.memory(start.wrapping_add(offset))))
+                .collect();
+            lines.push(format!("x{:04X}  {}", address, words.join(" ")));
+        }
+        lines.join("\n")
+    }
+
+    /// `count` instructions starting at `start`, with the PC marked by `=>`,
+    /// breakpoints by `*` and labels on a line of their own
+    fn disassemble(&self, start: u16, count: u16) -> String {
+        let labels = self.labels();
+        let mut lines = Vec::new();
+        for offset in 0..count {
+            let address = start.wrapping_add(offset);
+            if let Some(label) = labels.get(&address) {
+                lines.push(format!("{}:", label));
+            }
+            let word = self.machine.memory(address);
+            lines.push(format!(
+                "{}{} x{:04X}  x{:04X}  {}",
+                if address == self.machine.pc() {
+                    "=>"
+                } else {
+                    "  "
+                },
+                if self.breakpoints.contains(&address) {
+                    "*"
+                } else {
+                    " "
+                },
+                address,
+                word,
+                disassembler::instruction(word, address, &labels)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+fn describe_watch(watch: Watch) -> &'static str {
+    match (watch.reads, watch.writes) {
+        (true, true) => "reads and writes",
+        (true, false) => "reads",
+        _ => "writes",
+    }
+}
+
+/// describe an address by the first source location any of the executables
+/// have for it, like `file.asm:3:5 (x3001)`, or just as `x3001` if none do
+pub fn describe_address<'a>(
+    executables: impl IntoIterator<Item = &'a Executable>,
+    address: u16,
+) -> String {
+    let location = executables.into_iter().find_map(|executable| {
+        let debug_info = executable.debug_info.as_ref()?;
+        let location = debug_info.location(address)?;
+        Some(format!(
+            "{}:{}:{}",
+            debug_info.filename,
+            location.line + 1,
+            location.column + 1
+        ))
+    });
+    match location {
+        Some(location) => format!("{} (x{:04X})", location, address),
+        None => format!("x{:04X}", address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    const SOURCE: &str = ".ORIG x3000
+        AND R1, R1, #0
+loop    ADD R1, R1, #1
+        ADD R2, R1, #-3
+        BRn loop
+poll    LDI R0, kbsr
+        BRzp poll
+        AND R0, R0, #0
+        STI R0, mcr
+kbsr    .FILL xFE00
+mcr     .FILL xFFFE";
+
+    fn debugger() -> Debugger {
+        let executable = assemble("test.asm", SOURCE).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&executable);
+        Debugger::new(machine, vec![executable])
+    }
+
+    fn run(debugger: &mut Debugger, line: &str) -> Result<String, String> {
+        debugger.execute(&Command::parse(line)?)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse(""), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("s 10"), Ok(Command::Step(10)));
+        assert_eq!(
+            Command::parse("b loop"),
+            Ok(Command::Break(Location::Label(String::from("loop"))))
+        );
+        assert_eq!(Command::parse("diff"), Ok(Command::Diff));
+        assert_eq!(
+            Command::parse("x/16 x3000"),
+            Ok(Command::Examine {
+                count: 16,
+                start: Location::Address(0x3000)
+            })
+        );
+        assert_eq!(
+            Command::parse("set r7 #-1"),
+            Ok(Command::Set {
+                target: Target::Register(7),
+                value: Location::Address(0xFFFF)
+            })
+        );
+        assert_eq!(
+            Command::parse("set PC x3000"),
+            Ok(Command::Set {
+                target: Target::Pc,
+                value: Location::Address(0x3000)
+            })
+        );
+        assert_eq!(
+            Command::parse("dis"),
+            Ok(Command::Disassemble {
+                start: None,
+                count: None
+            })
+        );
+        assert!(Command::parse("step lots").is_err());
+        assert!(Command::parse("break").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        assert_eq!(
+            run(&mut debugger, "b loop"),
+            Ok(String::from("breakpoint at test.asm:3:9 (x3001)"))
+        );
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(0x3001)));
+        assert_eq!(debugger.machine().registers()[1], 0);
+        // carrying on runs the instruction at the breakpoint before stopping again
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(0x3001)));
+        assert_eq!(debugger.machine().registers()[1], 1);
+
+        assert!(run(&mut debugger, "d nowhere").is_err());
+        assert!(run(&mut debugger, "d x3002").is_err());
+        run(&mut debugger, "d loop").unwrap();
+        assert_eq!(debugger.resume(), Ok(Stop::WaitingForInput));
+        assert_eq!(debugger.machine().registers()[1], 3);
+        assert_eq!(debugger.resume(), Ok(Stop::WaitingForInput));
+        debugger.machine_mut().push_input(b"k");
+        assert_eq!(debugger.resume(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger();
+        assert_eq!(
+            run(&mut debugger, "watch R2"),
+            Ok(String::from("watching R2 for writes"))
+        );
+        assert_eq!(
+            run(&mut debugger, "rwatch kbsr"),
+            Ok(String::from("watching x3008 (kbsr) for reads"))
+        );
+        assert_eq!(
+            run(&mut debugger, "c"),
+            Ok(String::from(
+                "watchpoint: test.asm:4:9 (x3002)  ADD R2, R1, #-3
+    wrote R2: x0000 -> xFFFE
+test.asm:5:9 (x3003)  BRn loop"
+            ))
+        );
+        run(&mut debugger, "unwatch r2").unwrap();
+        assert_eq!(
+            debugger.resume(),
+            Ok(Stop::Watchpoint {
+                address: 0x3004,
+                accesses: vec![Access {
+                    watched: Watched::Memory(0x3008),
+                    kind: AccessKind::Read,
+                    old: 0xFE00,
+                    new: 0xFE00,
+                }],
+            })
+        );
+        assert_eq!(
+            run(&mut debugger, "watches"),
+            Ok(String::from("x3008 (kbsr) for reads"))
+        );
+        assert!(run(&mut debugger, "unwatch R2").is_err());
+        assert!(run(&mut debugger, "watch pc").is_err());
+    }
+
+    #[test]
+    fn test_uninitialized_reads() {
+        let source = ".ORIG x3000
+        LD R0, data
+        LDR R1, R0, #0
+        HALT
+data    .FILL x3010     ; past the end of the program
+.END";
+        let executable = assemble("test.asm", source).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&executable);
+        machine.track_uninitialized_reads();
+        let mut debugger = Debugger::new(machine, vec![executable]);
+        assert_eq!(
+            run(&mut debugger, "c"),
+            Ok(String::from(
+                "uninitialized read: test.asm:3:9 (x3001)  LDR R1, R0, #0
+    loaded x3010, which was never written
+test.asm:4:9 (x3002)  HALT"
+            ))
+        );
+        // without an OS, there's no trap vector table either
+        assert_eq!(
+            debugger.resume(),
+            Ok(Stop::UninitializedRead {
+                address: 0x3002,
+                reads: vec![UninitializedRead {
+                    address: 0x3002,
+                    target: 0x0025,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step(2), Ok(Stop::Stepped));
+        assert_eq!(debugger.machine().pc(), 0x3002);
+        assert_eq!(
+            run(&mut debugger, "step"),
+            Ok(String::from("test.asm:5:9 (x3003)  BRn loop"))
+        );
+    }
+
+    #[test]
+    fn test_inspecting_and_changing_state() {
+        let mut debugger = debugger();
+        run(&mut debugger, "set R3 x1234").unwrap();
+        run(&mut debugger, "set x4000 kbsr").unwrap();
+        run(&mut debugger, "set pc loop").unwrap();
+        assert_eq!(
+            run(&mut debugger, "registers"),
+            Ok(String::from(
+                "R0 x0000  R1 x0000  R2 x0000  R3 x1234  R4 x0000  R5 x0000  R6 x0000  R7 x0000  PC x3001  CC Z"
+            ))
+        );
+        assert_eq!(
+            run(&mut debugger, "x/9 x4000"),
+            Ok(String::from(
+                "x4000  x3008 x0000 x0000 x0000 x0000 x0000 x0000 x0000\nx4008  x0000"
+            ))
+        );
+        assert!(run(&mut debugger, "set R0 nowhere").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut debugger = debugger();
+        assert_eq!(
+            run(&mut debugger, "diff"),
+            Ok(String::from("nothing has changed"))
+        );
+        debugger.step(3).unwrap();
+        assert_eq!(
+            run(&mut debugger, "diff"),
+            Ok(String::from(
+                "R1 x0000 -> x0001 (#1)
+R2 x0000 -> xFFFE (#-2)
+PC x3000 -> x3003
+CC Z -> N"
+            ))
+        );
+
+        assert_eq!(
+            run(&mut debugger, "snapshot"),
+            Ok(String::from("took a snapshot at test.asm:5:9 (x3003)"))
+        );
+        run(&mut debugger, "set x4000 #1").unwrap();
+        run(&mut debugger, "set x4001 #2").unwrap();
+        debugger.step(1).unwrap();
+        assert_eq!(
+            run(&mut debugger, "diff"),
+            Ok(String::from(
+                "PC x3003 -> x3001\nx4000-x4001 x0000 x0000 -> x0001 x0002"
+            ))
+        );
+        assert_eq!(debugger.diff().word(0x4001), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_disassemble_around_pc() {
+        let mut debugger = debugger();
+        debugger.step(1).unwrap();
+        debugger.add_breakpoint(0x3003);
+        assert_eq!(
+            run(&mut debugger, "dis x3000 4"),
+            Ok(String::from(
+                "    x3000  x5260  AND R1, R1, #0
+loop:
+=>  x3001  x1261  ADD R1, R1, #1
+    x3002  x147D  ADD R2, R1, #-3
+  * x3003  x09FD  BRn loop"
+            ))
+        );
+        let around_pc = run(&mut debugger, "dis").unwrap();
+        assert!(around_pc.starts_with("    x2FFD"));
+        assert!(around_pc.ends_with("    x3005  x07FE  BRzp poll"));
+    }
+}
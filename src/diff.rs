@@ -0,0 +1,291 @@
+//! what changed in a machine between two points, like before and after a
+//! subroutine ran. memory that changed is grouped into runs of neighbouring
+//! words, so clearing a buffer is one change rather than hundreds
+//!
+//! ```
+//! use lc3_emulator::diff::{Snapshot, StateDiff};
+//! use lc3_emulator::Machine;
+//!
+//! let mut machine = Machine::new();
+//! let before = Snapshot::of(&machine);
+//! machine.set_register(2, 5);
+//! machine.set_memory(0x4000, 1);
+//! let diff = StateDiff::between(&before, &Snapshot::of(&machine));
+//! assert_eq!(diff.register(2), Some((0, 5)));
+//! assert_eq!(diff.to_lines(), ["R2 x0000 -> x0005 (#5)", "x4000 x0000 -> x0001"]);
+//! ```
+
+use crate::lc3::Machine;
+
+/// runs of changed memory up to this long are shown word by word
+const SHOWN_WORDS: usize = 4;
+
+/// everything about a machine a program can change, at one point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub registers: [u16; 8],
+    /// `'N'`, `'Z'` or `'P'`
+    pub condition: char,
+    /// every word of memory, by address
+    pub memory: Vec<u16>,
+}
+
+impl Snapshot {
+    pub fn of(machine: &Machine) -> Snapshot {
+        Snapshot {
+            pc: machine.pc(),
+            registers: machine.registers(),
+            condition: machine.condition(),
+            memory: (0..=0xFFFF)
+                .map(|address| machine.memory(address))
+                .collect(),
+        }
+    }
+}
+
+/// neighbouring words of memory that all changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryChange {
+    /// the address of the first word
+    pub start: u16,
+    pub old: Vec<u16>,
+    pub new: Vec<u16>,
+}
+
+impl MemoryChange {
+    /// the address of the last word
+    pub fn end(&self) -> u16 {
+        self.start.wrapping_add(self.old.len() as u16 - 1)
+    }
+
+    fn to_line(&self) -> String {
+        let words = |words: &[u16]| {
+            words
+                .iter()
+                .map(|word| format!("x{:04X}", word))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self.old.len() {
+            1 => format!(
+                "x{:04X} {} -> {}",
+                self.start,
+                words(&self.old),
+                words(&self.new)
+            ),
+            len if len <= SHOWN_WORDS => format!(
+                "x{:04X}-x{:04X} {} -> {}",
+                self.start,
+                self.end(),
+                words(&self.old),
+                words(&self.new)
+            ),
+            len => format!("x{:04X}-x{:04X} {} words", self.start, self.end(), len),
+        }
+    }
+}
+
+/// the differences between two snapshots of a machine. anything that's the
+/// same in both is left out
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+    /// each register that changed, with its old and new values
+    pub registers: Vec<(u16, u16, u16)>,
+    pub pc: Option<(u16, u16)>,
+    pub condition: Option<(char, char)>,
+    /// in order of address
+    pub memory: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> StateDiff {
+        let registers = (0..8)
+            .map(|index| (index, before.registers[index], after.registers[index]))
+            .filter(|(_, old, new)| old != new)
+            .map(|(index, old, new)| (index as u16, old, new))
+            .collect();
+        let words = before
+            .memory
+            .iter()
+            .zip(&after.memory)
+            .enumerate()
+            .map(|(address, (old, new))| (address as u16, *old, *new));
+        StateDiff {
+            registers,
+            pc: changed(before.pc, after.pc),
+            condition: changed(before.condition, after.condition),
+            memory: group(words),
+        }
+    }
+
+    /// leave out the memory at the addresses `ignore` picks, like memory that
+    /// belongs to the OS
+    pub fn ignoring(self, ignore: impl Fn(u16) -> bool) -> StateDiff {
+        let words = self.memory.iter().flat_map(|change| {
+            (0..change.old.len()).map(move |offset| {
+                (
+                    change.start.wrapping_add(offset as u16),
+                    change.old[offset],
+                    change.new[offset],
+                )
+            })
+        });
+        StateDiff {
+            memory: group(words.filter(|(address, _, _)| !ignore(*address))),
+            ..self
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &StateDiff::default()
+    }
+
+    /// the old and new values of a register, if it changed
+    pub fn register(&self, index: u16) -> Option<(u16, u16)> {
+        self.registers
+            .iter()
+            .find(|(register, _, _)| *register == index)
+            .map(|(_, old, new)| (*old, *new))
+    }
+
+    /// the old and new values of a word of memory, if it changed
+    pub fn word(&self, address: u16) -> Option<(u16, u16)> {
+        self.memory
+            .iter()
+            .find(|change| (change.start..=change.end()).contains(&address))
+            .map(|change| {
+                let offset = usize::from(address - change.start);
+                (change.old[offset], change.new[offset])
+            })
+    }
+
+    /// one line for each change, like `R1 x0000 -> xFFFE (#-2)` or
+    /// `x4000-x4003 x0000 x0000 x0000 x0000 -> x0001 x0002 x0003 x0004`.
+    /// longer runs of memory only give how many words changed
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .registers
+            .iter()
+            .map(|(index, old, new)| {
+                format!("R{} x{:04X} -> x{:04X} (#{})", index, old, new, *new as i16)
+            })
+            .collect();
+        if let Some((old, new)) = self.pc {
+            lines.push(format!("PC x{:04X} -> x{:04X}", old, new));
+        }
+        if let Some((old, new)) = self.condition {
+            lines.push(format!("CC {} -> {}", old, new));
+        }
+        lines.extend(self.memory.iter().map(MemoryChange::to_line));
+        lines
+    }
+}
+
+fn changed<T: PartialEq>(old: T, new: T) -> Option<(T, T)> {
+    if old != new {
+        Some((old, new))
+    } else {
+        None
+    }
+}
+
+/// put changed words, in order of address, into runs of neighbouring ones
+fn group(words: impl Iterator<Item = (u16, u16, u16)>) -> Vec<MemoryChange> {
+    let mut changes: Vec<MemoryChange> = Vec::new();
+    for (address, old, new) in words.filter(|(_, old, new)| old != new) {
+        match changes.last_mut() {
+            Some(last) if last.end().checked_add(1) == Some(address) => {
+                last.old.push(old);
+                last.new.push(new);
+            }
+            _ => changes.push(MemoryChange {
+                start: address,
+                old: vec![old],
+                new: vec![new],
+            }),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_between() {
+        let mut machine = Machine::new();
+        machine.load(
+            &assemble(
+                "test.asm",
+                ".ORIG x3000
+                LEA R1, buffer
+                AND R0, R0, #0
+                ADD R0, R0, #-1
+                STR R0, R1, #0
+                STR R0, R1, #1
+                STR R0, R1, #3
+                .FILL xD000
+        buffer  .BLKW 4",
+            )
+            .unwrap(),
+        );
+        let before = Snapshot::of(&machine);
+        for _ in 0..6 {
+            machine.step().unwrap();
+        }
+        let after = Snapshot::of(&machine);
+
+        let diff = StateDiff::between(&before, &after);
+        assert_eq!(
+            diff.to_lines(),
+            [
+                "R0 x0000 -> xFFFF (#-1)",
+                "R1 x0000 -> x3007 (#12295)",
+                "PC x3000 -> x3006",
+                "CC Z -> N",
+                "x3007-x3008 x0000 x0000 -> xFFFF xFFFF",
+                "x300A x0000 -> xFFFF",
+            ]
+        );
+        assert_eq!(diff.register(1), Some((0, 0x3007)));
+        assert_eq!(diff.register(2), None);
+        assert_eq!(diff.word(0x3008), Some((0, 0xFFFF)));
+        assert_eq!(diff.word(0x3009), None);
+        assert!(StateDiff::between(&after, &after).is_empty());
+
+        let diff = diff.ignoring(|address| address == 0x3007);
+        assert_eq!(
+            diff.memory,
+            [
+                MemoryChange {
+                    start: 0x3008,
+                    old: vec![0],
+                    new: vec![0xFFFF],
+                },
+                MemoryChange {
+                    start: 0x300A,
+                    old: vec![0],
+                    new: vec![0xFFFF],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_runs_of_memory() {
+        let mut machine = Machine::new();
+        let before = Snapshot::of(&machine);
+        for address in 0xFFF0..=0xFFFF {
+            machine.set_memory(address, 1);
+        }
+        machine.set_memory(0, 1);
+        // the end of memory doesn't run on into the start of it
+        assert_eq!(
+            StateDiff::between(&before, &Snapshot::of(&machine)).to_lines(),
+            ["x0000 x0000 -> x0001", "xFFF0-xFFFF 16 words"]
+        );
+    }
+}
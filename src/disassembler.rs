@@ -0,0 +1,269 @@
+use crate::assembler::Executable;
+use crate::instructions::Instruction;
+use std::collections::BTreeMap;
+
+/// the address every label points at, the other way around. when several labels
+/// share an address the first one alphabetically is used. labels made up by
+/// macro expansions can't be written in source, so they're left out
+pub fn labels(symbols: &BTreeMap<String, u16>) -> BTreeMap<u16, &str> {
+    let mut labels = BTreeMap::new();
+    for (name, address) in symbols {
+        if !name.contains('@') {
+            labels.entry(*address).or_insert(name.as_str());
+        }
+    }
+    labels
+}
+
+/// render a word as the instruction it encodes, as though it were at `address`.
+/// PC-relative operands name the label they point at if there is one. words
+/// that no instruction assembles to are rendered as a .FILL, so the result
+/// always assembles back into the same word
+pub fn instruction(word: u16, address: u16, labels: &BTreeMap<u16, &str>) -> String {
+    let instruction = Instruction::from(word);
+    if instruction.encode() != word {
+        return fill(word);
+    }
+
+    let target = |pc_offset: u16| {
+        let target = address.wrapping_add(1).wrapping_add(pc_offset);
+        match labels.get(&target) {
+            Some(label) => label.to_string(),
+            None => format!("#{}", pc_offset as i16),
+        }
+    };
+    match instruction {
+        Instruction::Add {
+            dest,
+            source_1,
+            source_2,
+        } => format!("ADD R{}, R{}, R{}", dest, source_1, source_2),
+        Instruction::AddImmediate {
+            dest,
+            source,
+            value,
+        } => format!("ADD R{}, R{}, #{}", dest, source, value as i16),
+        Instruction::And {
+            dest,
+            source_1,
+            source_2,
+        } => format!("AND R{}, R{}, R{}", dest, source_1, source_2),
+        Instruction::AndImmediate {
+            dest,
+            source,
+            value,
+        } => format!("AND R{}, R{}, #{}", dest, source, value as i16),
+        Instruction::Br {
+            n: false,
+            z: false,
+            p: false,
+            pc_offset: 0,
+        } => String::from("NOP"),
+        // a BR without any conditions can only be written as a NOP
+        Instruction::Br {
+            n: false,
+            z: false,
+            p: false,
+            ..
+        } => fill(word),
+        Instruction::Br { n, z, p, pc_offset } => format!(
+            "BR{}{}{} {}",
+            if n { "n" } else { "" },
+            if z { "z" } else { "" },
+            if p { "p" } else { "" },
+            target(pc_offset)
+        ),
+        Instruction::Jmp { base } => format!("JMP R{}", base),
+        Instruction::JmpT { base } => format!("JMPT R{}", base),
+        Instruction::Ret => String::from("RET"),
+        Instruction::Jsr { pc_offset } => format!("JSR {}", target(pc_offset)),
+        Instruction::JsrR { base } => format!("JSRR R{}", base),
+        Instruction::Ld { dest, pc_offset } => format!("LD R{}, {}", dest, target(pc_offset)),
+        Instruction::LdI { dest, pc_offset } => format!("LDI R{}, {}", dest, target(pc_offset)),
+        Instruction::LdR { dest, base, offset } => {
+            format!("LDR R{}, R{}, #{}", dest, base, offset as i16)
+        }
+        Instruction::Lea { dest, pc_offset } => format!("LEA R{}, {}", dest, target(pc_offset)),
+        Instruction::Not { dest, source } => format!("NOT R{}, R{}", dest, source),
+        Instruction::Rti => String::from("RTI"),
+        Instruction::St { source, pc_offset } => {
+            format!("ST R{}, {}", source, target(pc_offset))
+        }
+        Instruction::StI { source, pc_offset } => {
+            format!("STI R{}, {}", source, target(pc_offset))
+        }
+        Instruction::StR {
+            source,
+            base,
+            offset,
+        } => format!("STR R{}, R{}, #{}", source, base, offset as i16),
+        Instruction::Trap { vec } => match trap_alias(vec) {
+            Some(alias) => alias.to_string(),
+            None => format!("TRAP x{:02X}", vec),
+        },
+        Instruction::Illegal => fill(word),
+    }
+}
+
+fn fill(word: u16) -> String {
+    format!(".FILL x{:04X}", word)
+}
+
+/// the names the assembler accepts for the standard trap routines
+fn trap_alias(vec: u16) -> Option<&'static str> {
+    match vec {
+        0x20 => Some("GETC"),
+        0x21 => Some("OUT"),
+        0x22 => Some("PUTS"),
+        0x23 => Some("IN"),
+        0x24 => Some("PUTSP"),
+        0x25 => Some("HALT"),
+        _ => None,
+    }
+}
+
+/// render a whole executable as assembly that assembles back into it, with the
+/// address and word each line came from in a comment. if the executable knows
+/// which words are data, they're rendered as a .FILL rather than as instructions
+pub fn to_string(executable: &Executable) -> String {
+    let labels = labels(&executable.symbols);
+    let code = executable
+        .debug_info
+        .as_ref()
+        .map(|debug_info| &debug_info.code);
+    let width = labels
+        .values()
+        .map(|label| label.len() + 1)
+        .max()
+        .unwrap_or(0)
+        .max(8);
+    let line = |label: &str, text: &str, comment: &str| {
+        let line = format!("{:width$}{:24}{}", label, text, comment, width = width);
+        format!("{}\n", line.trim_end())
+    };
+
+    let mut disassembly = String::new();
+    for segment in &executable.segments {
+        disassembly.push_str(&line("", &format!(".ORIG x{:04X}", segment.origin), ""));
+        for (address, word) in segment.addressed_words() {
+            let text = if code.is_some_and(|code| !code.contains(&address)) {
+                fill(word)
+            } else {
+                instruction(word, address, &labels)
+            };
+            let label = labels.get(&address).copied().unwrap_or("");
+            let comment = format!("; x{:04X}  x{:04X}", address, word);
+            disassembly.push_str(&line(label, &text, &comment));
+        }
+
+        // a label can point just past the end of a segment
+        let end = segment.origin.wrapping_add(segment.words.len() as u16);
+        let starts_segment = executable
+            .segments
+            .iter()
+            .any(|segment| segment.origin == end);
+        if let Some(label) = labels.get(&end).filter(|_| !starts_segment) {
+            disassembly.push_str(&line(label, "", ""));
+        }
+    }
+    disassembly.push_str(&line("", ".END", ""));
+    disassembly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_instruction() {
+        let labels = vec![(0x3000, "loop")].into_iter().collect();
+        let cases = vec![
+            (0x1021, "ADD R0, R0, #1"),
+            (0x127F, "ADD R1, R1, #-1"),
+            (0x5482, "AND R2, R2, R2"),
+            (0x0BFE, "BRnp loop"),
+            (0x0E05, "BRnzp #5"),
+            (0x0000, "NOP"),
+            (0x0001, ".FILL x0001"),
+            (0x4FFE, "JSR loop"),
+            (0x4FFD, "JSR #-3"),
+            (0x25FF, "LD R2, #-1"),
+            (0x6E7F, "LDR R7, R1, #-1"),
+            (0x9A3F, "NOT R5, R0"),
+            // NOT with the low bits clear doesn't come from any instruction
+            (0x9A00, ".FILL x9A00"),
+            (0xC1C0, "RET"),
+            (0xC181, "JMPT R6"),
+            (0xF025, "HALT"),
+            (0xF026, "TRAP x26"),
+            (0xD000, ".FILL xD000"),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(instruction(word, 0x3001, &labels), expected);
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        let source = ".ORIG x3000
+loop ADD R0, R0, #-1
+     BRp loop
+     LEA R0, msg
+     HALT
+msg  .STRINGZ \"hi\"
+.ORIG x4000
+.FILL x1021
+end
+.END";
+        let executable = assemble("test.asm", source).unwrap();
+        assert_eq!(
+            to_string(&executable),
+            "        .ORIG x3000
+loop    ADD R0, R0, #-1         ; x3000  x103F
+        BRp loop                ; x3001  x03FE
+        LEA R0, msg             ; x3002  xE001
+        HALT                    ; x3003  xF025
+msg     .FILL x0068             ; x3004  x0068
+        .FILL x0069             ; x3005  x0069
+        .FILL x0000             ; x3006  x0000
+        .ORIG x4000
+        .FILL x1021             ; x4000  x1021
+end
+        .END
+"
+        );
+    }
+
+    #[test]
+    fn test_reassembles() {
+        let source = ".ORIG x3000
+start LD R1, count
+loop  JSR sub
+      ADD R1, R1, #-1
+      BRp loop
+      STI R0, far
+      TRAP x26
+      HALT
+sub   ADD R0, R0, #2
+      RET
+count .FILL 3
+far   .FILL xFE06
+.ORIG x4000
+      .FILL xD123
+      .FILL x9A00
+.END";
+        let executable = assemble("test.asm", source).unwrap();
+        let disassembly = to_string(&executable);
+        let reassembled = assemble("test.asm", &disassembly).unwrap();
+        assert_eq!(reassembled.segments, executable.segments);
+
+        // without debug info, data gets read as instructions where it can be
+        let bare = Executable {
+            debug_info: None,
+            ..executable
+        };
+        let reassembled = assemble("test.asm", &to_string(&bare)).unwrap();
+        assert_eq!(reassembled.segments, bare.segments);
+    }
+}
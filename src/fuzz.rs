@@ -0,0 +1,304 @@
+//! invariants for fuzzers and property tests to check, which panic when they
+//! don't hold. the cargo-fuzz targets in `fuzz/` feed them arbitrary input,
+//! and the tests here feed them every word and a few thousand pseudo-random
+//! programs. any `u16` decodes to an `Instruction`, so a word is all it takes
+//! to generate one
+
+use crate::assembler::{self, listing};
+use crate::disassembler;
+use crate::instructions::Instruction;
+use crate::lc3::Machine;
+
+/// decoding any word gives a well-formed instruction, and encoding that gives
+/// a word that decodes to the same instruction
+pub fn check_decode(word: u16) {
+    let instruction = Instruction::from(word);
+    assert!(
+        instruction.is_well_formed(),
+        "x{:04X} decodes to {:?}, which isn't well formed",
+        word,
+        instruction
+    );
+    assert_eq!(
+        Instruction::from(instruction.encode()),
+        instruction,
+        "x{:04X} doesn't decode the same after encoding it again",
+        word
+    );
+}
+
+/// encoding a well-formed instruction and decoding the word gives the same
+/// instruction back. ones that aren't well formed still encode to something
+pub fn check_encode(instruction: &Instruction) {
+    let word = instruction.encode();
+    if instruction.is_well_formed() {
+        assert_eq!(
+            &Instruction::from(word),
+            instruction,
+            "encodes to x{:04X}",
+            word
+        );
+    }
+}
+
+/// executing any word from any address, with anything in the registers,
+/// returns instead of panicking, even if it's an error
+pub fn check_execute(word: u16, pc: u16, registers: [u16; 8]) {
+    let mut machine = Machine::new();
+    machine.set_memory(pc, word);
+    machine.set_pc(pc);
+    for (index, value) in registers.iter().enumerate() {
+        machine.set_register(index as u16, *value);
+    }
+    let _ = machine.step();
+}
+
+/// assembling any source returns instead of panicking, and so does rendering
+/// the diagnostics, listing and disassembly that come out of it
+pub fn check_assemble(source: &str) {
+    let diagnostics = match assembler::assemble_with_diagnostics("fuzz.asm", source) {
+        Ok((executable, warnings)) => {
+            listing::to_string(&executable, source);
+            disassembler::to_string(&executable);
+            warnings
+        }
+        Err(errors) => errors,
+    };
+    for diagnostic in diagnostics {
+        diagnostic.pretty("fuzz.asm", source, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift, so the tests are the same every run without a dependency
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u16 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u16
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            usize::from(self.next()) % n
+        }
+
+        /// usually a value that fits in `size` bits, sign extended if it's
+        /// `signed`, but sometimes any word at all
+        fn field(&mut self, size: u16, signed: bool) -> u16 {
+            let value = self.next();
+            if self.below(8) == 0 {
+                return value;
+            }
+            let value = value & ((1 << size) - 1);
+            if signed && value & (1 << (size - 1)) != 0 {
+                value | !((1 << size) - 1)
+            } else {
+                value
+            }
+        }
+
+        fn instruction(&mut self) -> Instruction {
+            let mut register = || self.field(3, false);
+            let (a, b, c) = (register(), register(), register());
+            match self.below(21) {
+                0 => Instruction::Add {
+                    dest: a,
+                    source_1: b,
+                    source_2: c,
+                },
+                1 => Instruction::AddImmediate {
+                    dest: a,
+                    source: b,
+                    value: self.field(5, true),
+                },
+                2 => Instruction::And {
+                    dest: a,
+                    source_1: b,
+                    source_2: c,
+                },
+                3 => Instruction::AndImmediate {
+                    dest: a,
+                    source: b,
+                    value: self.field(5, true),
+                },
+                4 => Instruction::Br {
+                    n: a & 1 == 1,
+                    z: b & 1 == 1,
+                    p: c & 1 == 1,
+                    pc_offset: self.field(9, true),
+                },
+                5 => Instruction::Jmp { base: a },
+                6 => Instruction::JmpT { base: a },
+                7 => Instruction::Ret,
+                8 => Instruction::Jsr {
+                    pc_offset: self.field(11, true),
+                },
+                9 => Instruction::JsrR { base: a },
+                10 => Instruction::Ld {
+                    dest: a,
+                    pc_offset: self.field(9, true),
+                },
+                11 => Instruction::LdI {
+                    dest: a,
+                    pc_offset: self.field(9, true),
+                },
+                12 => Instruction::LdR {
+                    dest: a,
+                    base: b,
+                    offset: self.field(6, true),
+                },
+                13 => Instruction::Lea {
+                    dest: a,
+                    pc_offset: self.field(9, true),
+                },
+                14 => Instruction::Not { dest: a, source: b },
+                15 => Instruction::Rti,
+                16 => Instruction::St {
+                    source: a,
+                    pc_offset: self.field(9, true),
+                },
+                17 => Instruction::StI {
+                    source: a,
+                    pc_offset: self.field(9, true),
+                },
+                18 => Instruction::StR {
+                    source: a,
+                    base: b,
+                    offset: self.field(6, true),
+                },
+                19 => Instruction::Trap {
+                    vec: self.field(8, false),
+                },
+                _ => Instruction::Illegal,
+            }
+        }
+
+        /// a line of assembly that's mostly made of pieces of real
+        /// assembly, put together in ways that often don't make sense
+        fn line(&mut self) -> String {
+            const PIECES: &[&str] = &[
+                ".ORIG",
+                ".END",
+                ".FILL",
+                ".BLKW",
+                ".STRINGZ",
+                ".STRINGP",
+                ".MACRO",
+                ".END_MACRO",
+                ".IFDEF",
+                ".ELSE",
+                ".ENDIF",
+                "ADD",
+                "AND",
+                "BRnz",
+                "JSR",
+                "LDR",
+                "LEA",
+                "NOT",
+                "TRAP",
+                "HALT",
+                "PUTS",
+                "PUTSP",
+                "RET",
+                "R0",
+                "R7",
+                "R8",
+                "x3000",
+                "xFFFF",
+                "x10000",
+                "#-1",
+                "#99999",
+                "#-0",
+                "-0",
+                "x-0",
+                "b101",
+                "label",
+                "label:",
+                "%1",
+                "\"s\\n\"",
+                "\"",
+                ",",
+                ";",
+                " ",
+                "\t",
+                "é",
+                "\r",
+            ];
+            let mut line = String::new();
+            for _ in 0..self.below(8) {
+                line.push_str(PIECES[self.below(PIECES.len())]);
+                if self.below(2) == 0 {
+                    line.push(' ');
+                }
+            }
+            line
+        }
+    }
+
+    #[test]
+    fn test_decoding_every_word() {
+        for word in 0..=u16::MAX {
+            check_decode(word);
+        }
+    }
+
+    #[test]
+    fn test_encoding_random_instructions() {
+        let mut random = Random(0x2337);
+        let mut well_formed = 0;
+        for _ in 0..10_000 {
+            let instruction = random.instruction();
+            if instruction.is_well_formed() {
+                well_formed += 1;
+            }
+            check_encode(&instruction);
+        }
+        // make sure most of them get the round trip checked
+        assert!(well_formed > 5_000, "{}", well_formed);
+        assert!(!Instruction::Jmp { base: 7 }.is_well_formed());
+        assert!(!Instruction::Trap { vec: 0x100 }.is_well_formed());
+    }
+
+    #[test]
+    fn test_executing_every_word() {
+        let mut random = Random(0x2337);
+        for word in 0..=u16::MAX {
+            let mut registers = [0; 8];
+            for register in &mut registers {
+                *register = random.next();
+            }
+            // the edges of memory are where addresses wrap around
+            let pc = match word % 4 {
+                0 => 0xFFFF,
+                1 => 0,
+                _ => random.next(),
+            };
+            check_execute(word, pc, registers);
+        }
+    }
+
+    #[test]
+    fn test_assembling_random_sources() {
+        let mut random = Random(0x2337);
+        for _ in 0..2_000 {
+            let mut source = String::from(".ORIG x3000\n");
+            for _ in 0..random.below(10) {
+                source.push_str(&random.line());
+                source.push('\n');
+            }
+            check_assemble(&source);
+            // without the .ORIG, and cut off part of the way through
+            let cut = random.below(source.len()) + 1;
+            let cut = (cut..=source.len())
+                .find(|cut| source.is_char_boundary(*cut))
+                .unwrap();
+            check_assemble(&source[12..cut.max(12)]);
+        }
+    }
+}
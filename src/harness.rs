@@ -0,0 +1,308 @@
+use crate::assembler::{self, Executable, Options};
+use crate::lc3::{Machine, MachineError};
+
+/// how to set up a machine before running a program on it, for checking what
+/// the program does. everything has a default, so only what matters to a test
+/// has to be given:
+///
+/// ```
+/// use lc3_emulator::harness::{self, Setup};
+///
+/// let source = ".ORIG x3000\nGETC\nADD R3, R0, #-1\nHALT";
+/// let outcome = harness::run(source, &Setup {
+///     input: b"+".to_vec(),
+///     ..Setup::default()
+/// })
+/// .unwrap();
+/// outcome.check_halted().unwrap();
+/// outcome.check_register(3, 42).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Setup {
+    /// what diagnostics call the program
+    pub filename: String,
+    pub options: Options,
+    /// load the OS first, so the program can use traps. on by default
+    pub os: bool,
+    /// words to store in memory once the program is loaded, by address
+    pub memory: Vec<(u16, u16)>,
+    /// values for registers to start with, instead of 0
+    pub registers: Vec<(u16, u16)>,
+    /// keys for the program to read, in order
+    pub input: Vec<u8>,
+    /// the most instructions to execute before giving up on the program
+    pub step_limit: u64,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Setup {
+            filename: String::from("program.asm"),
+            options: Options::default(),
+            os: true,
+            memory: Vec::new(),
+            registers: Vec::new(),
+            input: Vec::new(),
+            step_limit: 1_000_000,
+        }
+    }
+}
+
+/// why a program stopped running
+#[derive(Debug, PartialEq)]
+pub enum Finish {
+    Halted,
+    /// it executed as many instructions as the step limit allows
+    StepLimit,
+    /// it was waiting for a key after reading all of the input
+    OutOfInput,
+    /// an instruction couldn't be executed
+    Failed(MachineError),
+}
+
+/// the state a program left the machine in, along with how it got there
+pub struct Outcome {
+    pub machine: Machine,
+    pub executable: Executable,
+    pub finish: Finish,
+    /// everything the program displayed
+    pub output: String,
+    /// how many instructions were executed, including in the OS
+    pub steps: u64,
+}
+
+/// assemble a program, set the machine up like `setup` says, and run the
+/// program until it stops. only fails if the program doesn't assemble
+pub fn run(source: &str, setup: &Setup) -> Result<Outcome, String> {
+    let executable = assembler::assemble_with_options(&setup.filename, source, &setup.options)
+        .map_err(|diagnostics| {
+            assembler::diagnostic::render(&diagnostics, &setup.filename, source, false)
+        })?
+        .0;
+
+    let mut machine = Machine::new();
+    if setup.os {
+        machine.load(&assembler::assemble("os.asm", include_str!("os.asm"))?);
+    }
+    machine.load(&executable);
+    for (address, value) in &setup.memory {
+        machine.set_memory(*address, *value);
+    }
+    for (register, value) in &setup.registers {
+        machine.set_register(*register, *value);
+    }
+    machine.push_input(&setup.input);
+
+    let mut steps = 0;
+    let finish = loop {
+        if !machine.is_running() {
+            break Finish::Halted;
+        }
+        if steps == setup.step_limit {
+            break Finish::StepLimit;
+        }
+        steps += 1;
+        if let Err(e) = machine.step() {
+            break Finish::Failed(e);
+        }
+        if machine.is_waiting_for_input() {
+            break Finish::OutOfInput;
+        }
+    };
+    let output = String::from_utf8_lossy(&machine.take_output()).into_owned();
+    Ok(Outcome {
+        machine,
+        executable,
+        finish,
+        output,
+        steps,
+    })
+}
+
+/// checks for what a program should have done, each failing with a message
+/// that explains what happened instead
+impl Outcome {
+    pub fn check_halted(&self) -> Result<(), String> {
+        match &self.finish {
+            Finish::Halted => Ok(()),
+            Finish::StepLimit => Err(format!(
+                "expected the program to halt, but it was still running after {} instructions",
+                self.steps
+            )),
+            Finish::OutOfInput => Err(String::from(
+                "expected the program to halt, but it was waiting for more input than it was given",
+            )),
+            Finish::Failed(e) => Err(format!(
+                "expected the program to halt, but the instruction at x{:04X} failed: {}",
+                e.address, e.message
+            )),
+        }
+    }
+
+    pub fn check_register(&self, register: u16, expected: u16) -> Result<(), String> {
+        let actual = self.machine.registers()[register as usize];
+        check(&format!("R{}", register), expected, actual)
+    }
+
+    pub fn check_memory(&self, address: u16, expected: u16) -> Result<(), String> {
+        check(
+            &format!("x{:04X}", address),
+            expected,
+            self.machine.memory(address),
+        )
+    }
+
+    /// check the word a label in the program points at
+    pub fn check_label(&self, label: &str, expected: u16) -> Result<(), String> {
+        let address = self.address_of(label)?;
+        check(label, expected, self.machine.memory(address))
+    }
+
+    /// check the condition code is `'N'`, `'Z'` or `'P'`
+    pub fn check_condition(&self, expected: char) -> Result<(), String> {
+        match self.machine.condition() {
+            actual if actual == expected => Ok(()),
+            actual => Err(format!(
+                "expected CC to be {}, but it was {}",
+                expected, actual
+            )),
+        }
+    }
+
+    pub fn check_output_contains(&self, expected: &str) -> Result<(), String> {
+        if self.output.contains(expected) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected the output to contain {:?}, but it was {:?}",
+                expected, self.output
+            ))
+        }
+    }
+
+    /// the address of a label in the program
+    pub fn address_of(&self, label: &str) -> Result<u16, String> {
+        self.executable
+            .symbols
+            .get(label)
+            .copied()
+            .ok_or_else(|| format!("the program doesn't have a label called {}", label))
+    }
+}
+
+fn check(name: &str, expected: u16, actual: u16) -> Result<(), String> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {} to be {} (x{:04X}), but it was {} (x{:04X})",
+            name, expected as i16, expected, actual as i16, actual
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUM: &str = ".ORIG x3000
+        LEA R1, numbers
+        AND R3, R3, #0
+loop    LDR R2, R1, #0
+        BRz done
+        ADD R3, R3, R2
+        ADD R1, R1, #1
+        BR loop
+done    ST R3, total
+        LEA R0, message
+        PUTS
+        HALT
+total   .FILL 0
+message .STRINGZ \"DONE\"
+numbers .BLKW 4";
+
+    #[test]
+    fn test_run() {
+        let numbers = 0x3000 + 17;
+        let outcome = run(
+            SUM,
+            &Setup {
+                memory: vec![(numbers, 40), (numbers + 1, 2)],
+                ..Setup::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(outcome.finish, Finish::Halted);
+        assert_eq!(outcome.check_halted(), Ok(()));
+        assert_eq!(outcome.check_register(3, 42), Ok(()));
+        assert_eq!(outcome.check_label("total", 42), Ok(()));
+        assert_eq!(outcome.check_memory(numbers, 40), Ok(()));
+        assert_eq!(outcome.check_condition('Z'), Ok(()));
+        assert_eq!(outcome.check_output_contains("DONE"), Ok(()));
+
+        assert_eq!(
+            outcome.check_register(3, 41),
+            Err(String::from(
+                "expected R3 to be 41 (x0029), but it was 42 (x002A)"
+            ))
+        );
+        assert_eq!(
+            outcome.check_output_contains("done"),
+            Err(String::from(
+                "expected the output to contain \"done\", but it was \"DONE\""
+            ))
+        );
+        assert!(outcome.check_label("nope", 0).is_err());
+    }
+
+    #[test]
+    fn test_programs_that_dont_halt() {
+        let limited = run(
+            ".ORIG x3000\nloop BR loop",
+            &Setup {
+                step_limit: 100,
+                ..Setup::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(limited.finish, Finish::StepLimit);
+        assert_eq!(limited.steps, 100);
+        assert_eq!(
+            limited.check_halted(),
+            Err(String::from(
+                "expected the program to halt, but it was still running after 100 instructions"
+            ))
+        );
+
+        let setup = Setup {
+            input: b"a".to_vec(),
+            ..Setup::default()
+        };
+        let hungry = run(
+            ".ORIG x3000\nGETC\nST R0, key\nGETC\nHALT\nkey .BLKW 1",
+            &setup,
+        )
+        .unwrap();
+        assert_eq!(hungry.finish, Finish::OutOfInput);
+        assert_eq!(hungry.check_label("key", u16::from(b'a')), Ok(()));
+
+        let failed = run(
+            ".ORIG x3000\nADD R0, R0, #1\n.FILL xD000",
+            &Setup {
+                os: false,
+                ..Setup::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            failed.finish,
+            Finish::Failed(MachineError {
+                message: String::from("illegal opcode"),
+                address: 0x3001,
+            })
+        );
+        assert_eq!(failed.steps, 2);
+
+        assert!(run(".ORIG x3000\nADD R0", &Setup::default()).is_err());
+    }
+}
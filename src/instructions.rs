@@ -14,7 +14,7 @@ const OPCODE_STI: u16 = 0b1011;
 const OPCODE_STR: u16 = 0b0111;
 const OPCODE_TRAP: u16 = 0b1111;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
     Add {
         dest: u16,
@@ -45,6 +45,10 @@ pub enum Instruction {
     Jmp {
         base: u16,
     },
+    /// a JMP that also clears the privilege bit, for an OS to jump to user code
+    JmpT {
+        base: u16,
+    },
     Ret,
     Jsr {
         pc_offset: u16,
@@ -113,7 +117,15 @@ fn sign_extend(n: u16, size: u16) -> u16 {
     }
 }
 
+/// the inverse of `sign_extend`: keep only the lowest `size` bits of `n`
+fn truncate(n: u16, size: u16) -> u16 {
+    n & ((1 << size) - 1)
+}
+
 impl Instruction {
+    /// decode a word. every word decodes to something, if only `Illegal`, and
+    /// every well-formed instruction is decoded from at least one word, so
+    /// any `u16` a fuzzer or property test comes up with makes an instruction
     pub fn from(instruction: u16) -> Instruction {
         let opcode = slice_bits(instruction, 15, 12);
         match opcode {
@@ -155,7 +167,9 @@ impl Instruction {
             },
             OPCODE_JMP => {
                 let base = slice_bits(instruction, 8, 6);
-                if base == 0b111 {
+                if is_bit_set(instruction, 0) {
+                    Instruction::JmpT { base }
+                } else if base == 0b111 {
                     Instruction::Ret
                 } else {
                     Instruction::Jmp { base }
@@ -213,9 +227,157 @@ impl Instruction {
             _ => Instruction::Illegal,
         }
     }
+
+    /// true if every field fits in the bits it's encoded in, with offsets
+    /// and immediates sign extended, so decoding what it encodes to gives the
+    /// same instruction back. a JMP to R7 is a RET, so only `Ret` is well
+    /// formed for it. everything `from` decodes is well formed
+    pub fn is_well_formed(&self) -> bool {
+        let register = |register: u16| register < 8;
+        let signed = |n: u16, size: u16| sign_extend(truncate(n, size), size) == n;
+        match *self {
+            Instruction::Add {
+                dest,
+                source_1,
+                source_2,
+            }
+            | Instruction::And {
+                dest,
+                source_1,
+                source_2,
+            } => register(dest) && register(source_1) && register(source_2),
+            Instruction::AddImmediate {
+                dest,
+                source,
+                value,
+            }
+            | Instruction::AndImmediate {
+                dest,
+                source,
+                value,
+            } => register(dest) && register(source) && signed(value, 5),
+            Instruction::Br { pc_offset, .. } => signed(pc_offset, 9),
+            Instruction::Jmp { base } => register(base) && base != 0b111,
+            Instruction::JmpT { base } | Instruction::JsrR { base } => register(base),
+            Instruction::Jsr { pc_offset } => signed(pc_offset, 11),
+            Instruction::Ld { dest, pc_offset }
+            | Instruction::LdI { dest, pc_offset }
+            | Instruction::Lea { dest, pc_offset } => register(dest) && signed(pc_offset, 9),
+            Instruction::St { source, pc_offset } | Instruction::StI { source, pc_offset } => {
+                register(source) && signed(pc_offset, 9)
+            }
+            Instruction::LdR { dest, base, offset }
+            | Instruction::StR {
+                source: dest,
+                base,
+                offset,
+            } => register(dest) && register(base) && signed(offset, 6),
+            Instruction::Not { dest, source } => register(dest) && register(source),
+            Instruction::Trap { vec } => vec < 0x100,
+            Instruction::Ret | Instruction::Rti | Instruction::Illegal => true,
+        }
+    }
+
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Add {
+                dest,
+                source_1,
+                source_2,
+            } => {
+                OPCODE_ADD << 12
+                    | truncate(dest, 3) << 9
+                    | truncate(source_1, 3) << 6
+                    | truncate(source_2, 3)
+            }
+            Instruction::AddImmediate {
+                dest,
+                source,
+                value,
+            } => {
+                OPCODE_ADD << 12
+                    | truncate(dest, 3) << 9
+                    | truncate(source, 3) << 6
+                    | 1 << 5
+                    | truncate(value, 5)
+            }
+            Instruction::And {
+                dest,
+                source_1,
+                source_2,
+            } => {
+                OPCODE_AND << 12
+                    | truncate(dest, 3) << 9
+                    | truncate(source_1, 3) << 6
+                    | truncate(source_2, 3)
+            }
+            Instruction::AndImmediate {
+                dest,
+                source,
+                value,
+            } => {
+                OPCODE_AND << 12
+                    | truncate(dest, 3) << 9
+                    | truncate(source, 3) << 6
+                    | 1 << 5
+                    | truncate(value, 5)
+            }
+            Instruction::Br { n, z, p, pc_offset } => {
+                OPCODE_BR << 12
+                    | (n as u16) << 11
+                    | (z as u16) << 10
+                    | (p as u16) << 9
+                    | truncate(pc_offset, 9)
+            }
+            Instruction::Jmp { base } => OPCODE_JMP << 12 | truncate(base, 3) << 6,
+            Instruction::JmpT { base } => OPCODE_JMP << 12 | truncate(base, 3) << 6 | 1,
+            Instruction::Ret => OPCODE_JMP << 12 | 0b111 << 6,
+            Instruction::Jsr { pc_offset } => OPCODE_JSR << 12 | 1 << 11 | truncate(pc_offset, 11),
+            Instruction::JsrR { base } => OPCODE_JSR << 12 | truncate(base, 3) << 6,
+            Instruction::Ld { dest, pc_offset } => {
+                OPCODE_LD << 12 | truncate(dest, 3) << 9 | truncate(pc_offset, 9)
+            }
+            Instruction::LdI { dest, pc_offset } => {
+                OPCODE_LDI << 12 | truncate(dest, 3) << 9 | truncate(pc_offset, 9)
+            }
+            Instruction::LdR { dest, base, offset } => {
+                OPCODE_LDR << 12
+                    | truncate(dest, 3) << 9
+                    | truncate(base, 3) << 6
+                    | truncate(offset, 6)
+            }
+            Instruction::Lea { dest, pc_offset } => {
+                OPCODE_LEA << 12 | truncate(dest, 3) << 9 | truncate(pc_offset, 9)
+            }
+            Instruction::Not { dest, source } => {
+                OPCODE_NOT << 12 | truncate(dest, 3) << 9 | truncate(source, 3) << 6 | 0b11_1111
+            }
+            Instruction::Rti => OPCODE_RTI << 12,
+            Instruction::St { source, pc_offset } => {
+                OPCODE_ST << 12 | truncate(source, 3) << 9 | truncate(pc_offset, 9)
+            }
+            Instruction::StI { source, pc_offset } => {
+                OPCODE_STI << 12 | truncate(source, 3) << 9 | truncate(pc_offset, 9)
+            }
+            Instruction::StR {
+                source,
+                base,
+                offset,
+            } => {
+                OPCODE_STR << 12
+                    | truncate(source, 3) << 9
+                    | truncate(base, 3) << 6
+                    | truncate(offset, 6)
+            }
+            Instruction::Trap { vec } => OPCODE_TRAP << 12 | truncate(vec, 8),
+            // 0b1101 is the reserved opcode
+            Instruction::Illegal => 0b1101 << 12,
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
 
@@ -317,6 +479,11 @@ mod tests {
 
         assert_eq!(Instruction::from(0b1100_000_111_000000), Instruction::Ret,);
 
+        assert_eq!(
+            Instruction::from(0b1100_000_111_000001),
+            Instruction::JmpT { base: 0b111 }
+        );
+
         assert_eq!(
             Instruction::from(0b0100_1_01000000001),
             Instruction::Jsr {
@@ -402,4 +569,44 @@ mod tests {
             Instruction::Trap { vec: 0b1111_1111 },
         );
     }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate(0b1111111111110001, 5), 0b10001);
+        assert_eq!(truncate(0b1001, 5), 0b1001);
+        assert_eq!(truncate(sign_extend(0b1_1000_0001, 9), 9), 0b1_1000_0001);
+    }
+
+    #[test]
+    fn test_encode_instruction() {
+        let words = [
+            0b0001_100_010_0_00_001,
+            0b0001_100_010_1_10001,
+            0b0101_100_010_0_00_001,
+            0b0101_100_010_1_01001,
+            0b0000_000_000000000,
+            0b0000_111_000000000,
+            0b0000_010_111111110,
+            0b1100_000_010_000000,
+            0b1100_000_111_000000,
+            0b1100_000_111_000001,
+            0b0100_1_01000000001,
+            0b0100_1_11111111111,
+            0b0100_0_00_010_000000,
+            0b0010_010_010000001,
+            0b1010_010_010000001,
+            0b0110_010_010_100000,
+            0b1110_010_010100000,
+            0b1001_010_010_111111,
+            0b1000_000000000000,
+            0b0011_010_100000000,
+            0b1011_010_100000000,
+            0b0111_010_010_100000,
+            0b1111_0000_1111_1111,
+            0b1101_0000_0000_0000,
+        ];
+        for word in words.iter() {
+            assert_eq!(Instruction::from(*word).encode(), *word, "{:016b}", word);
+        }
+    }
 }
@@ -1,9 +1,72 @@
+#[cfg(feature = "std")]
+use crate::assembler::Executable;
 use crate::instructions::Instruction;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// keyboard status register
+const KBSR: u16 = 0xFE00;
+/// keyboard data register
+const KBDR: u16 = 0xFE02;
+/// display status register
+const DSR: u16 = 0xFE04;
+/// display data register
+const DDR: u16 = 0xFE06;
+/// machine control register
+const MCR: u16 = 0xFFFE;
+
+#[derive(Debug, PartialEq)]
+pub struct MachineError {
+    pub message: String,
+    /// address of the instruction that was executing
+    pub address: u16,
+}
+
+/// a register or word of memory that can be watched
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Watched {
+    Register(u16),
+    Memory(u16),
+}
+
+/// which kinds of access to a watched location get recorded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watch {
+    pub reads: bool,
+    pub writes: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// an instruction reading or writing a watched location
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Access {
+    pub watched: Watched,
+    pub kind: AccessKind,
+    /// the value before the access
+    pub old: u16,
+    /// the value after the access, which is the same as `old` for a read
+    pub new: u16,
+}
+
+/// an instruction loading a word of memory that nothing had written, which is
+/// usually a load from the wrong label
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UninitializedRead {
+    /// address of the instruction that did the load
+    pub address: u16,
+    /// the word it loaded
+    pub target: u16,
+}
 
-#[allow(dead_code)]
 pub struct Machine {
     /// addressable memory from 0x0000 -> 0xFFFF
-    memory: [u16; 0xFFFF],
+    memory: [u16; 0x10000],
     /// general purpose registers
     regs: [u16; 8],
     /// program counter
@@ -14,67 +77,479 @@ pub struct Machine {
     cc_pos: u16,
     /// zero result condition code
     cc_zero: u16,
+    /// clock enable, bit 15 of the MCR. clearing it halts the machine
+    running: bool,
+    /// key presses that haven't been read through the KBDR yet
+    input: VecDeque<u8>,
+    /// characters written to the DDR that haven't been displayed yet
+    output: Vec<u8>,
+    /// set when the last instruction polled the KBSR and there was nothing to read
+    waiting_for_input: bool,
+    /// how many times the instruction at each address has been executed, if
+    /// coverage is being tracked
+    execution_counts: Option<BTreeMap<u16, u64>>,
+    /// locations whose accesses get recorded
+    watches: BTreeMap<Watched, Watch>,
+    /// accesses to watched locations since they were last taken
+    accesses: Vec<Access>,
+    /// a bit for every word of memory, set once the word has been loaded,
+    /// stored or set
+    written: [u64; 0x10000 / 64],
+    /// loads of words that were never written since they were last taken, if
+    /// they're being tracked
+    uninitialized_reads: Option<Vec<UninitializedRead>>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine::new()
+    }
 }
 
 impl Machine {
     pub fn new() -> Machine {
         Machine {
-            memory: [0; 0xFFFF],
+            memory: [0; 0x10000],
             regs: [0; 8],
             pc: 0,
             cc_neg: 0,
             cc_pos: 0,
-            cc_zero: 0,
+            // the LC-3 starts out with Z set, so a bare BR is unconditional
+            cc_zero: 1,
+            running: true,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            waiting_for_input: false,
+            execution_counts: None,
+            watches: BTreeMap::new(),
+            accesses: Vec::new(),
+            written: [0; 0x10000 / 64],
+            uninitialized_reads: None,
+        }
+    }
+
+    /// start counting how many times each instruction is executed
+    pub fn track_coverage(&mut self) {
+        self.execution_counts.get_or_insert_with(BTreeMap::new);
+    }
+
+    pub fn execution_counts(&self) -> Option<&BTreeMap<u16, u64>> {
+        self.execution_counts.as_ref()
+    }
+
+    /// start recording loads from memory that nothing has written, not even
+    /// the loader. fetching an instruction doesn't count as a load, and
+    /// neither does reading a device register
+    pub fn track_uninitialized_reads(&mut self) {
+        self.uninitialized_reads.get_or_insert_with(Vec::new);
+    }
+
+    /// every load from memory that was never written since this was last
+    /// called, if they're being tracked
+    pub fn take_uninitialized_reads(&mut self) -> Vec<UninitializedRead> {
+        self.uninitialized_reads
+            .as_mut()
+            .map(core::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// true once a word of memory has been loaded, stored or set. filling
+    /// memory doesn't count
+    pub fn is_initialized(&self, address: u16) -> bool {
+        self.written[address as usize / 64] & (1 << (address % 64)) != 0
+    }
+
+    fn mark_initialized(&mut self, address: u16) {
+        self.written[address as usize / 64] |= 1 << (address % 64);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// jump to `pc`, like `load` does to an executable's entry
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// R0 to R7
+    pub fn registers(&self) -> [u16; 8] {
+        self.regs
+    }
+
+    /// start register `reg` off with a value, leaving the condition codes alone
+    pub fn set_register(&mut self, reg: u16, value: u16) {
+        self.regs[reg as usize] = value;
+    }
+
+    /// which condition code is set: `'N'`, `'Z'` or `'P'`
+    pub fn condition(&self) -> char {
+        if self.cc_neg == 1 {
+            'N'
+        } else if self.cc_pos == 1 {
+            'P'
+        } else {
+            'Z'
+        }
+    }
+
+    /// read a word of memory without the side effects a load would have on
+    /// memory-mapped devices
+    pub fn memory(&self, address: u16) -> u16 {
+        self.memory[address as usize]
+    }
+
+    /// write a word of memory without the side effects a store would have on
+    /// memory-mapped devices
+    pub fn set_memory(&mut self, address: u16, value: u16) {
+        self.memory[address as usize] = value;
+        self.mark_initialized(address);
+    }
+
+    /// set every word of memory to `value`, as though that's what it powered
+    /// on with. anything loaded afterwards is loaded over it
+    pub fn fill_memory(&mut self, value: u16) {
+        self.memory = [value; 0x10000];
+    }
+
+    /// record the reads and/or writes the program makes to a location. watching
+    /// a location that's already watched replaces its watch
+    pub fn watch(&mut self, watched: Watched, watch: Watch) {
+        self.watches.insert(watched, watch);
+    }
+
+    /// stop recording accesses to a location, returning false if it wasn't watched
+    pub fn unwatch(&mut self, watched: Watched) -> bool {
+        self.watches.remove(&watched).is_some()
+    }
+
+    pub fn watches(&self) -> &BTreeMap<Watched, Watch> {
+        &self.watches
+    }
+
+    /// every access to a watched location since this was last called, in the
+    /// order they happened. fetching an instruction doesn't count as reading it
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        core::mem::take(&mut self.accesses)
+    }
+
+    /// note down an access if its location is being watched for that kind of access
+    fn record(&mut self, watched: Watched, kind: AccessKind, old: u16, new: u16) {
+        let recorded = match (self.watches.get(&watched), kind) {
+            (Some(watch), AccessKind::Read) => watch.reads,
+            (Some(watch), AccessKind::Write) => watch.writes,
+            (None, _) => false,
+        };
+        if recorded {
+            self.accesses.push(Access {
+                watched,
+                kind,
+                old,
+                new,
+            });
+        }
+    }
+
+    /// queue up key presses for the program to read through the KBDR
+    pub fn push_input(&mut self, keys: &[u8]) {
+        self.input.extend(keys);
+        self.waiting_for_input = false;
+    }
+
+    /// everything the program has written to the DDR since this was last called
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+
+    /// true when the last instruction polled the KBSR and there wasn't a key to
+    /// read
+    pub fn is_waiting_for_input(&self) -> bool {
+        self.waiting_for_input
+    }
+
+    /// false once the program has halted by clearing the MCR
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// set the clock enable bit in the MCR again after the program has halted,
+    /// so the machine can carry on
+    pub fn start_clock(&mut self) {
+        self.running = true;
+    }
+
+    /// copy every segment of an executable into memory at its origin, and point
+    /// the PC at its entry
+    #[cfg(feature = "std")]
+    pub fn load(&mut self, executable: &Executable) {
+        for segment in &executable.segments {
+            self.load_words(segment.origin, &segment.words);
+        }
+        self.pc = executable.entry;
+    }
+
+    /// copy machine code into memory starting at `origin`, wrapping around past
+    /// xFFFF, without moving the PC. this is how to load a program without the
+    /// assembler, which needs std
+    pub fn load_words(&mut self, origin: u16, words: &[u16]) {
+        for (offset, word) in words.iter().enumerate() {
+            self.set_memory(origin.wrapping_add(offset as u16), *word);
         }
     }
 
-    fn get_reg(&self, reg: u16) -> u16 {
-        self.regs[reg as usize]
+    fn get_reg(&mut self, reg: u16) -> u16 {
+        let value = self.regs[reg as usize];
+        self.record(Watched::Register(reg), AccessKind::Read, value, value);
+        value
     }
 
     fn set_reg(&mut self, reg: u16, val: u16) {
+        let old = self.regs[reg as usize];
         self.regs[reg as usize] = val;
+        self.record(Watched::Register(reg), AccessKind::Write, old, val);
     }
 
-    fn execute(&mut self, instruction: Instruction) {
-        if let Instruction::Add {
-            dest,
-            source_1,
-            source_2,
-        } = instruction
-        {
-            let value = self.get_reg(source_1) + self.get_reg(source_2);
-            self.set_reg(dest, value);
+    /// set a register and update the condition codes to match its new value
+    fn set_reg_cc(&mut self, reg: u16, val: u16) {
+        self.set_reg(reg, val);
+        self.cc_neg = (val >> 15) & 1;
+        self.cc_zero = (val == 0) as u16;
+        self.cc_pos = (val != 0 && val >> 15 == 0) as u16;
+    }
+
+    fn read_memory(&mut self, address: u16) -> u16 {
+        let device = matches!(address, KBSR | KBDR | DSR | MCR);
+        if !device && !self.is_initialized(address) {
+            // the PC has already moved past the instruction doing the load
+            let instruction = self.pc.wrapping_sub(1);
+            if let Some(reads) = &mut self.uninitialized_reads {
+                reads.push(UninitializedRead {
+                    address: instruction,
+                    target: address,
+                });
+            }
         }
+        let value = self.read_device_or_memory(address);
+        self.record(Watched::Memory(address), AccessKind::Read, value, value);
+        value
     }
 
-    pub fn run(&mut self, instructions: &[u16]) {
-        for instruction in instructions {
-            let instruction = Instruction::from(*instruction);
-            self.execute(instruction);
+    /// read memory without recording it as an access, like fetching an
+    /// instruction. devices still see the read
+    fn read_device_or_memory(&mut self, address: u16) -> u16 {
+        match address {
+            KBSR => {
+                if self.input.is_empty() {
+                    self.waiting_for_input = true;
+                    0
+                } else {
+                    1 << 15
+                }
+            }
+            KBDR => self.input.pop_front().map_or(0, u16::from),
+            // the display is always ready
+            DSR => 1 << 15,
+            MCR => (self.running as u16) << 15,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    fn write_memory(&mut self, address: u16, val: u16) {
+        let old = self.memory[address as usize];
+        self.record(Watched::Memory(address), AccessKind::Write, old, val);
+        match address {
+            DDR => self.output.push(val as u8),
+            MCR => self.running = val >> 15 == 1,
+            _ => self.set_memory(address, val),
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), String> {
+        match instruction {
+            Instruction::Add {
+                dest,
+                source_1,
+                source_2,
+            } => {
+                let value = self.get_reg(source_1).wrapping_add(self.get_reg(source_2));
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::AddImmediate {
+                dest,
+                source,
+                value,
+            } => {
+                let value = self.get_reg(source).wrapping_add(value);
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::And {
+                dest,
+                source_1,
+                source_2,
+            } => {
+                let value = self.get_reg(source_1) & self.get_reg(source_2);
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::AndImmediate {
+                dest,
+                source,
+                value,
+            } => {
+                let value = self.get_reg(source) & value;
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::Br { n, z, p, pc_offset } => {
+                if (n && self.cc_neg == 1) || (z && self.cc_zero == 1) || (p && self.cc_pos == 1) {
+                    self.pc = self.pc.wrapping_add(pc_offset);
+                }
+            }
+            // the machine only runs in user mode, so there's no privilege to clear
+            Instruction::Jmp { base } | Instruction::JmpT { base } => self.pc = self.get_reg(base),
+            Instruction::Ret => self.pc = self.get_reg(7),
+            Instruction::Jsr { pc_offset } => {
+                let return_address = self.pc;
+                self.pc = self.pc.wrapping_add(pc_offset);
+                self.set_reg(7, return_address);
+            }
+            Instruction::JsrR { base } => {
+                let return_address = self.pc;
+                self.pc = self.get_reg(base);
+                self.set_reg(7, return_address);
+            }
+            Instruction::Ld { dest, pc_offset } => {
+                let value = self.read_memory(self.pc.wrapping_add(pc_offset));
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::LdI { dest, pc_offset } => {
+                let address = self.read_memory(self.pc.wrapping_add(pc_offset));
+                let value = self.read_memory(address);
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::LdR { dest, base, offset } => {
+                let address = self.get_reg(base).wrapping_add(offset);
+                let value = self.read_memory(address);
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::Lea { dest, pc_offset } => {
+                self.set_reg(dest, self.pc.wrapping_add(pc_offset));
+            }
+            Instruction::Not { dest, source } => {
+                let value = !self.get_reg(source);
+                self.set_reg_cc(dest, value);
+            }
+            Instruction::St { source, pc_offset } => {
+                let value = self.get_reg(source);
+                self.write_memory(self.pc.wrapping_add(pc_offset), value);
+            }
+            Instruction::StI { source, pc_offset } => {
+                let address = self.read_memory(self.pc.wrapping_add(pc_offset));
+                let value = self.get_reg(source);
+                self.write_memory(address, value);
+            }
+            Instruction::StR {
+                source,
+                base,
+                offset,
+            } => {
+                let address = self.get_reg(base).wrapping_add(offset);
+                let value = self.get_reg(source);
+                self.write_memory(address, value);
+            }
+            Instruction::Trap { vec } => {
+                self.set_reg(7, self.pc);
+                self.pc = self.read_memory(vec);
+            }
+            Instruction::Rti => {
+                return Err(String::from(
+                    "RTI is not supported, since the machine only runs in user mode",
+                ))
+            }
+            Instruction::Illegal => return Err(String::from("illegal opcode")),
         }
+
+        Ok(())
+    }
+
+    /// fetch, decode and execute a single instruction
+    pub fn step(&mut self) -> Result<(), MachineError> {
+        let address = self.pc;
+        self.waiting_for_input = false;
+        if let Some(counts) = &mut self.execution_counts {
+            *counts.entry(address).or_insert(0) += 1;
+        }
+        let word = self.read_device_or_memory(address);
+        self.pc = self.pc.wrapping_add(1);
+        self.execute(Instruction::from(word))
+            .map_err(|message| MachineError { message, address })
     }
 }
 
+/// tests that don't need the assembler, so they also run without `std`
 #[cfg(test)]
+mod no_std_tests {
+    use super::{Machine, MachineError};
+    use alloc::string::String;
+
+    #[test]
+    fn test_load_words_and_step() {
+        let mut machine = Machine::new();
+        machine.load_words(
+            0x3000,
+            &[
+                0x5020, // AND R0, R0, #0
+                0x5260, // AND R1, R1, #0
+                0x1263, // ADD R1, R1, #3
+                0x1001, // ADD R0, R0, R1
+                0x127F, // ADD R1, R1, #-1
+                0x03FD, // BRp x3003
+                0xD000, // an illegal opcode
+            ],
+        );
+        assert_eq!(machine.pc(), 0);
+        machine.set_pc(0x3000);
+        while machine.pc() != 0x3006 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.registers()[..2], [6, 0]);
+        assert_eq!(machine.condition(), 'Z');
+        assert_eq!(
+            machine.step(),
+            Err(MachineError {
+                message: String::from("illegal opcode"),
+                address: 0x3006,
+            })
+        );
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use super::{Instruction, Machine};
+    use super::{
+        Access, AccessKind, Instruction, Machine, MachineError, UninitializedRead, Watch, Watched,
+    };
+    use crate::assembler::assemble;
 
     fn run_instructions(machine: &mut Machine, instructions: Vec<Instruction>) {
         for instruction in instructions {
-            machine.execute(instruction);
+            machine.execute(instruction).unwrap();
         }
     }
 
     fn from_regs(regs: [u16; 8]) -> Machine {
-        Machine {
-            memory: [0; 0xFFFF],
-            regs,
-            pc: 0,
-            cc_neg: 0,
-            cc_pos: 0,
-            cc_zero: 0,
+        let mut machine = Machine::new();
+        machine.regs = regs;
+        machine
+    }
+
+    /// assemble and run a program until it halts by clearing the MCR
+    fn run_source(source: &str, input: &[u8]) -> Result<Machine, MachineError> {
+        let mut machine = Machine::new();
+        machine.load(&assemble("test.asm", source).unwrap());
+        machine.input.extend(input);
+        while machine.running {
+            machine.step()?;
         }
+        Ok(machine)
     }
 
     #[test]
@@ -90,4 +565,303 @@ mod tests {
         );
         assert_eq!(machine.regs[0], 3);
     }
+
+    #[test]
+    fn test_jmpt() {
+        let mut machine = from_regs([0, 0, 0, 0x4000, 0, 0, 0, 0]);
+        run_instructions(&mut machine, vec![Instruction::JmpT { base: 3 }]);
+        assert_eq!(machine.pc, 0x4000);
+    }
+
+    #[test]
+    fn test_condition_codes() {
+        let mut machine = from_regs([0, 0xFFFF, 0, 0, 0, 0, 0, 0]);
+        run_instructions(
+            &mut machine,
+            vec![Instruction::AddImmediate {
+                dest: 0,
+                source: 1,
+                value: 0,
+            }],
+        );
+        assert_eq!((machine.cc_neg, machine.cc_zero, machine.cc_pos), (1, 0, 0));
+
+        run_instructions(
+            &mut machine,
+            vec![Instruction::AndImmediate {
+                dest: 0,
+                source: 1,
+                value: 0,
+            }],
+        );
+        assert_eq!((machine.cc_neg, machine.cc_zero, machine.cc_pos), (0, 1, 0));
+
+        run_instructions(&mut machine, vec![Instruction::Not { dest: 0, source: 0 }]);
+        assert_eq!((machine.cc_neg, machine.cc_zero, machine.cc_pos), (1, 0, 0));
+        assert_eq!(machine.regs[0], 0xFFFF);
+    }
+
+    #[test]
+    fn test_load_at_origin() {
+        let mut machine = Machine::new();
+        machine.load(&assemble("test.asm", ".ORIG x4000\n.FILL 1\n.FILL 2").unwrap());
+        assert_eq!(machine.memory[0x4000..0x4002], [1, 2]);
+        assert_eq!(machine.pc, 0x4000);
+    }
+
+    #[test]
+    fn test_load_segments() {
+        let mut machine = Machine::new();
+        machine.load(&assemble("test.asm", ".ORIG x4000\n.FILL 1\n.ORIG x5000\n.FILL 2").unwrap());
+        assert_eq!(machine.memory[0x4000], 1);
+        assert_eq!(machine.memory[0x5000], 2);
+        assert_eq!(machine.pc, 0x4000);
+    }
+
+    #[test]
+    fn test_loop_and_subroutine() {
+        let machine = run_source(
+            ".ORIG x3000
+                  AND R0, R0, #0
+                  LD R1, count
+            loop  JSR double_plus_one
+                  ADD R1, R1, #-1
+                  BRp loop
+                  ST R0, result
+                  AND R2, R2, #0
+                  STI R2, mcr
+            double_plus_one
+                  ADD R0, R0, R0
+                  ADD R0, R0, #1
+                  RET
+            count  .FILL 3
+            result .BLKW 1
+            mcr    .FILL xFFFE
+            .END",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(machine.regs[0], 7);
+        assert_eq!(machine.memory[0x300C], 7);
+    }
+
+    #[test]
+    fn test_memory_mapped_io() {
+        let machine = run_source(
+            ".ORIG x3000
+            poll  LDI R1, kbsr
+                  BRzp poll
+                  LDI R0, kbdr
+                  ADD R0, R0, #1
+                  STI R0, ddr
+                  LEA R3, message
+                  LDR R0, R3, #0
+                  STI R0, ddr
+                  AND R2, R2, #0
+                  STI R2, mcr
+            kbsr  .FILL xFE00
+            kbdr  .FILL xFE02
+            ddr   .FILL xFE06
+            mcr   .FILL xFFFE
+            message .STRINGZ \"!\"
+            .END",
+            b"a",
+        )
+        .unwrap();
+        assert_eq!(machine.output, b"b!");
+        assert!(machine.input.is_empty());
+    }
+
+    #[test]
+    fn test_trap_jumps_through_vector_table() {
+        let mut machine = Machine::new();
+        machine.load(&assemble("vector.asm", ".ORIG x0025\n.FILL x4000").unwrap());
+        machine.load(&assemble("trap.asm", ".ORIG x3000\nHALT").unwrap());
+        machine.step().unwrap();
+        assert_eq!(machine.pc, 0x4000);
+        assert_eq!(machine.regs[7], 0x3001);
+    }
+
+    #[test]
+    fn test_execution_counts() {
+        let mut machine = Machine::new();
+        assert_eq!(machine.execution_counts(), None);
+
+        machine.load(&assemble("test.asm", ".ORIG x3000\nADD R0, R0, #1\nBR #-2").unwrap());
+        machine.track_coverage();
+        for _ in 0..5 {
+            machine.step().unwrap();
+        }
+        assert_eq!(
+            machine.execution_counts(),
+            Some(&vec![(0x3000, 3), (0x3001, 2)].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn test_unconditional_branch_before_setting_condition_codes() {
+        let mut machine = Machine::new();
+        machine.load(&assemble("test.asm", ".ORIG x3000\nloop BR loop").unwrap());
+        machine.step().unwrap();
+        assert_eq!(machine.pc(), 0x3000);
+    }
+
+    #[test]
+    fn test_inspecting_state() {
+        let mut machine = Machine::new();
+        machine.load(&assemble("test.asm", ".ORIG x3000\nADD R2, R2, #-3\nHALT").unwrap());
+        machine.step().unwrap();
+        assert_eq!(machine.pc(), 0x3001);
+        assert_eq!(machine.registers(), [0, 0, 0xFFFD, 0, 0, 0, 0, 0]);
+        assert_eq!(machine.condition(), 'N');
+        assert_eq!(machine.memory(0x3001), 0xF025);
+        assert!(machine.is_running());
+    }
+
+    #[test]
+    fn test_load_words() {
+        let mut machine = Machine::new();
+        machine.load_words(0xFFFF, &[0x1021, 0xF025]);
+        assert_eq!(machine.memory(0xFFFF), 0x1021);
+        assert_eq!(machine.memory(0x0000), 0xF025);
+        assert_eq!(machine.pc(), 0);
+    }
+
+    #[test]
+    fn test_uninitialized_reads() {
+        let mut machine = Machine::new();
+        machine.fill_memory(0xDEAD);
+        let source = ".ORIG x3000
+LD R1, data
+LD R2, #5   ; just past the end of the program
+ST R1, #4
+LD R3, #3
+LDI R4, kbsr
+kbsr .FILL xFE00
+data .FILL 7";
+        machine.load(&assemble("test.asm", source).unwrap());
+        machine.track_uninitialized_reads();
+        assert!(!machine.is_initialized(0x3007));
+        for _ in 0..5 {
+            machine.step().unwrap();
+        }
+        assert_eq!(
+            machine.take_uninitialized_reads(),
+            vec![UninitializedRead {
+                address: 0x3001,
+                target: 0x3007,
+            }]
+        );
+        assert_eq!(machine.registers()[2], 0xDEAD);
+        assert_eq!(machine.registers()[3], 7);
+        assert!(machine.is_initialized(0x3007));
+        assert!(machine.take_uninitialized_reads().is_empty());
+    }
+
+    #[test]
+    fn test_initial_state() {
+        let mut machine = Machine::new();
+        machine.fill_memory(0xDEAD);
+        machine.load(&assemble("test.asm", ".ORIG x3000\nLD R1, #1\nHALT").unwrap());
+        machine.set_register(0, 0xBEEF);
+        machine.set_pc(0x3000);
+        machine.step().unwrap();
+        assert_eq!(machine.registers()[0], 0xBEEF);
+        // the word after the HALT was never loaded
+        assert_eq!(machine.registers()[1], 0xDEAD);
+        assert_eq!(machine.memory(0x3001), 0xF025);
+    }
+
+    #[test]
+    fn test_input_and_output() {
+        let mut machine = Machine::new();
+        machine.load(
+            &assemble(
+                "echo.asm",
+                ".ORIG x3000\nloop LDI R0, kbsr\nBRzp loop\nLDI R0, kbdr\nSTI R0, ddr\nBR loop
+                kbsr .FILL xFE00\nkbdr .FILL xFE02\nddr .FILL xFE06",
+            )
+            .unwrap(),
+        );
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        assert!(machine.is_waiting_for_input());
+        machine.push_input(b"a");
+        assert!(!machine.is_waiting_for_input());
+        for _ in 0..5 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.take_output(), b"a");
+        assert_eq!(machine.take_output(), b"");
+    }
+
+    #[test]
+    fn test_watches() {
+        let mut machine = Machine::new();
+        machine.load(
+            &assemble(
+                "test.asm",
+                ".ORIG x3000\nLD R1, data\nADD R2, R1, #1\nST R2, data\nHALT\ndata .FILL 5",
+            )
+            .unwrap(),
+        );
+        let both = Watch {
+            reads: true,
+            writes: true,
+        };
+        machine.watch(Watched::Memory(0x3004), both);
+        machine.watch(
+            Watched::Register(1),
+            Watch {
+                reads: false,
+                writes: true,
+            },
+        );
+        let access = |watched, kind, old, new| Access {
+            watched,
+            kind,
+            old,
+            new,
+        };
+
+        machine.step().unwrap();
+        assert_eq!(
+            machine.take_accesses(),
+            vec![
+                access(Watched::Memory(0x3004), AccessKind::Read, 5, 5),
+                access(Watched::Register(1), AccessKind::Write, 0, 5),
+            ]
+        );
+        // R1 is only watched for writes
+        machine.step().unwrap();
+        assert_eq!(machine.take_accesses(), vec![]);
+        machine.step().unwrap();
+        assert_eq!(
+            machine.take_accesses(),
+            vec![access(Watched::Memory(0x3004), AccessKind::Write, 5, 6)]
+        );
+
+        assert!(machine.unwatch(Watched::Memory(0x3004)));
+        assert!(!machine.unwatch(Watched::Memory(0x3004)));
+        // fetching a watched instruction isn't a read of it
+        machine.watch(Watched::Memory(0x3000), both);
+        machine.set_pc(0x3000);
+        machine.step().unwrap();
+        assert_eq!(
+            machine.take_accesses(),
+            vec![access(Watched::Register(1), AccessKind::Write, 5, 6)]
+        );
+    }
+
+    #[test]
+    fn test_illegal_opcode() {
+        assert_eq!(
+            run_source(".ORIG x3000\nNOP\n.FILL xD000", &[]).err(),
+            Some(MachineError {
+                message: String::from("illegal opcode"),
+                address: 0x3001,
+            })
+        );
+    }
 }
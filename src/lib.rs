@@ -0,0 +1,60 @@
+//! an LC-3 assembler and emulator, for embedding in other programs
+//!
+//! ```
+//! let mut machine = lc3_emulator::Machine::new();
+//! // ADD R0, R0, #5 and then AND R1, R0, #4
+//! machine.load_words(0x3000, &[0x1025, 0x5224]);
+//! machine.set_pc(0x3000);
+//! machine.step().unwrap();
+//! machine.step().unwrap();
+//! assert_eq!(machine.registers()[..2], [5, 4]);
+//! ```
+//!
+//! with `std`, programs can be assembled from source instead, with `assemble`
+//! and `Machine::load`
+//!
+//! everything but `Machine` and `Instruction` needs the `std` feature, which
+//! is on by default. without it the crate is `no_std`, and only needs `alloc`
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod disassembler;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod harness;
+pub mod instructions;
+pub mod lc3;
+#[cfg(feature = "std")]
+pub mod lsp;
+#[cfg(feature = "std")]
+pub mod repl;
+#[cfg(feature = "std")]
+pub mod terminal;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tui;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use assembler::diagnostic::{Diagnostic, Label, Severity};
+#[cfg(feature = "std")]
+pub use assembler::{
+    assemble, assemble_with_diagnostics, assemble_with_options, DebugInfo, Executable, Options,
+    Segment, SourceLocation, Span,
+};
+pub use instructions::Instruction;
+pub use lc3::{Machine, MachineError};
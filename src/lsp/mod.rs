@@ -0,0 +1,640 @@
+mod json;
+
+use crate::assembler::diagnostic::{Diagnostic, Severity};
+use crate::assembler::{self, Executable, Span};
+use json::Json;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// an open file, along with what was learned about it the last time it
+/// assembled
+struct Document {
+    text: String,
+    executable: Option<Executable>,
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    /// set once the client asks the server to shut down
+    shutting_down: bool,
+}
+
+impl Server {
+    /// respond to one message from the client, returning every message to send
+    /// back, along with whether it's time to exit
+    fn handle(&mut self, message: &Json) -> (Vec<Json>, bool) {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "initialized" => return (vec![], false),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Json::Null)
+            }
+            "exit" => return (vec![], true),
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument").unwrap_or(&Json::Null);
+                let text = document.get("text").and_then(Json::as_str).unwrap_or("");
+                return (self.update(uri(params), text.to_string()), false);
+            }
+            "textDocument/didChange" => {
+                // only full syncs are advertised, so the last change is the
+                // whole document
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str)
+                    .unwrap_or("");
+                return (self.update(uri(params), text.to_string()), false);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri(params));
+                return (vec![], false);
+            }
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            _ => Err(format!("unsupported method: {}", method)),
+        };
+
+        // notifications don't get a response, even if they aren't understood
+        let id = match id {
+            Some(id) => id,
+            None => return (vec![], false),
+        };
+        let response = match result {
+            Ok(result) => Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err(message) => Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("id", id),
+                (
+                    "error",
+                    Json::object(vec![
+                        ("code", Json::Number(-32601.0)),
+                        ("message", Json::from(message)),
+                    ]),
+                ),
+            ]),
+        };
+        (vec![response], false)
+    }
+
+    /// reassemble a document that has changed, and publish its diagnostics
+    fn update(&mut self, uri: &str, text: String) -> Vec<Json> {
+        let (executable, diagnostics) = match assembler::assemble_with_diagnostics(uri, &text) {
+            Ok((executable, warnings)) => (Some(executable), warnings),
+            Err(diagnostics) => (None, diagnostics),
+        };
+        let notification = Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from("textDocument/publishDiagnostics")),
+            (
+                "params",
+                Json::object(vec![
+                    ("uri", Json::from(uri)),
+                    (
+                        "diagnostics",
+                        Json::Array(
+                            diagnostics
+                                .iter()
+                                .map(|diagnostic| to_lsp_diagnostic(uri, &text, diagnostic))
+                                .collect(),
+                        ),
+                    ),
+                ]),
+            ),
+        ]);
+
+        let document = self
+            .documents
+            .entry(uri.to_string())
+            .or_insert_with(|| Document {
+                text: String::new(),
+                executable: None,
+            });
+        document.text = text;
+        // hold on to the last good assembly, so that editing a broken file
+        // doesn't stop hovers and definitions from working for the rest of it
+        if executable.is_some() {
+            document.executable = executable;
+        }
+        vec![notification]
+    }
+
+    /// the document a request is about, along with the label under the
+    /// cursor if there is one
+    fn lookup(&self, params: &Json) -> Option<(&Document, Option<String>)> {
+        let document = self.documents.get(uri(params))?;
+        let position = params.get("position")?;
+        let line = position.get("line").and_then(Json::as_usize)?;
+        let character = position.get("character").and_then(Json::as_usize)?;
+        Some((document, word_at(&document.text, line, character)))
+    }
+
+    /// show what a label points at, and what the line under the cursor
+    /// assembled to
+    fn hover(&self, params: &Json) -> Json {
+        let (document, word) = match self.lookup(params) {
+            Some(found) => found,
+            None => return Json::Null,
+        };
+        let executable = match &document.executable {
+            Some(executable) => executable,
+            None => return Json::Null,
+        };
+        let line = params
+            .get("position")
+            .and_then(|position| position.get("line"))
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+
+        let mut contents = Vec::new();
+        if let Some(label) = &word {
+            if let Some(address) = executable.symbols.get(label) {
+                contents.push(format!("`{}` = x{:04X}", label, address));
+            }
+        }
+        if let Some(debug_info) = &executable.debug_info {
+            for segment in &executable.segments {
+                for (address, word) in segment.addressed_words() {
+                    if debug_info.location(address).map(|location| location.line) == Some(line) {
+                        contents.push(format!("x{:04X}: x{:04X} ({:016b})", address, word, word));
+                    }
+                }
+            }
+        }
+        if contents.is_empty() {
+            return Json::Null;
+        }
+
+        Json::object(vec![(
+            "contents",
+            Json::object(vec![
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(contents.join("\n\n"))),
+            ]),
+        )])
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let definition = self.lookup(params).and_then(|(document, word)| {
+            let label = word?;
+            let debug_info = document.executable.as_ref()?.debug_info.as_ref()?;
+            Some((document, debug_info.labels.get(&label).copied()?))
+        });
+        match definition {
+            Some((document, span)) => location(uri(params), &document.text, span),
+            None => Json::Null,
+        }
+    }
+
+    fn document_symbols(&self, params: &Json) -> Json {
+        let debug_info = self.documents.get(uri(params)).and_then(|document| {
+            let executable = document.executable.as_ref()?;
+            Some((document, executable, executable.debug_info.as_ref()?))
+        });
+        let (document, executable, debug_info) = match debug_info {
+            Some(found) => found,
+            None => return Json::Array(vec![]),
+        };
+
+        Json::Array(
+            debug_info
+                .labels
+                .iter()
+                .map(|(label, span)| {
+                    let is_code = executable
+                        .symbols
+                        .get(label)
+                        .is_some_and(|address| debug_info.code.contains(address));
+                    Json::object(vec![
+                        ("name", Json::from(label.as_ref())),
+                        // labels of instructions are functions, anything else
+                        // is data
+                        ("kind", Json::from(if is_code { 12 } else { 13 })),
+                        ("range", range(&document.text, *span)),
+                        ("selectionRange", range(&document.text, *span)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                // send the whole document on every change
+                ("textDocumentSync", Json::from(1)),
+                ("hoverProvider", Json::from(true)),
+                ("definitionProvider", Json::from(true)),
+                ("documentSymbolProvider", Json::from(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![("name", Json::from("lc3-emulator"))]),
+        ),
+    ])
+}
+
+fn uri(params: &Json) -> &str {
+    params
+        .get("textDocument")
+        .and_then(|document| document.get("uri"))
+        .and_then(Json::as_str)
+        .unwrap_or("")
+}
+
+/// the range a span covers in `text`. spans count chars, but LSP counts
+/// UTF-16 code units, so anything outside the BMP earlier on the line counts
+/// twice
+fn range(text: &str, span: Span) -> Json {
+    let line = text.lines().nth(span.line).unwrap_or("");
+    let position = |column| {
+        Json::object(vec![
+            ("line", Json::from(span.line)),
+            ("character", Json::from(utf16_column(line, column))),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(span.column)),
+        ("end", position(span.column + span.len)),
+    ])
+}
+
+/// how many UTF-16 code units the first `column` chars of a line take up.
+/// anything past the end of the line, like the newline an error can point at,
+/// counts as one
+fn utf16_column(line: &str, column: usize) -> usize {
+    let in_line: usize = line.chars().take(column).map(char::len_utf16).sum();
+    in_line + column.saturating_sub(line.chars().count())
+}
+
+/// which char of a line an LSP position, in UTF-16 code units, is in
+fn char_column(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (index, c) in line.chars().enumerate() {
+        units += c.len_utf16();
+        if units > character {
+            return index;
+        }
+    }
+    line.chars().count() + character - units
+}
+
+fn location(uri: &str, text: &str, span: Span) -> Json {
+    Json::object(vec![("uri", Json::from(uri)), ("range", range(text, span))])
+}
+
+fn to_lsp_diagnostic(uri: &str, text: &str, diagnostic: &Diagnostic) -> Json {
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut message = diagnostic.message.clone();
+    if let Some(help) = &diagnostic.help {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+    Json::object(vec![
+        ("range", range(text, diagnostic.span)),
+        ("severity", Json::from(severity)),
+        ("source", Json::from("lc3-emulator")),
+        ("message", Json::from(message)),
+        (
+            "relatedInformation",
+            Json::Array(
+                diagnostic
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        Json::object(vec![
+                            ("location", location(uri, text, label.span)),
+                            ("message", Json::from(label.message.as_ref())),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+/// the symbol that covers a position in the source, if any. `character` is in
+/// UTF-16 code units, like every LSP position
+fn word_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let line = text.lines().nth(line)?;
+    let character = char_column(line, character);
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    if !chars.get(character).is_some_and(is_word) {
+        return None;
+    }
+    let start = (0..character)
+        .rev()
+        .take_while(|index| is_word(&chars[*index]))
+        .last()
+        .unwrap_or(character);
+    let end = (character..chars.len())
+        .take_while(|index| is_word(&chars[*index]))
+        .last()
+        .unwrap_or(character)
+        + 1;
+    Some(chars[start..end].iter().collect())
+}
+
+/// read one message, framed by a Content-Length header
+fn read_message(input: &mut impl BufRead) -> Result<Option<Json>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(|e| e.to_string())?);
+        }
+    }
+
+    let length = length.ok_or_else(|| String::from("message is missing a Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let body = String::from_utf8(body).map_err(|e| e.to_string())?;
+    json::parse(&body).map(Some)
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|e| e.to_string())
+}
+
+/// serve the language server protocol over stdin and stdout until the client
+/// tells the server to exit
+pub fn run() -> Result<(), String> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        let (responses, exit) = server.handle(&message);
+        for response in &responses {
+            write_message(&mut output, response)?;
+        }
+        if exit {
+            break;
+        }
+    }
+
+    if server.shutting_down {
+        Ok(())
+    } else {
+        Err(String::from(
+            "the client exited without shutting the server down",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = ".ORIG x3000\nloop ADD R0, R0, #1\n     BR loop\ndata .FILL x2A\n.END";
+
+    fn request(method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(1)),
+            ("method", Json::from(method)),
+            ("params", params),
+        ])
+    }
+
+    fn position(line: usize, character: usize) -> Json {
+        Json::object(vec![
+            (
+                "textDocument",
+                Json::object(vec![("uri", Json::from("file:///a.asm"))]),
+            ),
+            (
+                "position",
+                Json::object(vec![
+                    ("line", Json::from(line)),
+                    ("character", Json::from(character)),
+                ]),
+            ),
+        ])
+    }
+
+    fn open(server: &mut Server, text: &str) -> Json {
+        let (mut messages, _) = server.handle(&Json::object(vec![
+            ("method", Json::from("textDocument/didOpen")),
+            (
+                "params",
+                Json::object(vec![(
+                    "textDocument",
+                    Json::object(vec![
+                        ("uri", Json::from("file:///a.asm")),
+                        ("text", Json::from(text)),
+                    ]),
+                )]),
+            ),
+        ]));
+        messages.remove(0)
+    }
+
+    fn result(server: &mut Server, method: &str, params: Json) -> Json {
+        let (messages, _) = server.handle(&request(method, params));
+        messages[0].get("result").unwrap().clone()
+    }
+
+    #[test]
+    fn test_publishes_diagnostics() {
+        let mut server = Server::default();
+        let notification = open(&mut server, ".ORIG x3000\nADD R0, R9, R1");
+        let diagnostics = notification
+            .get("params")
+            .and_then(|params| params.get("diagnostics"))
+            .and_then(Json::as_array)
+            .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].get("message"),
+            Some(&Json::from("invalid register: R9 (expected R0-R7)"))
+        );
+        assert_eq!(
+            diagnostics[0].get("range"),
+            Some(&range(
+                ".ORIG x3000\nADD R0, R9, R1",
+                Span {
+                    line: 1,
+                    column: 8,
+                    len: 2
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_definition() {
+        let mut server = Server::default();
+        open(&mut server, SOURCE);
+        assert_eq!(
+            result(&mut server, "textDocument/definition", position(2, 10)),
+            location(
+                "file:///a.asm",
+                SOURCE,
+                Span {
+                    line: 1,
+                    column: 0,
+                    len: 4
+                }
+            )
+        );
+        assert_eq!(
+            result(&mut server, "textDocument/definition", position(2, 5)),
+            Json::Null
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut server = Server::default();
+        open(&mut server, SOURCE);
+        let hover = result(&mut server, "textDocument/hover", position(2, 8));
+        assert_eq!(
+            hover
+                .get("contents")
+                .and_then(|contents| contents.get("value")),
+            Some(&Json::from(
+                "`loop` = x3000\n\nx3001: x0FFE (0000111111111110)"
+            ))
+        );
+        assert_eq!(
+            result(&mut server, "textDocument/hover", position(4, 1)),
+            Json::Null
+        );
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let mut server = Server::default();
+        open(&mut server, SOURCE);
+        let symbols = result(&mut server, "textDocument/documentSymbol", position(0, 0));
+        let symbols: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| (symbol.get("name").cloned(), symbol.get("kind").cloned()))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (Some(Json::from("data")), Some(Json::from(13))),
+                (Some(Json::from("loop")), Some(Json::from(12))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keeps_last_good_assembly() {
+        let mut server = Server::default();
+        open(&mut server, SOURCE);
+        open(&mut server, &SOURCE.replace("#1", "#G"));
+        assert_ne!(
+            result(&mut server, "textDocument/definition", position(2, 10)),
+            Json::Null
+        );
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut server = Server::default();
+        let (messages, _) = server.handle(&request("initialize", Json::Null));
+        assert_eq!(messages[0].get("result"), Some(&capabilities()));
+
+        let (messages, _) = server.handle(&request("textDocument/unknown", Json::Null));
+        assert!(messages[0].get("error").is_some());
+
+        let (messages, exit) = server.handle(&request("shutdown", Json::Null));
+        assert_eq!(messages[0].get("result"), Some(&Json::Null));
+        assert!(!exit && server.shutting_down);
+
+        let (messages, exit) = server.handle(&Json::object(vec![("method", Json::from("exit"))]));
+        assert!(messages.is_empty() && exit);
+    }
+
+    #[test]
+    fn test_word_at() {
+        assert_eq!(
+            word_at("\n  BR loop_2 ; x", 1, 7),
+            Some(String::from("loop_2"))
+        );
+        assert_eq!(
+            word_at("\n  BR loop_2 ; x", 1, 5),
+            Some(String::from("loop_2"))
+        );
+        assert_eq!(
+            word_at("\n  BR loop_2", 1, 10),
+            Some(String::from("loop_2"))
+        );
+        assert_eq!(word_at("\n  BR loop_2", 1, 4), None);
+        assert_eq!(word_at("", 3, 0), None);
+        // positions count UTF-16 code units, so 🙂 takes up two of them
+        assert_eq!(word_at("🙂 a b", 0, 3), Some(String::from("a")));
+        assert_eq!(word_at("🙂 a b", 0, 5), Some(String::from("b")));
+        assert_eq!(word_at("🙂 a b", 0, 2), None);
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let mut server = Server::default();
+        let notification = open(&mut server, ".ORIG x3000\n.STRINGZ \"🙂\" R9");
+        let range = notification
+            .get("params")
+            .and_then(|params| params.get("diagnostics"))
+            .and_then(Json::as_array)
+            .and_then(|diagnostics| diagnostics[0].get("range"))
+            .unwrap();
+        // R9 is 13 chars into the line, but 14 UTF-16 code units
+        let character = |end: &str| range.get(end)?.get("character").cloned();
+        assert_eq!(character("start"), Some(Json::from(14)));
+        assert_eq!(character("end"), Some(Json::from(16)));
+
+        assert_eq!(utf16_column("é🙂 ok", 3), 4);
+        assert_eq!(utf16_column("ab", 3), 3);
+        assert_eq!(char_column("é🙂 ok", 3), 2);
+        assert_eq!(char_column("é🙂 ok", 2), 1);
+        assert_eq!(char_column("ab", 3), 3);
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let message = Json::object(vec![("id", Json::from(1))]);
+        let mut output = Vec::new();
+        write_message(&mut output, &message).unwrap();
+        assert_eq!(output, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut input = &output[..];
+        assert_eq!(read_message(&mut input), Ok(Some(message)));
+        assert_eq!(read_message(&mut input), Ok(None));
+    }
+}
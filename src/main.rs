@@ -1,27 +1,1059 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::panic;
+use std::path::Path;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-mod assembler;
-mod instructions;
-mod lc3;
+use lc3_emulator::assembler::diagnostic::render;
+use lc3_emulator::assembler::output::Format;
+use lc3_emulator::debugger::{Command, Debugger};
+use lc3_emulator::repl::Repl;
+use lc3_emulator::{assembler, coverage, debugger, disassembler, lc3, lsp, terminal, trace, tui};
 
 fn main() {
     if let Err(err) = run() {
-        println!("failed to run: {}", err)
+        eprintln!("failed to run: {}", err);
+        std::process::exit(1);
     }
 }
 
+const USAGE: &str = "usage: lc3-emulator COMMAND [OPTIONS] FILE...
+
+commands:
+    asm       assemble a program into a .obj and a .sym file
+    run       assemble and run a program, or run a .obj file
+    disasm    print the assembly a program's machine code came from
+    trace     run a program, printing the state after every instruction
+    debug     step through a program, with breakpoints and watchpoints
+    repl      assemble and run instructions as they're typed
+    lsp       run a language server for editors, over stdin and stdout
+    help      print this, or the options a command takes with `help COMMAND`
+";
+
+const ASM_USAGE: &str = "usage: lc3-emulator asm [OPTIONS] FILE...
+
+assemble each FILE into FILE.obj and FILE.sym. a FILE that's a directory
+stands for every .asm file in it and its subdirectories. several files are
+assembled in parallel, with everything wrong with them printed in order
+
+options:
+    --format obj|hex|bin    what to write the machine code as. each of them holds a
+                            single .ORIG block, so programs with more than one
+                            can only be run or debugged from source
+    --listing               also write a FILE.lst listing
+    --jobs COUNT            assemble COUNT files at a time, instead of one per CPU
+";
+
+const RUN_USAGE: &str = "usage: lc3-emulator run [OPTIONS] FILE...
+
+load every FILE into the same machine and run it. each one is either assembly,
+`-` to read assembly from stdin, or a .obj with an optional .sym next to it
+
+options:
+    --entry ADDRESS|LABEL   start at ADDRESS or LABEL, instead of the first FILE's .ORIG
+    --pc ADDRESS|LABEL      the same as --entry
+    --set-reg RN=VALUE      start with register RN holding VALUE, instead of 0
+    --fill-mem VALUE        fill memory that nothing is loaded into with VALUE, instead of 0
+    --check-uninitialized   warn about loads from memory that nothing wrote, not even the loader
+    --coverage              print the source annotated with how often each line ran
+    --lcov PATH             write an lcov coverage report to PATH
+";
+
+const TRACE_USAGE: &str = "usage: lc3-emulator trace [OPTIONS] FILE...
+
+load every FILE into the same machine, like run does, and print a line for
+every instruction it executes with the registers and condition code it left
+behind. traces from other simulators that are converted to the same format can
+be compared with --check. what the program displays goes to stderr
+
+options:
+    --check TRACE           compare with the trace in the file TRACE, instead of printing one
+    --input TEXT            keys for the program to read, since stdin isn't used
+    --limit COUNT           stop after COUNT instructions, instead of 1000000
+    --program-only          leave out instructions that aren't in a FILE, like the OS's
+    --entry ADDRESS|LABEL   start at ADDRESS or LABEL, instead of the first FILE's .ORIG
+    --pc ADDRESS|LABEL      the same as --entry
+    --set-reg RN=VALUE      start with register RN holding VALUE, instead of 0
+    --fill-mem VALUE        fill memory that nothing is loaded into with VALUE, instead of 0
+    --check-uninitialized   warn about loads from memory that nothing wrote, not even the loader
+";
+
+const DISASM_USAGE: &str = "usage: lc3-emulator disasm [OPTIONS] FILE
+
+print FILE's machine code as assembly, labelled with its symbols
+";
+
+const DEBUG_USAGE: &str = "usage: lc3-emulator debug [OPTIONS] FILE...
+
+load every FILE into the same machine, like run does, and debug it with
+breakpoints, watchpoints, stepping, and commands to inspect and change
+registers and memory.
+type help at the prompt for the commands
+
+options:
+    --tui                   debug full screen, with panes for the registers,
+                            disassembly, memory and the program's output
+    --entry ADDRESS|LABEL   start at ADDRESS or LABEL, instead of the first FILE's .ORIG
+    --pc ADDRESS|LABEL      the same as --entry
+    --set-reg RN=VALUE      start with register RN holding VALUE, instead of 0
+    --fill-mem VALUE        fill memory that nothing is loaded into with VALUE, instead of 0
+    --check-uninitialized   stop at loads from memory that nothing wrote, not even the loader
+";
+
+const REPL_USAGE: &str = "usage: lc3-emulator repl [OPTIONS]
+
+assemble each line as it's typed and run it straight away, printing the
+registers, condition code and memory it changed. lines go one after another,
+and can use the labels defined by earlier lines
+
+options:
+    --origin ADDRESS        put the first line at ADDRESS, instead of x3000
+";
+
+const LSP_USAGE: &str = "usage: lc3-emulator lsp
+
+run a language server for editors, over stdin and stdout
+";
+
+/// the flags every command that assembles a program takes
+const ASSEMBLY_USAGE: &str = "
+assembly options:
+    --define NAME[=VALUE]   define a constant for .IFDEF, as 1 unless a value is given
+    --strict                require commas between operands, and labels without colons
+    --warnings-as-errors    fail on warnings, like unused labels
+";
+
 fn run() -> Result<(), String> {
-    let os = include_str!("./os.asm");
-    let os_executable = assembler::assemble("./os.asm", &os)?;
-    lc3::Machine::new().run(&os_executable.instructions);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err(format!("expected a command\n\n{}", USAGE)),
+    };
+    if let "help" | "--help" | "-h" = command {
+        match rest {
+            [] => print!("{}", USAGE),
+            [command] => print!("{}", usage(command)?),
+            _ => return Err(format!("help takes at most one command\n\n{}", USAGE)),
+        }
+        return Ok(());
+    }
+
+    let usage = usage(command)?;
+    if rest.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", usage);
+        return Ok(());
+    }
+    // mistakes in the arguments get the command's usage, so it's clear what
+    // it does take
+    let usage_error = |err: String| format!("{}\n\n{}", err, usage);
+    match command {
+        "asm" => assemble_files(&parse_asm_options(rest).map_err(usage_error)?),
+        "run" => run_program(&parse_run_options(rest).map_err(usage_error)?),
+        "trace" => trace_program(&parse_trace_options(rest).map_err(usage_error)?),
+        "disasm" => disassemble_file(&parse_file_options(rest).map_err(usage_error)?),
+        "debug" => debug_program(&parse_debug_options(rest).map_err(usage_error)?),
+        "repl" => repl(&parse_repl_options(rest).map_err(usage_error)?),
+        "lsp" if rest.is_empty() => lsp::run(),
+        "lsp" => Err(usage_error(String::from("lsp doesn't take any arguments"))),
+        _ => unreachable!("every command has a usage"),
+    }
+}
+
+/// the help text for a command
+fn usage(command: &str) -> Result<String, String> {
+    let usage = match command {
+        "asm" => ASM_USAGE,
+        "run" => RUN_USAGE,
+        "trace" => TRACE_USAGE,
+        "disasm" => DISASM_USAGE,
+        "debug" => DEBUG_USAGE,
+        "repl" => REPL_USAGE,
+        "lsp" => return Ok(String::from(LSP_USAGE)),
+        _ => return Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+    };
+    Ok(format!("{}{}", usage, ASSEMBLY_USAGE))
+}
+
+struct AsmOptions {
+    filenames: Vec<String>,
+    format: Format,
+    listing: bool,
+    /// how many files to assemble at once
+    jobs: usize,
+    assembly: assembler::Options,
+}
+
+/// parse `[--format obj|hex|bin] [--listing] [--jobs COUNT] [ASSEMBLY OPTIONS]
+/// FILE...`
+fn parse_asm_options(args: &[String]) -> Result<AsmOptions, String> {
+    let mut format = Format::Obj;
+    let mut listing = false;
+    let mut jobs = thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let mut assembly = assembler::Options::default();
+    let mut filenames = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--format" => {
+                format = args
+                    .next()
+                    .ok_or_else(|| String::from("--format needs a value"))?
+                    .parse()?
+            }
+            "--listing" => listing = true,
+            "--jobs" => {
+                let count = args
+                    .next()
+                    .ok_or_else(|| String::from("--jobs needs a count"))?;
+                jobs = count
+                    .parse()
+                    .ok()
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| format!("expected a count of at least 1: {}", count))?;
+            }
+            _ if parse_assembly_flag(&mut assembly, arg, &mut args)? => {}
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => filenames.push(arg.clone()),
+        }
+    }
+
+    if filenames.is_empty() {
+        return Err(String::from("expected a file to assemble"));
+    }
+    Ok(AsmOptions {
+        filenames,
+        format,
+        listing,
+        jobs,
+        assembly,
+    })
+}
+
+/// just a file and how to assemble it, for commands that don't take anything else
+struct FileOptions {
+    filename: String,
+    assembly: assembler::Options,
+}
+
+/// parse `[ASSEMBLY OPTIONS] FILE`
+fn parse_file_options(args: &[String]) -> Result<FileOptions, String> {
+    let mut assembly = assembler::Options::default();
+    let mut filename = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if parse_assembly_flag(&mut assembly, arg, &mut args)? {
+            continue;
+        } else if arg.starts_with("--") {
+            return Err(format!("unknown option: {}", arg));
+        } else if filename.is_none() {
+            filename = Some(arg.clone());
+        } else {
+            return Err(format!("unexpected argument: {}", arg));
+        }
+    }
+
+    Ok(FileOptions {
+        filename: filename.ok_or_else(|| String::from("expected a file"))?,
+        assembly,
+    })
+}
+
+/// the files to load into a machine, and where to start running them
+#[derive(Default)]
+struct ProgramOptions {
+    filenames: Vec<String>,
+    /// an address or label to start at, instead of the first file's entry
+    entry: Option<String>,
+    /// values to start registers with, instead of 0
+    registers: Vec<(u16, u16)>,
+    /// what memory starts out as before anything is loaded, instead of 0
+    fill: Option<u16>,
+    /// track loads from memory that was never written
+    check_uninitialized: bool,
+    assembly: assembler::Options,
+}
+
+/// handle a flag that changes how the machine starts out, an assembly flag, or
+/// the name of a file to load, if `arg` is one of them
+fn parse_program_arg(
+    program: &mut ProgramOptions,
+    arg: &str,
+    args: &mut slice::Iter<String>,
+) -> Result<bool, String> {
+    match arg {
+        "--entry" | "--pc" => {
+            program.entry = Some(
+                args.next()
+                    .ok_or_else(|| format!("{} needs an address or a label", arg))?
+                    .clone(),
+            )
+        }
+        "--set-reg" => {
+            let assignment = args.next().ok_or_else(|| {
+                String::from("--set-reg needs a register and a value, like R0=x3000")
+            })?;
+            program
+                .registers
+                .push(parse_register_assignment(assignment)?);
+        }
+        "--fill-mem" => {
+            let value = args
+                .next()
+                .ok_or_else(|| String::from("--fill-mem needs a value"))?;
+            program.fill = Some(parse_number(value)?);
+        }
+        "--check-uninitialized" => program.check_uninitialized = true,
+        _ if parse_assembly_flag(&mut program.assembly, arg, args)? => {}
+        _ if arg.starts_with("--") => return Ok(false),
+        _ => program.filenames.push(arg.to_string()),
+    }
+    Ok(true)
+}
+
+impl ProgramOptions {
+    /// make sure there's something to run, and that stdin is only read once
+    fn check(&self) -> Result<(), String> {
+        if self.filenames.is_empty() {
+            return Err(String::from("expected a file to run"));
+        }
+        if self
+            .filenames
+            .iter()
+            .filter(|filename| *filename == "-")
+            .count()
+            > 1
+        {
+            return Err(String::from(
+                "- can only be given once, since stdin can only be read once",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// parse the `RN=VALUE` given to `--set-reg` into a register index and a value
+fn parse_register_assignment(arg: &str) -> Result<(u16, u16), String> {
+    let (register, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected a register and a value, like R0=x3000: {}", arg))?;
+    let index = register
+        .strip_prefix('R')
+        .or_else(|| register.strip_prefix('r'))
+        .and_then(|index| index.parse::<u16>().ok())
+        .filter(|index| *index < 8)
+        .ok_or_else(|| format!("expected a register from R0 to R7: {}", register))?;
+    Ok((index, parse_number(value)?))
+}
+
+/// handle one of the flags in `ASSEMBLY_USAGE`, if `arg` is one of them
+fn parse_assembly_flag(
+    options: &mut assembler::Options,
+    arg: &str,
+    args: &mut slice::Iter<String>,
+) -> Result<bool, String> {
+    match arg {
+        "--define" => define(options, args.next())?,
+        "--strict" => options.strict = true,
+        "--warnings-as-errors" => options.warnings_as_errors = true,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// add the `NAME` or `NAME=VALUE` given to `--define` to the constants a
+/// program is assembled with. a bare name is defined as 1
+fn define(options: &mut assembler::Options, arg: Option<&String>) -> Result<(), String> {
+    let arg = arg.ok_or_else(|| String::from("--define needs a name"))?;
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, parse_number(value)?),
+        None => (arg.as_str(), 1),
+    };
+    options.defines.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// parse a number written like the assembler would take it, as decimal or as
+/// hex with an `x` in front
+fn parse_number(value: &str) -> Result<u16, String> {
+    let parsed = match value.strip_prefix('x').or_else(|| value.strip_prefix('X')) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value
+            .parse::<u16>()
+            .ok()
+            .or_else(|| value.parse::<i16>().ok().map(|value| value as u16)),
+    };
+    parsed.ok_or_else(|| format!("invalid number: {}", value))
+}
+
+/// assemble a file into an executable and a .sym file next to it, and
+/// optionally a .lst
+fn assemble_files(options: &AsmOptions) -> Result<(), String> {
+    match &options.filenames[..] {
+        // a single file fails the way the other commands do
+        [filename] if !Path::new(filename).is_dir() => assemble_file(filename, options),
+        filenames => assemble_batch(&find_sources(filenames)?, options),
+    }
+}
+
+fn assemble_file(filename: &str, options: &AsmOptions) -> Result<(), String> {
+    let file = fs::read_to_string(filename).map_err(|e| format!("{}", e))?;
+    let executable = assemble_source(filename, &file, &options.assembly)?;
+    write_outputs(filename, &file, &executable, options)
+}
+
+/// assemble every file in parallel, then print each one's diagnostics in the
+/// order the files were given, so they don't get interleaved
+fn assemble_batch(filenames: &[String], options: &AsmOptions) -> Result<(), String> {
+    let color = io::stderr().is_terminal();
+    let results = in_parallel(
+        filenames,
+        options.jobs,
+        |filename| -> Result<String, String> {
+            let file =
+                fs::read_to_string(filename).map_err(|e| format!("{}: {}\n", filename, e))?;
+            let (executable, warnings) =
+                assembler::assemble_with_options(filename, &file, &options.assembly)
+                    .map_err(|diagnostics| render(&diagnostics, filename, &file, color))?;
+            write_outputs(filename, &file, &executable, options).map_err(|e| e + "\n")?;
+            Ok(render(&warnings, filename, &file, color))
+        },
+    );
+
+    let mut failed = 0;
+    for result in &results {
+        match result {
+            Ok(warnings) => eprint!("{}", warnings),
+            Err(errors) => {
+                failed += 1;
+                eprint!("{}", errors);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} failed to assemble",
+            failed,
+            files(filenames.len())
+        ));
+    }
+    println!("assembled {}", files(filenames.len()));
+    Ok(())
+}
+
+fn files(count: usize) -> String {
+    match count {
+        1 => String::from("1 file"),
+        _ => format!("{} files", count),
+    }
+}
+
+/// call `f` with every item on `jobs` threads, returning the results in the
+/// same order as the items. each thread takes whichever item is next as soon
+/// as it's done with its last, so a slow item doesn't hold the others up
+fn in_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            return results;
+                        };
+                        results.push((index, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// every file to assemble: each file given, and every .asm file in and under
+/// each directory given, in order
+fn find_sources(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut sources = Vec::new();
+    for path in paths {
+        find_asm_files(Path::new(path), &mut sources)?;
+    }
+    if sources.is_empty() {
+        return Err(String::from("there aren't any .asm files to assemble"));
+    }
+    Ok(sources)
+}
+
+fn find_asm_files(path: &Path, sources: &mut Vec<String>) -> Result<(), String> {
+    if !path.is_dir() {
+        sources.push(path.display().to_string());
+        return Ok(());
+    }
+    let error = |e: io::Error| format!("{}: {}", path.display(), e);
+    let mut entries = fs::read_dir(path)
+        .map_err(error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir()
+            || entry
+                .extension()
+                .is_some_and(|extension| extension == "asm")
+        {
+            find_asm_files(&entry, sources)?;
+        }
+    }
+    Ok(())
+}
+
+/// write the machine code and symbol table for an assembled file next to it,
+/// and its listing if there's meant to be one
+fn write_outputs(
+    filename: &str,
+    file: &str,
+    executable: &assembler::Executable,
+    options: &AsmOptions,
+) -> Result<(), String> {
+    write_output(
+        filename,
+        options.format.extension(),
+        &assembler::output::write(executable, options.format)?,
+    )?;
+    write_output(
+        filename,
+        "sym",
+        assembler::symbols::to_string(&executable.symbols).as_bytes(),
+    )?;
+    if options.listing {
+        write_output(
+            filename,
+            "lst",
+            assembler::listing::to_string(executable, file).as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+/// write an output file next to the input, with the given extension
+fn write_output(filename: &str, extension: &str, contents: &[u8]) -> Result<(), String> {
+    let output_filename = Path::new(filename).with_extension(extension);
+    fs::write(&output_filename, contents)
+        .map_err(|e| format!("{}: {}", output_filename.display(), e))
+}
+
+struct RunOptions {
+    program: ProgramOptions,
+    /// print the source annotated with execution counts once the program halts
+    coverage: bool,
+    /// write an lcov tracefile here once the program halts
+    lcov: Option<String>,
+}
+
+/// parse `[--coverage] [--lcov PATH] [PROGRAM OPTIONS] FILE...`
+fn parse_run_options(args: &[String]) -> Result<RunOptions, String> {
+    let mut coverage = false;
+    let mut lcov = None;
+    let mut program = ProgramOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--coverage" => coverage = true,
+            "--lcov" => {
+                lcov = Some(
+                    args.next()
+                        .ok_or_else(|| String::from("--lcov needs a path"))?
+                        .clone(),
+                )
+            }
+            _ if parse_program_arg(&mut program, arg, &mut args)? => {}
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    program.check()?;
+    Ok(RunOptions {
+        program,
+        coverage,
+        lcov,
+    })
+}
+
+fn run_program(options: &RunOptions) -> Result<(), String> {
+    let (mut machine, os, files) = load_program(&options.program)?;
+    let tracking_coverage = options.coverage || options.lcov.is_some();
+    if tracking_coverage && files.iter().all(|file| file.source.is_none()) {
+        return Err(String::from(
+            "coverage needs a program's source, so it can't be reported for .obj files",
+        ));
+    }
+
+    if tracking_coverage {
+        machine.track_coverage();
+    }
+    // reading memory that was never written is often why a program failed
+    let result = terminal::run(&mut machine);
+    warn_uninitialized_reads(&mut machine, &os, &files);
+    result.map_err(|e| describe_error(&os, &files, e))?;
+
+    if let Some(execution_counts) = machine.execution_counts() {
+        let mut tracefile = String::new();
+        let sources = files.iter().filter(|file| file.source.is_some()).count();
+        for file in &files {
+            let (Some(source), Some(debug_info)) = (&file.source, &file.executable.debug_info)
+            else {
+                continue;
+            };
+            let line_counts = coverage::line_counts(debug_info, execution_counts);
+            if options.coverage {
+                if sources > 1 {
+                    eprintln!("{}:", file.filename);
+                }
+                eprint!("{}", coverage::annotate(source, &line_counts));
+            }
+            tracefile.push_str(&coverage::lcov(&file.filename, &line_counts));
+        }
+        if let Some(path) = &options.lcov {
+            fs::write(path, tracefile).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    Ok(())
+}
+
+struct TraceOptions {
+    program: ProgramOptions,
+    /// a trace to compare with, instead of printing this one
+    check: Option<String>,
+    input: String,
+    /// the most instructions to execute
+    limit: u64,
+    /// only trace instructions in the program's files
+    program_only: bool,
+}
+
+/// parse `[--check TRACE] [--input TEXT] [--limit COUNT] [--program-only]
+/// [PROGRAM OPTIONS] FILE...`
+fn parse_trace_options(args: &[String]) -> Result<TraceOptions, String> {
+    let mut check = None;
+    let mut input = String::new();
+    let mut limit = 1_000_000;
+    let mut program_only = false;
+    let mut program = ProgramOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--check" => {
+                check = Some(
+                    args.next()
+                        .ok_or_else(|| String::from("--check needs a path"))?
+                        .clone(),
+                )
+            }
+            "--input" => {
+                input = args
+                    .next()
+                    .ok_or_else(|| String::from("--input needs some text"))?
+                    .clone()
+            }
+            "--limit" => {
+                let count = args
+                    .next()
+                    .ok_or_else(|| String::from("--limit needs a count"))?;
+                limit = count
+                    .parse()
+                    .map_err(|_| format!("expected a count: {}", count))?;
+            }
+            "--program-only" => program_only = true,
+            _ if parse_program_arg(&mut program, arg, &mut args)? => {}
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    program.check()?;
+    Ok(TraceOptions {
+        program,
+        check,
+        input,
+        limit,
+        program_only,
+    })
+}
+
+fn trace_program(options: &TraceOptions) -> Result<(), String> {
+    let (mut machine, os, files) = load_program(&options.program)?;
+    machine.push_input(options.input.as_bytes());
+    let in_program = |address| {
+        files
+            .iter()
+            .flat_map(|file| &file.executable.segments)
+            .any(|segment| segment.contains(address))
+    };
+
+    // the trace up to an error is still worth printing or comparing, since
+    // where it goes wrong is what a trace is for
+    let mut entries = Vec::new();
+    let mut error = None;
+    let mut steps = 0;
+    while machine.is_running() {
+        if steps == options.limit {
+            eprintln!("stopped after {} instructions", options.limit);
+            break;
+        }
+        steps += 1;
+        match trace::step(&mut machine) {
+            Ok(entry) if options.program_only && !in_program(entry.pc) => {}
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                error = Some(describe_error(&os, &files, e));
+                break;
+            }
+        }
+        if machine.is_waiting_for_input() {
+            error = Some(String::from(
+                "the program is waiting for more input than --input gave it",
+            ));
+            break;
+        }
+    }
+    io::stderr()
+        .write_all(&machine.take_output())
+        .map_err(|e| format!("failed to write output: {}", e))?;
+    warn_uninitialized_reads(&mut machine, &os, &files);
+
+    match &options.check {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let expected = trace::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+            trace::compare(&expected, &entries)?;
+            if error.is_none() {
+                println!("the traces match, for {} instructions", entries.len());
+            }
+        }
+        None => print!("{}", trace::to_string(&entries)),
+    }
+    error.map_or(Ok(()), Err)
+}
+
+/// print a warning for each instruction that loaded a word nothing wrote,
+/// once for every word it loaded like that, if they were being tracked
+fn warn_uninitialized_reads(
+    machine: &mut lc3::Machine,
+    os: &assembler::Executable,
+    files: &[LoadedFile],
+) {
+    let mut warned = BTreeSet::new();
+    for read in machine.take_uninitialized_reads() {
+        if warned.insert((read.address, read.target)) {
+            eprintln!(
+                "warning: {} loaded x{:04X}, which was never written",
+                describe_address(os, files, read.address),
+                read.target
+            );
+        }
+    }
+}
+
+/// a machine with the operating system loaded, along with the OS itself.
+/// memory the OS isn't loaded into holds `fill`
+fn boot(fill: u16) -> Result<(lc3::Machine, assembler::Executable), String> {
+    let mut machine = lc3::Machine::new();
+    machine.fill_memory(fill);
+    let os = assembler::assemble("./os.asm", include_str!("./os.asm"))?;
+    machine.load(&os);
+    Ok((machine, os))
+}
 
-    let args: Vec<String> = env::args().collect();
-    if let [_, filename] = args.as_slice() {
-        let file = fs::read_to_string(filename).map_err(|e| format!("{}", e))?;
-        let executable = assembler::assemble(filename, &file)?;
-        lc3::Machine::new().run(&executable.instructions);
+/// a file that's been loaded into a machine
+struct LoadedFile {
+    /// what diagnostics and coverage call the file
+    filename: String,
+    /// the assembly the file was assembled from, unless it was a .obj
+    source: Option<String>,
+    executable: assembler::Executable,
+}
+
+/// load every file of a program into a machine that has the OS loaded, with
+/// the PC at the program's entry and the registers it's meant to start with.
+/// the OS is returned separately, since it isn't one of the program's files
+fn load_program(
+    program: &ProgramOptions,
+) -> Result<(lc3::Machine, assembler::Executable, Vec<LoadedFile>), String> {
+    let (mut machine, os) = boot(program.fill.unwrap_or(0))?;
+    let files = program
+        .filenames
+        .iter()
+        .map(|filename| load_file(filename, &program.assembly))
+        .collect::<Result<Vec<_>, _>>()?;
+    for file in &files {
+        machine.load(&file.executable);
+    }
+
+    let entry = match &program.entry {
+        Some(entry) => parse_number(entry).or_else(|_| {
+            files
+                .iter()
+                .find_map(|file| file.executable.symbols.get(entry).copied())
+                .ok_or_else(|| format!("--entry {} isn't an address or a label in any file", entry))
+        })?,
+        None => files[0].executable.entry,
+    };
+    machine.set_pc(entry);
+    for (register, value) in &program.registers {
+        machine.set_register(*register, *value);
+    }
+    if program.check_uninitialized {
+        machine.track_uninitialized_reads();
     }
+    Ok((machine, os, files))
+}
 
+fn disassemble_file(options: &FileOptions) -> Result<(), String> {
+    let file = load_file(&options.filename, &options.assembly)?;
+    print!("{}", disassembler::to_string(&file.executable));
     Ok(())
 }
+
+/// step through a program, taking debugger commands from stdin
+struct DebugOptions {
+    program: ProgramOptions,
+    /// debug full screen, instead of at a prompt
+    tui: bool,
+}
+
+/// parse `[--tui] [PROGRAM OPTIONS] FILE...`
+fn parse_debug_options(args: &[String]) -> Result<DebugOptions, String> {
+    let mut tui = false;
+    let mut program = ProgramOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--tui" => tui = true,
+            _ if parse_program_arg(&mut program, arg, &mut args)? => {}
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    program.check()?;
+    Ok(DebugOptions { program, tui })
+}
+
+fn debug_program(options: &DebugOptions) -> Result<(), String> {
+    let (machine, os, files) = load_program(&options.program)?;
+    let mut executables = vec![os];
+    executables.extend(files.into_iter().map(|file| file.executable));
+    let mut debugger = Debugger::new(machine, executables);
+    if options.tui {
+        return tui::run(debugger);
+    }
+
+    println!("type help for a list of commands");
+    println!("{}", debugger.current_instruction());
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        let prompt = if debugger.machine().is_waiting_for_input() {
+            "(input) "
+        } else {
+            "(debug) "
+        };
+        print!("{}", prompt);
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        line.clear();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Ok(());
+        }
+
+        // a program waiting for a key gets the next line typed
+        if debugger.machine().is_waiting_for_input() {
+            debugger.machine_mut().push_input(line.as_bytes());
+            continue;
+        }
+        let command = match Command::parse(&line) {
+            Ok(Command::Quit) => return Ok(()),
+            Ok(command) => command,
+            Err(err) => {
+                println!("{}", err);
+                continue;
+            }
+        };
+        let result = debugger.execute(&command);
+        let output = debugger.machine_mut().take_output();
+        io::stdout().write_all(&output).map_err(|e| e.to_string())?;
+        match result {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => println!("{}", text),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/// describe an address by the source location it was assembled from, if any
+/// of the files know where that is
+fn describe_address(os: &assembler::Executable, files: &[LoadedFile], address: u16) -> String {
+    // later files were loaded over earlier ones, so they're the ones in memory
+    let executables = files.iter().rev().map(|file| &file.executable);
+    debugger::describe_address(executables.chain(Some(os)), address)
+}
+
+fn describe_error(
+    os: &assembler::Executable,
+    files: &[LoadedFile],
+    error: lc3::MachineError,
+) -> String {
+    format!(
+        "{}: {}",
+        describe_address(os, files, error.address),
+        error.message
+    )
+}
+
+/// assemble a source file, or one read from stdin if the filename is `-`, or
+/// read a pre-assembled .obj file along with its .sym file if there is one
+fn load_file(filename: &str, options: &assembler::Options) -> Result<LoadedFile, String> {
+    let path = Path::new(filename);
+    if filename == "-" {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|e| format!("failed to read stdin: {}", e))?;
+        let filename = String::from("<stdin>");
+        Ok(LoadedFile {
+            executable: assemble_source(&filename, &source, options)?,
+            filename,
+            source: Some(source),
+        })
+    } else if path.extension().is_some_and(|extension| extension == "obj") {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", filename, e))?;
+        let mut executable =
+            assembler::object::from_bytes(&bytes).map_err(|e| format!("{}: {}", filename, e))?;
+
+        let sym_path = path.with_extension("sym");
+        if let Ok(sym) = fs::read_to_string(&sym_path) {
+            executable.symbols = assembler::symbols::parse(&sym)
+                .map_err(|e| format!("{}: {}", sym_path.display(), e))?;
+        }
+        Ok(LoadedFile {
+            filename: filename.to_string(),
+            source: None,
+            executable,
+        })
+    } else {
+        let source = fs::read_to_string(filename).map_err(|e| format!("{}", e))?;
+        Ok(LoadedFile {
+            filename: filename.to_string(),
+            executable: assemble_source(filename, &source, options)?,
+            source: Some(source),
+        })
+    }
+}
+
+/// assemble a program, printing any warnings about it to stderr. diagnostics
+/// are only colored when they're going to a terminal
+fn assemble_source(
+    filename: &str,
+    source: &str,
+    options: &assembler::Options,
+) -> Result<assembler::Executable, String> {
+    let color = io::stderr().is_terminal();
+    match assembler::assemble_with_options(filename, source, options) {
+        Ok((executable, warnings)) => {
+            eprint!("{}", render(&warnings, filename, source, color));
+            Ok(executable)
+        }
+        // errors get printed to stderr by `main`
+        Err(diagnostics) => Err(render(&diagnostics, filename, source, color)),
+    }
+}
+
+struct ReplOptions {
+    /// where the first line goes
+    origin: u16,
+    assembly: assembler::Options,
+}
+
+/// parse `[--origin ADDRESS] [ASSEMBLY OPTIONS]`
+fn parse_repl_options(args: &[String]) -> Result<ReplOptions, String> {
+    let mut origin = 0x3000;
+    let mut assembly = assembler::Options::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--origin" => {
+                origin = parse_number(
+                    args.next()
+                        .ok_or_else(|| String::from("--origin needs an address"))?,
+                )?
+            }
+            _ if parse_assembly_flag(&mut assembly, arg, &mut args)? => {}
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(ReplOptions { origin, assembly })
+}
+
+fn repl(options: &ReplOptions) -> Result<(), String> {
+    let (machine, os) = boot(0)?;
+    let mut repl = Repl::new(machine, vec![os], options.origin, options.assembly.clone());
+
+    println!("type a line of assembly to run it, or ctrl-d to quit");
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        if repl.is_waiting_for_input() {
+            print!("(input) ");
+        } else {
+            print!("x{:04X}> ", repl.address());
+        }
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        line.clear();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Ok(());
+        }
+
+        // a line waiting for a key gets the next line typed
+        let result = if repl.is_waiting_for_input() {
+            repl.machine_mut().push_input(line.as_bytes());
+            repl.resume()
+        } else {
+            repl.eval(&line)
+        };
+        let output = repl.machine_mut().take_output();
+        io::stdout().write_all(&output).map_err(|e| e.to_string())?;
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            println!();
+        }
+        match result {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => println!("{}", text),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
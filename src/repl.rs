@@ -0,0 +1,350 @@
+use crate::assembler::diagnostic::{self, Diagnostic};
+use crate::assembler::{self, Executable, Options};
+use crate::debugger::describe_address;
+use crate::diff::{Snapshot, StateDiff};
+use crate::disassembler;
+use crate::lc3::Machine;
+use std::collections::BTreeMap;
+
+/// what diagnostics call the line that was typed
+const FILENAME: &str = "<repl>";
+
+/// how many instructions a line can run before it's given up on, in case it
+/// jumped somewhere it never comes back from
+const LIMIT: u64 = 1_000_000;
+
+/// a line that's been assembled, and is running until the PC gets past it
+struct Running {
+    /// everything the line can change, from before it ran
+    before: Snapshot,
+    /// the address just past the line
+    end: u16,
+    /// how many instructions it's run for so far
+    steps: u64,
+    /// how the line was assembled, to show before what it changed
+    listing: Vec<String>,
+}
+
+/// assembles lines one at a time and runs each as soon as it's been typed,
+/// on a machine that keeps its state between them. each line goes just after
+/// the last, and can use the labels defined by the ones before it. running a
+/// line carries on until the PC reaches the address just past it, so a branch
+/// back to an earlier line runs everything in between again
+pub struct Repl {
+    machine: Machine,
+    /// what's loaded underneath the lines, like the OS. changes to it, like
+    /// trap routines saving registers, are noise so they aren't shown
+    system: Vec<Executable>,
+    /// where the next line goes
+    address: u16,
+    /// every label defined so far
+    symbols: BTreeMap<String, u16>,
+    options: Options,
+    running: Option<Running>,
+}
+
+impl Repl {
+    /// put lines at `origin` onwards, in a machine that `system` has been
+    /// loaded into
+    pub fn new(machine: Machine, system: Vec<Executable>, origin: u16, options: Options) -> Repl {
+        Repl {
+            machine,
+            system,
+            address: origin,
+            symbols: BTreeMap::new(),
+            options,
+            running: None,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// the machine, for things the REPL doesn't do itself, like pushing input
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    /// where the next line will be assembled
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// true if the last line stopped to wait for a key. it carries on with
+    /// `resume` once there's input
+    pub fn is_waiting_for_input(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// assemble a line and run it, returning what it assembled to and what it
+    /// changed. lines of data, like a .FILL, are only loaded
+    pub fn eval(&mut self, line: &str) -> Result<String, String> {
+        if self.running.is_some() {
+            return Err(String::from("the last line is still waiting for input"));
+        }
+        let executable = self.assemble(line)?;
+        let start = self.address;
+        let new_labels: Vec<String> = executable
+            .symbols
+            .iter()
+            .filter(|(name, address)| self.symbols.get(*name) != Some(*address))
+            .map(|(name, address)| format!("{} is x{:04X}", name, address))
+            .collect();
+        let code = executable
+            .debug_info
+            .as_ref()
+            .map(|debug_info| debug_info.code.clone())
+            .unwrap_or_default();
+
+        let labels = disassembler::labels(&executable.symbols);
+        let mut listing = new_labels;
+        for segment in &executable.segments {
+            for (address, word) in segment.addressed_words() {
+                self.machine.set_memory(address, word);
+                let text = if code.contains(&address) {
+                    disassembler::instruction(word, address, &labels)
+                } else {
+                    format!(".FILL x{:04X}", word)
+                };
+                listing.push(format!("x{:04X}  x{:04X}  {}", address, word, text));
+            }
+        }
+        if let Some(segment) = executable.segments.last() {
+            self.address = segment.origin.wrapping_add(segment.words.len() as u16);
+        }
+        self.symbols = executable.symbols;
+        if code.is_empty() {
+            return Ok(listing.join("\n"));
+        }
+
+        // a line after the program halted still runs
+        self.machine.start_clock();
+        self.machine.set_pc(start);
+        self.running = Some(Running {
+            before: Snapshot::of(&self.machine),
+            end: self.address,
+            steps: 0,
+            listing,
+        });
+        self.resume()
+    }
+
+    /// carry on running the last line, once it's been given the input it was
+    /// waiting for
+    pub fn resume(&mut self) -> Result<String, String> {
+        let mut running = match self.running.take() {
+            Some(running) => running,
+            None => return Err(String::from("there's nothing to resume")),
+        };
+        while self.machine.is_running() && self.machine.pc() != running.end {
+            if running.steps == LIMIT {
+                return Err(format!(
+                    "gave up after {} instructions without getting back to x{:04X}",
+                    LIMIT, running.end
+                ));
+            }
+            let result = self.machine.step();
+            running.steps += 1;
+            if let Err(e) = result {
+                return Err(format!(
+                    "{}: {}",
+                    describe_address(&self.system, e.address),
+                    e.message
+                ));
+            }
+            if self.machine.is_waiting_for_input() {
+                self.running = Some(running);
+                return Ok(String::new());
+            }
+        }
+
+        let mut lines = running.listing;
+        lines.extend(self.changes(&running.before));
+        if !self.machine.is_running() {
+            lines.push(String::from("the machine halted"));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// assemble a line at the next address, against every label so far
+    fn assemble(&self, line: &str) -> Result<Executable, String> {
+        let source = format!(".ORIG x{:04X}\n{}", self.address, line);
+        let options = Options {
+            symbols: self.symbols.clone(),
+            ..self.options.clone()
+        };
+        match assembler::assemble_with_options(FILENAME, &source, &options) {
+            Ok((executable, _)) => Ok(executable),
+            // diagnostics point into the line that was typed, not the .ORIG
+            // in front of it
+            Err(diagnostics) => {
+                let diagnostics: Vec<Diagnostic> =
+                    diagnostics.into_iter().map(without_origin).collect();
+                Err(diagnostic::render(&diagnostics, FILENAME, line, false)
+                    .trim_end()
+                    .to_string())
+            }
+        }
+    }
+
+    /// every register, condition code and word of memory that's different to
+    /// how it was. memory that belongs to the system isn't included, and
+    /// neither is the PC, which always moves on past the line
+    fn changes(&self, before: &Snapshot) -> Vec<String> {
+        let diff = StateDiff::between(before, &Snapshot::of(&self.machine))
+            .ignoring(|address| self.is_system(address));
+        StateDiff { pc: None, ..diff }.to_lines()
+    }
+
+    fn is_system(&self, address: u16) -> bool {
+        self.system
+            .iter()
+            .flat_map(|executable| &executable.segments)
+            .any(|segment| segment.contains(address))
+    }
+}
+
+/// move a diagnostic up a line, to point into the source after the `.ORIG`
+fn without_origin(diagnostic: Diagnostic) -> Diagnostic {
+    let up = |span: assembler::Span| assembler::Span {
+        line: span.line.saturating_sub(1),
+        ..span
+    };
+    Diagnostic {
+        span: up(diagnostic.span),
+        labels: diagnostic
+            .labels
+            .into_iter()
+            .map(|label| diagnostic::Label {
+                span: up(label.span),
+                ..label
+            })
+            .collect(),
+        ..diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn repl() -> Repl {
+        let os = assemble("os.asm", include_str!("os.asm")).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&os);
+        Repl::new(machine, vec![os], 0x3000, Options::default())
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut repl = repl();
+        assert_eq!(
+            repl.eval("ADD R1, R1, #-2"),
+            Ok(String::from(
+                "x3000  x127E  ADD R1, R1, #-2
+R1 x0000 -> xFFFE (#-2)
+CC Z -> N"
+            ))
+        );
+        assert_eq!(repl.address(), 0x3001);
+        assert_eq!(
+            repl.eval("data .FILL 5"),
+            Ok(String::from("data is x3001\nx3001  x0005  .FILL x0005"))
+        );
+        assert_eq!(
+            repl.eval("ST R1, data"),
+            Ok(String::from(
+                "x3002  x33FE  ST R1, data
+x3001 x0005 -> xFFFE"
+            ))
+        );
+        assert_eq!(repl.eval("; just a comment"), Ok(String::new()));
+        assert_eq!(repl.machine().pc(), 0x3003);
+    }
+
+    #[test]
+    fn test_loops_run_earlier_lines_again() {
+        let mut repl = repl();
+        repl.eval("AND R0, R0, #0").unwrap();
+        repl.eval("loop ADD R0, R0, #1").unwrap();
+        repl.eval("ADD R1, R0, #-3").unwrap();
+        assert_eq!(
+            repl.eval("BRn loop"),
+            Ok(String::from(
+                "x3003  x09FD  BRn loop
+R0 x0001 -> x0003 (#3)
+R1 xFFFE -> x0000 (#0)
+CC N -> Z"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut repl = repl();
+        assert_eq!(
+            repl.eval("msg .STRINGZ \"hi\""),
+            Ok(String::from(
+                "msg is x3000
+x3000  x0068  .FILL x0068
+x3001  x0069  .FILL x0069
+x3002  x0000  .FILL x0000"
+            ))
+        );
+        repl.eval("LEA R0, msg").unwrap();
+        // the trap leaves its return address in R7, but the OS saving
+        // registers while it prints isn't a change worth showing
+        assert_eq!(
+            repl.eval("PUTS"),
+            Ok(String::from(
+                "x3004  xF022  PUTS
+R7 x0000 -> x3005 (#12293)
+CC Z -> P"
+            ))
+        );
+        assert_eq!(repl.machine_mut().take_output(), b"hi");
+
+        assert_eq!(repl.eval("GETC"), Ok(String::new()));
+        assert!(repl.is_waiting_for_input());
+        assert!(repl.eval("HALT").is_err());
+        repl.machine_mut().push_input(b"a");
+        assert_eq!(
+            repl.resume(),
+            Ok(String::from(
+                "x3005  xF020  GETC
+R0 x3000 -> x0061 (#97)
+R7 x3005 -> x3006 (#12294)"
+            ))
+        );
+
+        assert!(repl.eval("HALT").unwrap().ends_with("the machine halted"));
+        // the clock starts again for the next line
+        assert_eq!(
+            repl.eval("ADD R2, R2, #1").unwrap().lines().next(),
+            Some("x3007  x14A1  ADD R2, R2, #1")
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut repl = repl();
+        assert_eq!(
+            repl.eval("ADD R0, R0, nowhere"),
+            Err(String::from(
+                "error: undefined label: nowhere
+ --> <repl>:1:13
+  |
+1 | ADD R0, R0, nowhere
+  |             ^^^^^^^"
+            ))
+        );
+        // nothing was loaded, so the next line goes in the same place
+        assert_eq!(repl.address(), 0x3000);
+        assert!(repl.resume().is_err());
+
+        repl.eval("forever BR forever").unwrap_err();
+        assert_eq!(repl.address(), 0x3001);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::lc3::{Machine, MachineError};
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// what ctrl-c sends once the terminal is in raw mode
+pub(crate) const CTRL_C: u8 = 0x03;
+
+/// the host terminal in raw mode: key presses are sent as soon as they're
+/// typed, without waiting for enter and without being echoed. ctrl-c is sent
+/// as a key too, rather than killing the emulator with the terminal still in
+/// raw mode. the terminal is restored when this is dropped, which includes
+/// unwinding from a panic
+pub struct RawMode {
+    /// the terminal's settings from before, as `stty -g` prints them
+    saved: String,
+}
+
+impl RawMode {
+    /// switch to raw mode, if stdin is a terminal that `stty` can configure
+    pub fn enable() -> Option<RawMode> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+/// run `stty` on the terminal stdin is attached to, returning what it printed
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        None
+    }
+}
+
+/// run until the program halts, using the terminal as the keyboard and display.
+/// the terminal is in raw mode while the program runs, if stdin is a terminal,
+/// so GETC and IN get each key the moment it's pressed
+pub fn run(machine: &mut Machine) -> Result<(), MachineError> {
+    let raw_mode = RawMode::enable();
+    let keys = read_keys();
+    while machine.is_running() {
+        step(machine, &keys, raw_mode.is_some())?;
+    }
+    Ok(())
+}
+
+/// the number of rows and columns the terminal stdin is attached to has
+pub(crate) fn size() -> Option<(usize, usize)> {
+    let size = stty(&["size"])?;
+    let mut size = size.split_whitespace().map(str::parse);
+    match (size.next(), size.next()) {
+        (Some(Ok(rows)), Some(Ok(columns))) => Some((rows, columns)),
+        _ => None,
+    }
+}
+
+/// read stdin on another thread, so key presses can be picked up between
+/// instructions without blocking. stdin closing closes the channel
+pub(crate) fn read_keys() -> Receiver<io::Result<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for key in io::stdin().lock().bytes() {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// execute a single instruction, then display what it wrote and hand it any
+/// keys that have been pressed. only blocks if the program is waiting for a key.
+/// in raw mode, ctrl-c stops the program
+fn step(
+    machine: &mut Machine,
+    keys: &Receiver<io::Result<u8>>,
+    raw_mode: bool,
+) -> Result<(), MachineError> {
+    let address = machine.pc();
+    machine.step()?;
+    let error = |message| MachineError { message, address };
+
+    let output = machine.take_output();
+    if !output.is_empty() {
+        let mut stdout = io::stdout();
+        stdout
+            .write_all(&output)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| error(format!("failed to write output: {}", e)))?;
+    }
+
+    loop {
+        let key = match keys.try_recv() {
+            Ok(key) => key,
+            Err(_) if !machine.is_waiting_for_input() => return Ok(()),
+            // nothing else can happen until a key is pressed
+            Err(_) => match keys.recv() {
+                Ok(key) => key,
+                Err(_) => return Err(error(String::from("unexpected end of input"))),
+            },
+        };
+        match key {
+            Ok(CTRL_C) if raw_mode => return Err(error(String::from("interrupted"))),
+            Ok(key) => machine.push_input(&[key]),
+            Err(e) => return Err(error(format!("failed to read input: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn echo() -> Machine {
+        let mut machine = Machine::new();
+        machine.load(
+            &assemble(
+                "echo.asm",
+                ".ORIG x3000\nloop LDI R0, kbsr\nBRzp loop\nLDI R0, kbdr\nBR loop
+                kbsr .FILL xFE00\nkbdr .FILL xFE02",
+            )
+            .unwrap(),
+        );
+        machine
+    }
+
+    #[test]
+    fn test_step_reads_pressed_keys() {
+        let mut machine = echo();
+        let (sender, keys) = mpsc::channel();
+        sender.send(Ok(b'a')).unwrap();
+        sender.send(Ok(b'b')).unwrap();
+        for _ in 0..5 {
+            step(&mut machine, &keys, true).unwrap();
+        }
+        assert_eq!(machine.registers()[0], u16::from(b'a'));
+        for _ in 0..4 {
+            step(&mut machine, &keys, true).unwrap();
+        }
+        assert_eq!(machine.registers()[0], u16::from(b'b'));
+
+        // once stdin is closed, there's nothing left to wait for
+        drop(sender);
+        step(&mut machine, &keys, true).unwrap();
+        assert_eq!(
+            step(&mut machine, &keys, true).unwrap_err().message,
+            "unexpected end of input"
+        );
+    }
+
+    #[test]
+    fn test_ctrl_c_interrupts_in_raw_mode() {
+        let mut machine = echo();
+        let (sender, keys) = mpsc::channel();
+        sender.send(Ok(CTRL_C)).unwrap();
+        assert_eq!(
+            step(&mut machine, &keys, true),
+            Err(MachineError {
+                message: String::from("interrupted"),
+                address: 0x3000,
+            })
+        );
+
+        // without raw mode, it's just another key
+        let mut machine = echo();
+        sender.send(Ok(CTRL_C)).unwrap();
+        for _ in 0..5 {
+            step(&mut machine, &keys, false).unwrap();
+        }
+        assert_eq!(machine.registers()[0], u16::from(CTRL_C));
+    }
+}
@@ -0,0 +1,253 @@
+//! per-instruction traces of a run, in a plain text format that other
+//! simulators' output can be converted to, so the same program can be run in
+//! both and the traces diffed. each line is one executed instruction: where
+//! it was, the word that was executed, and the registers and condition code
+//! after it ran
+//!
+//! ```text
+//! PC=x3000 IR=x127E R0=x0000 R1=xFFFE R2=x0000 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000 CC=N
+//! ```
+//!
+//! blank lines and lines starting with `#` are ignored when reading one back
+
+use crate::lc3::{Machine, MachineError};
+
+/// one executed instruction, and the state it left the machine in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// the address the instruction was executed from
+    pub pc: u16,
+    /// the instruction
+    pub ir: u16,
+    pub registers: [u16; 8],
+    /// `'N'`, `'Z'` or `'P'`
+    pub condition: char,
+}
+
+impl Entry {
+    /// render the entry as a line of a trace, without the newline
+    pub fn to_line(&self) -> String {
+        let mut line = format!("PC=x{:04X} IR=x{:04X}", self.pc, self.ir);
+        for (index, value) in self.registers.iter().enumerate() {
+            line.push_str(&format!(" R{}=x{:04X}", index, value));
+        }
+        line.push_str(&format!(" CC={}", self.condition));
+        line
+    }
+
+    /// every field that's different in `other`, as the `NAME=VALUE` it has
+    /// in each
+    fn differences(&self, other: &Entry) -> Vec<(String, String)> {
+        // the fields line up, since both lines are rendered the same way
+        self.to_line()
+            .split(' ')
+            .zip(other.to_line().split(' '))
+            .filter(|(ours, theirs)| ours != theirs)
+            .map(|(ours, theirs)| (ours.to_string(), theirs.to_string()))
+            .collect()
+    }
+}
+
+/// execute one instruction, returning the trace entry for it
+pub fn step(machine: &mut Machine) -> Result<Entry, MachineError> {
+    let pc = machine.pc();
+    let ir = machine.memory(pc);
+    machine.step()?;
+    Ok(Entry {
+        pc,
+        ir,
+        registers: machine.registers(),
+        condition: machine.condition(),
+    })
+}
+
+/// render a whole trace, one line per entry
+pub fn to_string(entries: &[Entry]) -> String {
+    entries.iter().map(|entry| entry.to_line() + "\n").collect()
+}
+
+/// read back a trace written by `to_string`, or converted from another
+/// simulator. hex digits and field names can be in either case, and the
+/// fields can be separated by any whitespace, but all of them are needed
+pub fn parse(trace: &str) -> Result<Vec<Entry>, String> {
+    trace
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| parse_line(line).map_err(|e| format!("line {}: {}", index + 1, e)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<Entry, String> {
+    let mut pc = None;
+    let mut ir = None;
+    let mut registers = [None; 8];
+    let mut condition = None;
+    for field in line.split_whitespace() {
+        let (name, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("expected a field like R0=x0000: {}", field))?;
+        let name = name.to_ascii_uppercase();
+        let slot = match name.as_str() {
+            "CC" => {
+                condition = match value.to_ascii_uppercase().as_str() {
+                    "N" => Some('N'),
+                    "Z" => Some('Z'),
+                    "P" => Some('P'),
+                    _ => return Err(format!("expected CC to be N, Z or P: {}", value)),
+                };
+                continue;
+            }
+            "PC" => &mut pc,
+            "IR" => &mut ir,
+            _ => match name
+                .strip_prefix('R')
+                .and_then(|index| index.parse::<usize>().ok())
+            {
+                Some(index) if index < 8 => &mut registers[index],
+                _ => return Err(format!("unknown field: {}", name)),
+            },
+        };
+        *slot = Some(parse_word(value)?);
+    }
+
+    let missing = |name: &str| format!("missing {}", name);
+    let mut values = [0; 8];
+    for (index, register) in registers.iter().enumerate() {
+        values[index] = register.ok_or_else(|| missing(&format!("R{}", index)))?;
+    }
+    Ok(Entry {
+        pc: pc.ok_or_else(|| missing("PC"))?,
+        ir: ir.ok_or_else(|| missing("IR"))?,
+        registers: values,
+        condition: condition.ok_or_else(|| missing("CC"))?,
+    })
+}
+
+/// a word written as hex, with or without an `x` or `0x` in front
+fn parse_word(value: &str) -> Result<u16, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .or_else(|| value.strip_prefix('x'))
+        .or_else(|| value.strip_prefix('X'))
+        .unwrap_or(value);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("expected a hex word: {}", value))
+}
+
+/// check two traces of the same program match, describing the first
+/// instruction where they don't
+pub fn compare(expected: &[Entry], actual: &[Entry]) -> Result<(), String> {
+    for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        let differences = expected.differences(actual);
+        if differences.is_empty() {
+            continue;
+        }
+        let mut message = format!(
+            "the traces differ at instruction {}, at x{:04X}:",
+            index + 1,
+            expected.pc
+        );
+        for (expected, actual) in differences {
+            message.push_str(&format!("\n    expected {}, got {}", expected, actual));
+        }
+        return Err(message);
+    }
+    if expected.len() != actual.len() {
+        return Err(format!(
+            "the traces match until one ends: the expected trace has {} instructions and this one has {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn trace(source: &str) -> Vec<Entry> {
+        let mut machine = Machine::new();
+        machine.load(&assemble("trace.asm", source).unwrap());
+        let mut entries = Vec::new();
+        while machine.is_running() {
+            match step(&mut machine) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => break,
+            }
+        }
+        entries
+    }
+
+    const SOURCE: &str = ".ORIG x3000\nADD R1, R1, #-2\nADD R2, R1, #3\n.FILL xD000";
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(
+            to_string(&trace(SOURCE)),
+            "PC=x3000 IR=x127E R0=x0000 R1=xFFFE R2=x0000 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000 CC=N
+PC=x3001 IR=x1463 R0=x0000 R1=xFFFE R2=x0001 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000 CC=P
+"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let entries = trace(SOURCE);
+        assert_eq!(parse(&to_string(&entries)), Ok(entries.clone()));
+        // lowercase, in another order, with other spacing and comments
+        assert_eq!(
+            parse(
+                "# from another simulator\n\n\
+                 cc=n  ir=0x127e pc=3000 r0=x0 r1=xfffe r2=0 r3=0 r4=0 r5=0 r6=0 r7=0"
+            ),
+            Ok(entries[..1].to_vec())
+        );
+
+        assert_eq!(
+            parse("\nPC=x3000 IR=x127E"),
+            Err(String::from("line 2: missing R0"))
+        );
+        assert_eq!(
+            parse("PC=x3000 R8=x0000"),
+            Err(String::from("line 1: unknown field: R8"))
+        );
+        assert_eq!(
+            parse("PC=x30000"),
+            Err(String::from("line 1: expected a hex word: x30000"))
+        );
+        assert_eq!(
+            parse("CC=Q"),
+            Err(String::from("line 1: expected CC to be N, Z or P: Q"))
+        );
+        assert_eq!(
+            parse("PC"),
+            Err(String::from("line 1: expected a field like R0=x0000: PC"))
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = trace(SOURCE);
+        assert_eq!(compare(&expected, &expected), Ok(()));
+
+        let actual = trace(".ORIG x3000\nADD R1, R1, #-2\nADD R2, R1, #1\n.FILL xD000");
+        assert_eq!(
+            compare(&expected, &actual),
+            Err(String::from(
+                "the traces differ at instruction 2, at x3001:
+    expected IR=x1463, got IR=x1461
+    expected R2=x0001, got R2=xFFFF
+    expected CC=P, got CC=N"
+            ))
+        );
+        assert_eq!(
+            compare(&expected, &expected[..1]),
+            Err(String::from(
+                "the traces match until one ends: the expected trace has 2 instructions and this one has 1"
+            ))
+        );
+    }
+}
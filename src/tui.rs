@@ -0,0 +1,596 @@
+use crate::debugger::{Command, Debugger, Stop};
+use crate::disassembler;
+use crate::lc3::MachineError;
+use crate::terminal::{self, RawMode, CTRL_C};
+use std::io::{self, Write};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+/// how many instructions to run between redrawing the screen and checking for
+/// key presses while the program is running
+const CHUNK: u64 = 10_000;
+
+/// how many rows of eight words the memory pane shows
+const MEMORY_ROWS: u16 = 4;
+
+/// the most the console pane holds on to, so a program that prints forever
+/// doesn't use up all the memory there is
+const CONSOLE_LIMIT: usize = 64 * 1024;
+
+/// the size to assume if the terminal's size can't be found out
+const DEFAULT_SIZE: (usize, usize) = (24, 80);
+
+/// how long to wait for the rest of an escape sequence, like an arrow key
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+const ESCAPE: u8 = 0x1B;
+
+/// the keys that do something while the program is paused
+const KEYS: &str =
+    "s step  c continue  b break  \u{2191}\u{2193} move  m memory  i input  : command  q quit";
+
+/// a key that was pressed. anything other than the keys that send escape
+/// sequences is the byte it sends
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Byte(u8),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+}
+
+/// what key presses do at the moment
+#[derive(Clone, Debug, PartialEq)]
+enum Mode {
+    /// keys are commands, like s to step
+    Paused,
+    /// the program is running, and keys are its input. ctrl-c pauses it
+    Running,
+    /// the next key is sent to the program
+    Input,
+    /// a debugger command is being typed after a `:`
+    Command(String),
+}
+
+/// everything on the screen, as rows of text, and which row the cursor is on
+#[derive(Debug, PartialEq)]
+struct Frame {
+    rows: Vec<String>,
+    cursor: Option<usize>,
+}
+
+/// a full screen debugger, drawn over a `Debugger`
+pub struct Tui {
+    debugger: Debugger,
+    mode: Mode,
+    /// the address breakpoints are toggled at, moved with the arrow keys. it
+    /// goes back to the PC whenever the program stops
+    cursor: u16,
+    /// the first address in the disassembly pane
+    top: u16,
+    /// the first address in the memory pane
+    memory: u16,
+    /// what the program has written
+    console: String,
+    /// what happened last
+    message: String,
+}
+
+impl Tui {
+    pub fn new(debugger: Debugger) -> Tui {
+        let pc = debugger.machine().pc();
+        let message = debugger.current_instruction();
+        Tui {
+            debugger,
+            mode: Mode::Paused,
+            cursor: pc,
+            top: pc,
+            memory: pc,
+            console: String::new(),
+            message,
+        }
+    }
+
+    /// respond to a key press. returns false once it's time to quit
+    fn press(&mut self, key: Key) -> bool {
+        match (&mut self.mode, key) {
+            (Mode::Running, Key::Byte(CTRL_C)) => {
+                self.mode = Mode::Paused;
+                self.cursor = self.debugger.machine().pc();
+                self.message = format!("paused: {}", self.debugger.current_instruction());
+            }
+            (Mode::Running, Key::Byte(byte)) => self.debugger.machine_mut().push_input(&[byte]),
+            (Mode::Input, Key::Byte(CTRL_C | ESCAPE)) => {
+                self.mode = Mode::Paused;
+                self.message = String::from("didn't send anything");
+            }
+            (Mode::Input, Key::Byte(byte)) => {
+                self.debugger.machine_mut().push_input(&[byte]);
+                self.mode = Mode::Paused;
+                self.message = format!("sent x{:02X} to the program", byte);
+            }
+            (Mode::Command(_), Key::Byte(CTRL_C | ESCAPE)) => self.mode = Mode::Paused,
+            (Mode::Command(line), Key::Byte(b'\n' | b'\r')) => {
+                let line = line.clone();
+                self.mode = Mode::Paused;
+                return self.execute(&line);
+            }
+            (Mode::Command(line), Key::Byte(0x08 | 0x7F)) => {
+                line.pop();
+            }
+            (Mode::Command(line), Key::Byte(byte @ 0x20..=0x7E)) => line.push(char::from(byte)),
+            (Mode::Running | Mode::Input | Mode::Command(_), _) => {}
+            (Mode::Paused, Key::Byte(b'q' | CTRL_C)) => return false,
+            (Mode::Paused, Key::Byte(b's' | b' ' | b'\n' | b'\r')) => {
+                let stop = self.debugger.step(1);
+                self.stop(stop);
+            }
+            (Mode::Paused, Key::Byte(b'c')) => self.resume(),
+            (Mode::Paused, Key::Byte(b'b')) => {
+                let description = self.debugger.describe(self.cursor);
+                self.message = if self.debugger.remove_breakpoint(self.cursor) {
+                    format!("deleted the breakpoint at {}", description)
+                } else {
+                    self.debugger.add_breakpoint(self.cursor);
+                    format!("breakpoint at {}", description)
+                };
+            }
+            (Mode::Paused, Key::Up | Key::Byte(b'k')) => self.cursor = self.cursor.wrapping_sub(1),
+            (Mode::Paused, Key::Down | Key::Byte(b'j')) => {
+                self.cursor = self.cursor.wrapping_add(1)
+            }
+            (Mode::Paused, Key::Byte(b'm')) => self.memory = self.cursor,
+            (Mode::Paused, Key::Byte(b'[')) => self.memory = self.memory.wrapping_sub(8),
+            (Mode::Paused, Key::Byte(b']')) => self.memory = self.memory.wrapping_add(8),
+            (Mode::Paused, Key::PageUp) => self.memory = self.memory.wrapping_sub(MEMORY_ROWS * 8),
+            (Mode::Paused, Key::PageDown) => {
+                self.memory = self.memory.wrapping_add(MEMORY_ROWS * 8)
+            }
+            (Mode::Paused, Key::Byte(b'i')) => self.mode = Mode::Input,
+            (Mode::Paused, Key::Byte(b':')) => self.mode = Mode::Command(String::new()),
+            (Mode::Paused, _) => {}
+        }
+        true
+    }
+
+    /// carry out a command typed after a `:`. returns false if it was quit
+    fn execute(&mut self, line: &str) -> bool {
+        match Command::parse(line) {
+            Ok(Command::Quit) => return false,
+            // continuing could take forever, so it has to be able to be paused
+            Ok(Command::Continue) => self.resume(),
+            Ok(command) => {
+                let result = self.debugger.execute(&command);
+                self.take_output();
+                self.cursor = self.debugger.machine().pc();
+                self.message = result.unwrap_or_else(|err| err);
+            }
+            Err(err) => self.message = err,
+        }
+        true
+    }
+
+    /// start running the program. the instruction at the PC always runs, even
+    /// if there's a breakpoint on it
+    fn resume(&mut self) {
+        self.mode = Mode::Running;
+        let stop = self.debugger.step(1);
+        self.stop(stop);
+    }
+
+    /// run some more of the program, if it's running and isn't waiting for a key
+    fn run_for(&mut self, count: u64) {
+        if self.mode != Mode::Running || self.debugger.machine().is_waiting_for_input() {
+            return;
+        }
+        let pc = self.debugger.machine().pc();
+        let stop = if self.debugger.breakpoints().contains(&pc) {
+            Ok(Stop::Breakpoint(pc))
+        } else {
+            self.debugger.step(count)
+        };
+        self.stop(stop);
+    }
+
+    /// deal with the program stopping. a running program carries on unless
+    /// it reached a breakpoint, a watchpoint or the end
+    fn stop(&mut self, stop: Result<Stop, MachineError>) {
+        self.take_output();
+        if self.mode == Mode::Running {
+            if let Ok(Stop::Stepped | Stop::WaitingForInput) = stop {
+                return;
+            }
+        }
+        self.mode = Mode::Paused;
+        self.cursor = self.debugger.machine().pc();
+        self.message = self.debugger.report(stop).unwrap_or_else(|err| err);
+    }
+
+    fn take_output(&mut self) {
+        let output = self.debugger.machine_mut().take_output();
+        self.console.push_str(&String::from_utf8_lossy(&output));
+        if self.console.len() > CONSOLE_LIMIT {
+            let mut start = self.console.len() - CONSOLE_LIMIT;
+            while !self.console.is_char_boundary(start) {
+                start += 1;
+            }
+            self.console.drain(..start);
+        }
+    }
+
+    /// lay the screen out for a terminal of the given size: the registers at
+    /// the top, then the disassembly around the cursor, memory and the console,
+    /// with what happened last and the keys at the bottom
+    fn render(&mut self, rows: usize, columns: usize) -> Frame {
+        let machine = self.debugger.machine();
+        let registers = machine.registers();
+        let register_row = |range: std::ops::Range<usize>| {
+            range
+                .map(|index| format!("R{} x{:04X}", index, registers[index]))
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let mut state = format!("PC x{:04X}  CC {}", machine.pc(), machine.condition());
+        if !machine.is_running() {
+            state.push_str("  halted");
+        } else if machine.is_waiting_for_input() {
+            state.push_str("  waiting for input");
+        }
+        let mut lines = vec![
+            title("registers", columns),
+            register_row(0..4),
+            register_row(4..8),
+            state,
+        ];
+
+        // whatever isn't used by the other panes is shared between the
+        // disassembly and the console
+        let fixed = lines.len() + 1 + 1 + MEMORY_ROWS as usize + 1 + 2;
+        let remaining = rows.saturating_sub(fixed);
+        let console_rows = remaining / 3;
+        let disassembly_rows = remaining - console_rows;
+
+        lines.push(title("disassembly", columns));
+        if usize::from(self.cursor.wrapping_sub(self.top)) >= disassembly_rows {
+            self.top = self.cursor.wrapping_sub((disassembly_rows / 4) as u16);
+        }
+        let cursor = lines.len() + usize::from(self.cursor.wrapping_sub(self.top));
+        let labels = self.debugger.labels();
+        let width = labels
+            .values()
+            .map(|label| label.len() + 1)
+            .max()
+            .unwrap_or(0);
+        for offset in 0..disassembly_rows {
+            let address = self.top.wrapping_add(offset as u16);
+            let word = machine.memory(address);
+            let line = format!(
+                "{}{} x{:04X}  x{:04X}  {:width$}{}",
+                if address == machine.pc() { "=>" } else { "  " },
+                if self.debugger.breakpoints().contains(&address) {
+                    "*"
+                } else {
+                    " "
+                },
+                address,
+                word,
+                labels.get(&address).copied().unwrap_or(""),
+                disassembler::instruction(word, address, &labels),
+                width = width
+            );
+            lines.push(line);
+        }
+
+        lines.push(title("memory", columns));
+        for row in 0..MEMORY_ROWS {
+            let start = self.memory.wrapping_add(row * 8);
+            let words: Vec<u16> = (0..8)
+                .map(|offset| machine.memory(start.wrapping_add(offset)))
+                .collect();
+            let hex: Vec<String> = words.iter().map(|word| format!("x{:04X}", word)).collect();
+            let text: String = words
+                .iter()
+                .map(|word| match *word {
+                    0x20..=0x7E => char::from(*word as u8),
+                    _ => '.',
+                })
+                .collect();
+            lines.push(format!("x{:04X}  {}  {}", start, hex.join(" "), text));
+        }
+
+        lines.push(title("console", columns));
+        let mut console: Vec<String> = self
+            .console
+            .split('\n')
+            .flat_map(|line| wrap(line.trim_end_matches('\r'), columns))
+            .collect();
+        // the last line is the one being written to, and it's empty after a
+        // newline
+        if console.last().is_some_and(String::is_empty) {
+            console.pop();
+        }
+        let skipped = console.len().saturating_sub(console_rows);
+        lines.extend(console.into_iter().skip(skipped));
+        lines.resize(rows.saturating_sub(2), String::new());
+
+        let status = match &self.mode {
+            Mode::Paused => self
+                .message
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("  "),
+            Mode::Running => String::from("running, ctrl-c to pause"),
+            Mode::Input => String::from("press a key to send it to the program, or escape"),
+            Mode::Command(line) => format!(":{}", line),
+        };
+        lines.push(status);
+        lines.push(String::from(KEYS));
+        lines.truncate(rows);
+
+        Frame {
+            rows: lines
+                .into_iter()
+                .map(|line| line.chars().take(columns).collect())
+                .collect(),
+            cursor: Some(cursor).filter(|cursor| *cursor < rows.saturating_sub(2)),
+        }
+    }
+}
+
+/// a row that starts a pane
+fn title(name: &str, columns: usize) -> String {
+    let title = format!("\u{2500}\u{2500} {} ", name);
+    let len = title.chars().count();
+    title + &"\u{2500}".repeat(columns.saturating_sub(len))
+}
+
+/// split a line into rows no wider than `columns`
+fn wrap(line: &str, columns: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() || columns == 0 {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(columns)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+/// the terminal's alternate screen, with the cursor hidden, which is left when
+/// this is dropped so whatever was on the terminal before comes back
+struct AlternateScreen;
+
+impl AlternateScreen {
+    fn enter() -> io::Result<AlternateScreen> {
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(AlternateScreen)
+    }
+}
+
+impl Drop for AlternateScreen {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+    }
+}
+
+/// draw a frame over the last one, with the cursor's row highlighted
+fn draw(frame: &Frame, columns: usize, out: &mut impl Write) -> io::Result<()> {
+    let mut screen = String::from("\x1b[H");
+    for (index, row) in frame.rows.iter().enumerate() {
+        if index > 0 {
+            screen.push_str("\r\n");
+        }
+        if frame.cursor == Some(index) {
+            let padding = columns.saturating_sub(row.chars().count());
+            screen.push_str(&format!("\x1b[7m{}{}\x1b[0m", row, " ".repeat(padding)));
+        } else {
+            screen.push_str(row);
+            screen.push_str("\x1b[K");
+        }
+    }
+    screen.push_str("\x1b[J");
+    out.write_all(screen.as_bytes())?;
+    out.flush()
+}
+
+/// wait for the next key press. returns `None` once stdin is closed
+fn read_key(keys: &Receiver<io::Result<u8>>) -> io::Result<Option<Key>> {
+    match keys.recv() {
+        Ok(byte) => decode(byte?, keys).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// turn the bytes a key sends into the key, reading the rest of the escape
+/// sequence if it sends one. an escape on its own is just an escape
+fn decode(first: u8, keys: &Receiver<io::Result<u8>>) -> io::Result<Key> {
+    if first != ESCAPE {
+        return Ok(Key::Byte(first));
+    }
+    let next = || keys.recv_timeout(ESCAPE_TIMEOUT).ok().transpose();
+    if next()? != Some(b'[') {
+        return Ok(Key::Byte(ESCAPE));
+    }
+    Ok(match next()? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'5') if next()? == Some(b'~') => Key::PageUp,
+        Some(b'6') if next()? == Some(b'~') => Key::PageDown,
+        _ => Key::Byte(ESCAPE),
+    })
+}
+
+/// debug a program full screen, until q is pressed. stdin has to be a terminal
+pub fn run(debugger: Debugger) -> Result<(), String> {
+    let _raw_mode =
+        RawMode::enable().ok_or_else(|| String::from("the TUI needs stdin to be a terminal"))?;
+    let _screen = AlternateScreen::enter().map_err(|e| e.to_string())?;
+    let keys = terminal::read_keys();
+    let mut tui = Tui::new(debugger);
+    let mut size = terminal::size().unwrap_or(DEFAULT_SIZE);
+    loop {
+        let (rows, columns) = size;
+        draw(&tui.render(rows, columns), columns, &mut io::stdout()).map_err(|e| e.to_string())?;
+
+        if tui.mode == Mode::Running && !tui.debugger.machine().is_waiting_for_input() {
+            tui.run_for(CHUNK);
+            // keys pressed while it ran are its input, or ctrl-c
+            while let Ok(byte) = keys.try_recv() {
+                let key = byte.and_then(|byte| decode(byte, &keys));
+                tui.press(key.map_err(|e| e.to_string())?);
+            }
+            continue;
+        }
+        match read_key(&keys).map_err(|e| e.to_string())? {
+            Some(key) if tui.press(key) => {}
+            _ => return Ok(()),
+        }
+        size = terminal::size().unwrap_or(size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::lc3::Machine;
+    use std::sync::mpsc;
+
+    const SOURCE: &str = ".ORIG x3000
+        AND R1, R1, #0
+poll    LDI R0, kbsr
+        BRzp poll
+        LDI R0, kbdr
+        STI R0, ddr
+        ADD R1, R1, #1
+        BR poll
+kbsr    .FILL xFE00
+kbdr    .FILL xFE02
+ddr     .FILL xFE06";
+
+    fn tui() -> Tui {
+        let executable = assemble("echo.asm", SOURCE).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&executable);
+        Tui::new(Debugger::new(machine, vec![executable]))
+    }
+
+    fn press(tui: &mut Tui, keys: &str) {
+        for byte in keys.bytes() {
+            assert!(tui.press(Key::Byte(byte)));
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let mut tui = tui();
+        press(&mut tui, "sjb");
+        tui.console
+            .push_str("hello\nthis line is long enough that it's split in two\n");
+        let frame = tui.render(20, 40);
+        assert_eq!(
+            frame.rows.join("\n"),
+            "── registers ───────────────────────────
+R0 x0000  R1 x0000  R2 x0000  R3 x0000
+R4 x0000  R5 x0000  R6 x0000  R7 x0000
+PC x3001  CC Z
+── disassembly ─────────────────────────
+    x3000  x5260       AND R1, R1, #0
+=>  x3001  xA005  poll LDI R0, kbsr
+  * x3002  x07FE       BRzp poll
+    x3003  xA004       LDI R0, kbdr
+    x3004  xB004       STI R0, ddr
+── memory ──────────────────────────────
+x3000  x5260 xA005 x07FE xA004 xB004 x12
+x3008  xFE02 xFE06 x0000 x0000 x0000 x00
+x3010  x0000 x0000 x0000 x0000 x0000 x00
+x3018  x0000 x0000 x0000 x0000 x0000 x00
+── console ─────────────────────────────
+this line is long enough that it's split
+ in two
+breakpoint at echo.asm:4:9 (x3002)
+s step  c continue  b break  ↑↓ move  m "
+        );
+        assert_eq!(frame.cursor, Some(7));
+
+        // a terminal too small for every pane still gets the status line
+        let frame = tui.render(3, 40);
+        assert_eq!(frame.rows.len(), 3);
+        assert_eq!(frame.rows[2], "s step  c continue  b break  ↑↓ move  m ");
+    }
+
+    #[test]
+    fn test_running() {
+        let mut tui = tui();
+        press(&mut tui, "c");
+        assert_eq!(tui.mode, Mode::Running);
+        tui.run_for(CHUNK);
+        assert!(tui.debugger.machine().is_waiting_for_input());
+
+        // keys go to the program while it's running
+        press(&mut tui, "hi");
+        tui.run_for(CHUNK);
+        assert_eq!(tui.console, "hi");
+        assert_eq!(tui.debugger.machine().registers()[1], 2);
+
+        // until ctrl-c pauses it
+        assert!(tui.press(Key::Byte(CTRL_C)));
+        assert_eq!(tui.mode, Mode::Paused);
+        tui.debugger.add_breakpoint(0x3005);
+        press(&mut tui, "i!c");
+        tui.run_for(CHUNK);
+        assert_eq!(tui.mode, Mode::Paused);
+        assert_eq!(tui.debugger.machine().pc(), 0x3005);
+        assert_eq!(tui.cursor, 0x3005);
+        assert_eq!(tui.console, "hi!");
+        assert_eq!(
+            tui.message,
+            "breakpoint: echo.asm:7:9 (x3005)  ADD R1, R1, #1"
+        );
+
+        // the breakpoint is stepped over when it carries on
+        press(&mut tui, "ca");
+        tui.run_for(CHUNK);
+        assert_eq!(tui.mode, Mode::Paused);
+        assert_eq!(tui.debugger.machine().pc(), 0x3005);
+        assert_eq!(tui.debugger.machine().registers()[1], 3);
+        assert_eq!(tui.console, "hi!a");
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut tui = tui();
+        press(&mut tui, ":set r3 x1234\n");
+        assert_eq!(tui.debugger.machine().registers()[3], 0x1234);
+        press(&mut tui, ":b kbsrr\x7F\n");
+        assert_eq!(
+            tui.debugger.breakpoints().iter().collect::<Vec<_>>(),
+            vec![&0x3007]
+        );
+        press(&mut tui, ":nope\n");
+        assert_eq!(tui.message, "unknown command: nope");
+        press(&mut tui, ":s 2\x1b");
+        assert_eq!(tui.debugger.machine().pc(), 0x3000);
+        assert!(!tui.press(Key::Byte(b'q')));
+    }
+
+    #[test]
+    fn test_decode() {
+        let (sender, keys) = mpsc::channel();
+        for byte in b"[A[6~x" {
+            sender.send(Ok(*byte)).unwrap();
+        }
+        assert_eq!(decode(ESCAPE, &keys).unwrap(), Key::Up);
+        assert_eq!(decode(ESCAPE, &keys).unwrap(), Key::PageDown);
+        assert_eq!(read_key(&keys).unwrap(), Some(Key::Byte(b'x')));
+        assert_eq!(decode(ESCAPE, &keys).unwrap(), Key::Byte(ESCAPE));
+        drop(sender);
+        assert_eq!(read_key(&keys).unwrap(), None);
+    }
+}
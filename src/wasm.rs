@@ -0,0 +1,157 @@
+//! bindings for running the assembler and emulator from JavaScript, built with
+//! the `wasm` feature. build the module with `cargo rustc --lib --release
+//! --target wasm32-unknown-unknown --features wasm --crate-type cdylib`, then
+//! generate its JavaScript with `wasm-bindgen --target web`. a `Session` shows
+//! up there as the class `Lc3`
+
+use crate::assembler::{self, Executable};
+use crate::lc3::Machine;
+use wasm_bindgen::prelude::*;
+
+/// why `step` stopped
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// every step it was asked to take was taken
+    Stepped,
+    Halted,
+    /// the program is waiting for a key
+    WaitingForInput,
+}
+
+/// a machine with the OS loaded, and the program most recently assembled
+#[wasm_bindgen(js_name = Lc3)]
+pub struct Session {
+    machine: Machine,
+    program: Option<Executable>,
+}
+
+#[wasm_bindgen(js_class = Lc3)]
+impl Session {
+    /// start a session, with a new machine that has the OS loaded into it
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Session {
+        let mut machine = Machine::new();
+        let os = assembler::assemble("os.asm", include_str!("os.asm")).expect("the OS assembles");
+        machine.load(&os);
+        Session {
+            machine,
+            program: None,
+        }
+    }
+
+    /// assemble a program, ready to be loaded. throws the errors if it doesn't
+    /// assemble
+    pub fn assemble(&mut self, source: &str) -> Result<(), String> {
+        self.program = Some(assembler::assemble("program.asm", source)?);
+        Ok(())
+    }
+
+    /// load the program that was last assembled, and point the PC at its start
+    pub fn load(&mut self) -> Result<(), String> {
+        let program = self
+            .program
+            .as_ref()
+            .ok_or_else(|| String::from("nothing has been assembled"))?;
+        self.machine.load(program);
+        Ok(())
+    }
+
+    /// execute up to `count` instructions, stopping early if the program
+    /// halts or is waiting for input. throws if an instruction fails
+    pub fn step(&mut self, count: u32) -> Result<Stop, String> {
+        for _ in 0..count {
+            if !self.machine.is_running() {
+                return Ok(Stop::Halted);
+            }
+            self.machine
+                .step()
+                .map_err(|e| format!("x{:04X}: {}", e.address, e.message))?;
+            if self.machine.is_waiting_for_input() {
+                return Ok(Stop::WaitingForInput);
+            }
+        }
+        Ok(if self.machine.is_running() {
+            Stop::Stepped
+        } else {
+            Stop::Halted
+        })
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.machine.pc()
+    }
+
+    /// R0 to R7
+    pub fn registers(&self) -> Vec<u16> {
+        self.machine.registers().to_vec()
+    }
+
+    /// `'N'`, `'Z'` or `'P'`
+    pub fn condition(&self) -> char {
+        self.machine.condition()
+    }
+
+    pub fn memory(&self, address: u16) -> u16 {
+        self.machine.memory(address)
+    }
+
+    /// queue up keys for the program to read
+    #[wasm_bindgen(js_name = pushInput)]
+    pub fn push_input(&mut self, keys: &str) {
+        self.machine.push_input(keys.as_bytes());
+    }
+
+    /// everything the program has displayed since this was last called
+    #[wasm_bindgen(js_name = takeOutput)]
+    pub fn take_output(&mut self) -> String {
+        String::from_utf8_lossy(&self.machine.take_output()).into_owned()
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_a_program() {
+        let mut session = Session::new();
+        session
+            .assemble(".ORIG x3000\nGETC\nOUT\nADD R3, R0, #-1\nHALT")
+            .unwrap();
+        session.load().unwrap();
+        assert_eq!(session.pc(), 0x3000);
+
+        assert_eq!(session.step(1000), Ok(Stop::WaitingForInput));
+        session.push_input("b");
+        assert_eq!(session.step(1000), Ok(Stop::Halted));
+        assert!(session.take_output().starts_with('b'));
+        assert_eq!(session.take_output(), "");
+        assert_eq!(session.registers()[3], u16::from(b'a'));
+        assert_eq!(session.condition(), 'Z');
+        assert_eq!(session.memory(0x3003), 0xF025);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.load(),
+            Err(String::from("nothing has been assembled"))
+        );
+        assert!(session
+            .assemble(".ORIG x3000\nADD R0")
+            .unwrap_err()
+            .starts_with("error: unexpected end of input"));
+
+        session.assemble(".ORIG x3000\n.FILL xD000").unwrap();
+        session.load().unwrap();
+        assert_eq!(session.step(1), Err(String::from("x3000: illegal opcode")));
+    }
+}
@@ -0,0 +1,116 @@
+//! runs the command line tool the way scripts do, checking what it prints and
+//! the status it exits with. the tool needs the `std` feature
+#![cfg(feature = "std")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// a directory of its own for each test, so they can run at the same time
+fn directory(test: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("lc3-emulator-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn run(directory: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3-emulator"))
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_errors_go_to_stderr_and_fail() {
+    let directory = directory("errors");
+    fs::write(directory.join("bad.asm"), ".ORIG x3000\nADD R0\n").unwrap();
+
+    let output = run(&directory, &["run", "bad.asm"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected a comma"));
+
+    let output = run(&directory, &["nonsense"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown command: nonsense"));
+
+    assert!(run(&directory, &["help"]).status.success());
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_asm_explains_why_several_origins_cant_be_written() {
+    let directory = directory("origins");
+    fs::write(
+        directory.join("two.asm"),
+        ".ORIG x3000\nHALT\n.ORIG x4000\n.FILL 1\n",
+    )
+    .unwrap();
+    for format in ["obj", "hex", "bin"] {
+        let output = run(&directory, &["asm", "--format", format, "two.asm"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+            "{} files hold a single .ORIG block, but the program has 2, at x3000, x4000",
+            format
+        )));
+    }
+    // it still runs
+    assert!(run(&directory, &["run", "two.asm"]).status.success());
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_trace_check_fails_when_the_traces_differ() {
+    let directory = directory("trace");
+    fs::write(
+        directory.join("program.asm"),
+        ".ORIG x3000\nADD R1, R1, #-2\nADD R2, R1, #3\nHALT\n",
+    )
+    .unwrap();
+    let output = run(&directory, &["trace", "--program-only", "program.asm"]);
+    assert!(output.status.success());
+    let trace = String::from_utf8(output.stdout).unwrap();
+    fs::write(directory.join("same.trace"), &trace).unwrap();
+    fs::write(
+        directory.join("different.trace"),
+        trace.replacen("R2=x0001", "R2=x0002", 1),
+    )
+    .unwrap();
+
+    let args = ["trace", "--program-only", "--check"];
+    let output = run(
+        &directory,
+        &[&args[..], &["same.trace", "program.asm"]].concat(),
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("the traces match"));
+
+    let output = run(
+        &directory,
+        &[&args[..], &["different.trace", "program.asm"]].concat(),
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("the traces differ at instruction 2, at x3001"));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_asm_fails_when_any_file_does() {
+    let directory = directory("asm");
+    fs::write(directory.join("good.asm"), ".ORIG x3000\nHALT\n").unwrap();
+    fs::write(directory.join("bad.asm"), ".ORIG x3000\nADD R0\n").unwrap();
+
+    let output = run(&directory, &["asm", "good.asm", "bad.asm"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 2 files failed to assemble"));
+    // the files that assemble are still written
+    assert!(directory.join("good.obj").exists());
+    assert!(!directory.join("bad.obj").exists());
+
+    assert!(run(&directory, &["asm", "good.asm"]).status.success());
+    fs::remove_dir_all(directory).unwrap();
+}